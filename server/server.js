@@ -18,6 +18,7 @@ io.on('connection', socket => {
                 sockets: new Map(),               
                 request_queue: new Array(),
                 game_cache: null,
+                player_cache: null,
                 access_requests: new Array()
             });
             socket.join(room_id);
@@ -56,7 +57,7 @@ io.on('connection', socket => {
                 game_res.host_socket = socket.id;
             }
             else 
-                { io.to(socket.id).emit("joinGame", data.roomid, data.setid, data.roles, data.game); }
+                { io.to(socket.id).emit("joinGame", data.roomid, data.setid, data.roles, game_res.player_cache); }
             game_keys.set(room_id, game_res);
         }
         else io.to(socket.id).emit("transactionFailed", (`Couldn't find game: '${room_id}'`));
@@ -76,11 +77,12 @@ io.on('connection', socket => {
         else io.to(socket.id).emit("transactionFailed", (`Couldn't find game: '${room_id}'`));
     })
 
-    socket.on("startTurn", (room_id, game_data) => {
+    socket.on("startTurn", (room_id, game_data, player_data) => {
         if (game_keys.has(room_id)) {
             let game_res = game_keys.get(room_id);
             game_res.request_queue = new Array();
             game_res.game_cache = game_data
+            game_res.player_cache = player_data
             console.log(game_res);
             io.to
             for (const [key, value] of game_res.role_assignments) {
@@ -146,12 +148,12 @@ io.on('connection', socket => {
         else io.to(socket.id).emit("transactionFailed", (`Couldn't find game: '${room_id}'`));
     })
 
-    socket.on("approveRequest", (room, request, msg) => {
+    socket.on("approveRequest", (room, request, user, msg) => {
         if (game_keys.has(room)) {
             io.to(room).emit("executeRequest", request);
             io.to(room).emit("socketLog", msg);
         }
-        else io.to(socket.id).emit("transactionFailed", (`Couldn't find game: '${room_id}'`));
+        else io.to(socket.id).emit("transactionFailed", (`Couldn't find game: '${room}'`));
     })
 
     socket.on("removeRollRequest", (room, request_data) => {
@@ -160,7 +162,7 @@ io.on('connection', socket => {
             io.to(game_res.sockets.get(request_data.user)).emit("removeRoll");
             io.to(room).emit("socketLog", ('Roll cancelled, skipping request'));
         }
-        else io.to(socket.id).emit("transactionFailed", (`Couldn't find game: '${room_id}'`));
+        else io.to(socket.id).emit("transactionFailed", (`Couldn't find game: '${room}'`));
     })
 
     socket.on("broadcastLog", (room, msg) => {
@@ -193,7 +195,7 @@ io.on('connection', socket => {
                 io.to(game_res.host_socket).emit("confirmFailure", (index));
             }
         }
-        else io.to(socket.id).emit("transactionFailed", (`Couldn't find game: '${room_id}'`));
+        else io.to(socket.id).emit("transactionFailed", (`Couldn't find game: '${room}'`));
     })
 
     socket.on("distributeRequest", req => {
@@ -0,0 +1,374 @@
+/*/////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+ *
+ *      Monster AI for ByteDungeon, requests proposed for the tokens the DM plays
+ *
+ *      Implementation and Assumptions
+ *          - The party is the tokens granted to a player (see permissions.rs), every other token with hitpoints left
+ *            is a monster played by the DM, monsters are on the same side and the party is their enemy
+ *          - The DM sets a behaviour per token (aggressive when none is set), behaviours are GM information and are
+ *            never sent to players
+ *              aggressive      walks to the nearest enemy and hits the weakest one in range
+ *              kiter           hits from as far as its longest attack reaches, then backs off (aggressive with melee only)
+ *              defender        holds its ground, only steps out to hit enemies it can reach this turn
+ *              coward          aggressive until under FLEE_PERCENT of its hitpoints, then runs from the enemies
+ *              support         heals any hurt ally it can reach, walks to the most hurt one, else acts like a kiter
+ *          - Every behaviour first uses an item that grants abilities the token lacks (or heals it while under half
+ *            its hitpoints), an item is the token's action but it may still move
+ *          - Abilities are told apart by what their target effects do to health: lowering it attacks, raising it
+ *            heals, abilities that do neither aren't used, ranges come from get_action_range like the options shown
+ *            to players, walking distances go around walls but not tokens since they move
+ *          - A plan is worked out on a copy of the session: moves and items are played on the copy so the next
+ *            request (and the next monster) starts from where they leave things, abilities aren't since they need
+ *            a roll, proposed requests are still checked when approved (see check_request)
+ *
+ */////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+use serde::{Serialize, Deserialize};
+
+use std::fmt;
+use std::cmp::Reverse;
+use std::str::FromStr;
+use std::collections::VecDeque;
+
+use crate::{GameSession, Request, Ability, copy_session, get_action_range};
+
+pub const FLEE_PERCENT: i32 = 25;               // A coward flees under this share of its hitpoints
+
+type CellKey = (usize, usize);
+
+/***********************************************
+ * Behaviour - How a DM-played token acts
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(ts_rs::TS))]
+#[serde(rename_all = "kebab-case")]
+pub enum Behaviour
+{
+    #[default]
+    Aggressive,
+    Kiter,
+    Defender,
+    Coward,
+    Support
+}
+
+impl fmt::Display for Behaviour
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Behaviour::Aggressive => write!(f, "aggressive"),
+            Behaviour::Kiter => write!(f, "kiter"),
+            Behaviour::Defender => write!(f, "defender"),
+            Behaviour::Coward => write!(f, "coward"),
+            Behaviour::Support => write!(f, "support")
+        }
+    }
+}
+
+impl FromStr for Behaviour
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Behaviour, String> {
+        match s {
+            "aggressive" => Ok(Behaviour::Aggressive),
+            "kiter" => Ok(Behaviour::Kiter),
+            "defender" => Ok(Behaviour::Defender),
+            "coward" => Ok(Behaviour::Coward),
+            "support" => Ok(Behaviour::Support),
+            _ => Err(format!("no behaviour named '{}'", s))
+        }
+    }
+}
+
+impl GameSession
+{
+    /******************************************************************************
+     *  party - Tokens granted to a player, sorted
+     *---------------------------------------------------------------------------*/
+    pub fn party(&self) -> Vec<char>
+    {
+        let mut party: Vec<char> = self.characters.keys().filter(|token| !self.permissions.controllers_of(**token).is_empty()).cloned().collect();
+        party.sort();
+        return party;
+    }
+
+    /******************************************************************************
+     *  auto_play - Plans the turn of every monster, in initiative order (ties by
+     *              token), see header
+     *
+     *  PARAMS: PARTY are the tokens the monsters fight, see party
+     *  RETURN: Requests to hand the DM for approval, in the order to execute them
+     *---------------------------------------------------------------------------*/
+    pub fn auto_play(&self, party: &[char]) -> Vec<Request>
+    {
+        let mut monsters: Vec<char> = self.characters.keys().filter(|token| !party.contains(token) && self.is_up(**token)).cloned().collect();
+        monsters.sort_by_key(|token| (Reverse(self.characters[token].initiative.unwrap_or(i8::MIN)), *token));
+        let mut scratch = copy_session(self);
+        let mut requests = Vec::new();
+        for token in monsters {
+            for req in scratch.propose_turn(token, party) {
+                if req.action_type != 2 { let _ = scratch.execute_request(token, &req); }
+                requests.push(req);
+            }
+        }
+        return requests;
+    }
+
+    /******************************************************************************
+     *  propose_turn - Plans the turn of TOKEN with its behaviour, the other side
+     *                 of PARTY being its enemies
+     *
+     *  RETURN: Requests in the order to execute them, none if it has no enemy left
+     *---------------------------------------------------------------------------*/
+    pub fn propose_turn(&self, token: char, party: &[char]) -> Vec<Request>
+    {
+        let mut plan = Vec::new();
+        if !self.is_up(token) { return plan; }
+        let side = party.contains(&token);
+        let mut up: Vec<char> = self.characters.keys().filter(|other| self.is_up(**other)).cloned().collect();
+        up.sort();
+        let (allies, enemies): (Vec<char>, Vec<char>) = up.into_iter().partition(|other| party.contains(other) == side);
+        if enemies.is_empty() { return plan; }
+
+        let behaviour = self.behaviours.get(&token).cloned().unwrap_or_default();
+        let mut scratch = copy_session(self);
+        let used_item = match scratch.helpful_item(token) {
+            Some(req) => {
+                let _ = scratch.execute_request(token, &req);
+                plan.push(req);
+                true
+            },
+            None => false
+        };
+        let sheet = &scratch.characters[&token].sheet;
+        let fleeing = behaviour == Behaviour::Coward && sheet.hitpoints * 100 < sheet.max_hp * FLEE_PERCENT;
+        let range = scratch.attack_range(token);
+        let heal_any = behaviour == Behaviour::Support;
+        let act = |scratch: &GameSession, plan: &mut Vec<Request>| {
+            match scratch.best_action(token, &allies, &enemies, heal_any) {
+                Some(req) if !used_item => { plan.push(req); true },
+                _ => false
+            }
+        };
+
+        match behaviour {
+            _ if fleeing => { scratch.go(&mut plan, token, scratch.flee(token, &enemies)); },
+            Behaviour::Kiter if range > 1 => {
+                let acted = act(&scratch, &mut plan);
+                scratch.go(&mut plan, token, scratch.kite(token, &enemies, range));
+                if !acted { act(&scratch, &mut plan); }
+            },
+            Behaviour::Defender => {
+                if act(&scratch, &mut plan) { return plan; }
+                let cell = scratch.approach(token, &enemies);
+                let mut ahead = copy_session(&scratch);
+                ahead.go(&mut Vec::new(), token, cell);
+                if ahead.best_action(token, &[], &enemies, false).is_none() { return plan; }
+                scratch.go(&mut plan, token, cell);
+                act(&scratch, &mut plan);
+            },
+            Behaviour::Support => {
+                if act(&scratch, &mut plan) { return plan; }
+                let hurt = allies.iter().filter(|ally| **ally != token)
+                    .filter(|ally| scratch.characters[ally].sheet.hitpoints < scratch.characters[ally].sheet.max_hp)
+                    .min_by_key(|ally| (scratch.characters[ally].sheet.hitpoints, **ally));
+                let cell = match hurt {
+                    Some(ally) => scratch.approach(token, &[*ally]),
+                    None if range > 1 => scratch.kite(token, &enemies, range),
+                    None => scratch.approach(token, &enemies)
+                };
+                scratch.go(&mut plan, token, cell);
+                act(&scratch, &mut plan);
+            },
+            _ => {
+                if act(&scratch, &mut plan) { return plan; }
+                scratch.go(&mut plan, token, scratch.approach(token, &enemies));
+                act(&scratch, &mut plan);
+            }
+        }
+        return plan;
+    }
+
+    pub(crate) fn is_up(&self, token: char) -> bool
+    {
+        self.characters.get(&token).is_some_and(|tok| tok.sheet.hitpoints > 0)
+    }
+
+    /******************************************************************************
+     *  go - Moves TOKEN to CELL on this copy and adds the move to PLAN
+     *---------------------------------------------------------------------------*/
+    fn go(&mut self, plan: &mut Vec<Request>, token: char, cell: Option<CellKey>)
+    {
+        let req = match cell {
+            Some(cell) => Request { caster: token, action_type: 0, target_cell: Some(cell), ..Default::default() },
+            None => return
+        };
+        if self.check_request(&req).is_err() { return; }
+        let _ = self.execute_request(token, &req);
+        plan.push(req);
+    }
+
+    /******************************************************************************
+     *  helpful_item - Request using the first item granting abilities TOKEN lacks,
+     *                 or healing it while under half its hitpoints
+     *---------------------------------------------------------------------------*/
+    fn helpful_item(&self, token: char) -> Option<Request>
+    {
+        let sheet = &self.characters[&token].sheet;
+        let hurt = sheet.hitpoints * 2 < sheet.max_hp;
+        let index = sheet.items.iter().position(|item| {
+            item.abilities.iter().any(|ability| !sheet.abilities.contains(ability))
+                || (hurt && item.effects.iter().map(|effect| self.health_of(effect)).sum::<i32>() > 0)
+        })?;
+        let req = Request { caster: token, action_type: 1, subtype_key: Some(index.to_string()), ..Default::default() };
+        return self.check_request(&req).ok().map(|_| req);
+    }
+
+    /******************************************************************************
+     *  best_action - Heals the most hurt ally under half its hitpoints (any hurt
+     *                ally with HEAL_ANY), or else hits the weakest enemy, with
+     *                the strongest ability TOKEN has in range
+     *---------------------------------------------------------------------------*/
+    fn best_action(&self, token: char, allies: &[char], enemies: &[char], heal_any: bool) -> Option<Request>
+    {
+        let tok = &self.characters[&token];
+        let grid = self.board(&tok.map)?;
+        let mut keys: Vec<&String> = tok.sheet.abilities.iter().collect();
+        keys.sort();
+        let mut best: Option<(bool, i32, String, char)> = None;     // Heals, power, ability and target, best is greatest
+        for key in keys {
+            let ability = match self.abilities.get(key) { Some(ability) => ability, None => continue };
+            let power = self.power_of(ability);
+            let options = get_action_range(grid, tok.row, tok.column, ability.range as i32, true);
+            let in_range = |target: &&char| {
+                let other = &self.characters[*target];
+                **target == token || (other.map == tok.map && options.contains(&(other.row as i32, other.column as i32)))
+            };
+            let hitpoints = |target: &&char| self.characters[*target].sheet.hitpoints;
+            let hurt = |target: &&char| {
+                let sheet = &self.characters[*target].sheet;
+                if heal_any { sheet.hitpoints < sheet.max_hp } else { sheet.hitpoints * 2 < sheet.max_hp }
+            };
+            let target = match power {
+                p if p < 0 => enemies.iter().filter(in_range).min_by_key(|target| (hitpoints(target), **target)),
+                p if p > 0 => allies.iter().filter(in_range).filter(hurt).min_by_key(|target| (hitpoints(target), **target)),
+                _ => None
+            };
+            let candidate = match target { Some(target) => (power > 0, power.abs(), key.clone(), *target), None => continue };
+            if best.as_ref().is_none_or(|best| (candidate.0, candidate.1) > (best.0, best.1)) { best = Some(candidate); }
+        }
+        let (_, _, key, target) = best?;
+        let req = Request { caster: token, action_type: 2, subtype_key: Some(key), target_tokens: Some(vec![target]), ..Default::default() };
+        return self.check_request(&req).ok().map(|_| req);
+    }
+
+    /******************************************************************************
+     *  attack_range - Longest range of the abilities TOKEN can hit enemies with
+     *---------------------------------------------------------------------------*/
+    fn attack_range(&self, token: char) -> i32
+    {
+        self.characters[&token].sheet.abilities.iter().filter_map(|key| self.abilities.get(key))
+            .filter(|ability| self.power_of(ability) < 0).map(|ability| ability.range as i32).max().unwrap_or(0)
+    }
+
+    /******************************************************************************
+     *  approach - Cell TOKEN can move to that is the closest walk to one of
+     *             TARGETS, None if none is closer than where it stands
+     *---------------------------------------------------------------------------*/
+    fn approach(&self, token: char, targets: &[char]) -> Option<CellKey>
+    {
+        let (here, cells, distance) = self.walks(token, targets)?;
+        cells.into_iter().filter(|(r, c)| distance[*r][*c] < distance[here.0][here.1]).min_by_key(|(r, c)| (distance[*r][*c], *r, *c))
+    }
+
+    /******************************************************************************
+     *  flee - Cell TOKEN can move to that is the farthest walk from ENEMIES,
+     *         None if none is farther than where it stands
+     *---------------------------------------------------------------------------*/
+    fn flee(&self, token: char, enemies: &[char]) -> Option<CellKey>
+    {
+        let (here, cells, distance) = self.walks(token, enemies)?;
+        cells.into_iter().filter(|(r, c)| distance[*r][*c] > distance[here.0][here.1]).max_by_key(|(r, c)| (distance[*r][*c], Reverse((*r, *c))))
+    }
+
+    /******************************************************************************
+     *  kite - Cell TOKEN can move to (or stay on) from which an enemy is within
+     *         RANGE that is the farthest walk from ENEMIES, or else approach
+     *---------------------------------------------------------------------------*/
+    fn kite(&self, token: char, enemies: &[char], range: i32) -> Option<CellKey>
+    {
+        let (here, mut cells, distance) = self.walks(token, enemies)?;
+        let tok = &self.characters[&token];
+        let grid = self.board(&tok.map)?;
+        let targets: Vec<(i32, i32)> = enemies.iter().map(|enemy| &self.characters[enemy]).filter(|enemy| enemy.map == tok.map)
+            .map(|enemy| (enemy.row as i32, enemy.column as i32)).collect();
+        cells.push(here);
+        let best = cells.into_iter().filter(|(r, c)| {
+            let options = get_action_range(grid, *r, *c, range, true);
+            targets.iter().any(|target| options.contains(target))
+        }).max_by_key(|cell| (distance[cell.0][cell.1], *cell == here, Reverse(*cell)));
+        match best {
+            Some(cell) if cell == here => None,
+            Some(cell) => Some(cell),
+            None => self.approach(token, enemies)
+        }
+    }
+
+    /******************************************************************************
+     *  walks - Where TOKEN stands, the cells it can move to and the walking
+     *          distances to the nearest of TARGETS on its map
+     *---------------------------------------------------------------------------*/
+    fn walks(&self, token: char, targets: &[char]) -> Option<(CellKey, Vec<CellKey>, Vec<Vec<u32>>)>
+    {
+        let tok = &self.characters[&token];
+        let grid = self.board(&tok.map)?;
+        let sources: Vec<CellKey> = targets.iter().map(|target| &self.characters[target])
+            .filter(|target| target.map == tok.map).map(|target| (target.row, target.column)).collect();
+        let mut cells: Vec<CellKey> = get_action_range(grid, tok.row, tok.column, tok.sheet.speed, false).into_iter()
+            .map(|(r, c)| (r as usize, c as usize)).collect();
+        cells.sort();
+        return Some(((tok.row, tok.column), cells, walking_distances(grid, &sources)));
+    }
+
+    /******************************************************************************
+     *  power_of - Hitpoints an ability gives its targets, negative for damage
+     *---------------------------------------------------------------------------*/
+    fn power_of(&self, ability: &Ability) -> i32
+    {
+        ability.target_effects.iter().map(|effect| self.health_of(effect)).sum()
+    }
+
+    fn health_of(&self, effect: &str) -> i32
+    {
+        match self.effects.get(effect) {
+            Some(effect) if effect.target_stat.eq_ignore_ascii_case("health") => effect.modifier[0],
+            _ => 0
+        }
+    }
+}
+
+/******************************************************************************
+ *  walking_distances - Steps from the nearest of SOURCES to every cell, walls
+ *                      block the way, tokens don't since they move
+ *
+ *  RETURN: Distance by row and column, u32::MAX where no source can be reached
+ *---------------------------------------------------------------------------*/
+fn walking_distances(grid: &[Vec<char>], sources: &[CellKey]) -> Vec<Vec<u32>>
+{
+    let mut distance = vec![vec![u32::MAX; grid[0].len()]; grid.len()];
+    let mut queue = VecDeque::new();
+    for (row, col) in sources {
+        distance[*row][*col] = 0;
+        queue.push_back((*row, *col));
+    }
+    while let Some((row, col)) = queue.pop_front() {
+        let next = [(row.wrapping_sub(1), col), (row + 1, col), (row, col.wrapping_sub(1)), (row, col + 1)];
+        for (r, c) in next {
+            if r >= grid.len() || c >= grid[0].len() || grid[r][c] == '1' || distance[r][c] != u32::MAX { continue; }
+            distance[r][c] = distance[row][col] + 1;
+            queue.push_back((r, c));
+        }
+    }
+    return distance;
+}
@@ -0,0 +1,194 @@
+/*/////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+ *
+ *      Plain text maps for ByteDungeon, import and export of human-editable multi-line ASCII layouts
+ *
+ *      Implementation and Assumptions
+ *          - Each line of the text is a row of the map, every row must have the same number of glyphs
+ *          - A legend maps glyphs to their meaning, by default '#' wall, '.' floor, '+' door, '^' trap, '@' spawn
+ *          - Any letter that isn't in the legend is a token spawn, so are the chars of tokens already in the session
+ *          - Token spawns place the token if its sheet exists, otherwise a spawn point reserved for it is left on the map
+ *          - Validation reports every ragged row and unknown glyph at once, the session is untouched on error
+ *
+ */////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+use serde::{Serialize, Deserialize};
+
+use std::fmt;
+use std::collections::BTreeMap;
+
+use crate::GameSession;
+use crate::maps::{Cell, ObjectKind};
+
+/***********************************************
+ * Glyph - Meaning of a char in a text map
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Glyph
+{
+    Wall,
+    Floor,
+    Door,
+    Trap,
+    Spawn                                       // Spawn point not reserved for any token
+}
+
+/***********************************************
+ * Legend - Glyph to meaning lookup table
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(transparent)]
+pub struct Legend
+{
+    pub glyphs: BTreeMap<char, Glyph>
+}
+
+/***********************************************
+ * AsciiError - Problems found in a text map
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum AsciiError
+{
+    Empty,
+    RaggedRow { line: usize, expected: usize, found: usize },
+    UnknownGlyph { line: usize, column: usize, glyph: char },
+    DuplicateToken { line: usize, column: usize, token: char },
+    GlyphClash(char)                            // Export only, a token uses a char reserved by the legend
+}
+
+impl Default for Legend
+{
+    fn default() -> Legend {
+        let glyphs = [('#', Glyph::Wall), ('.', Glyph::Floor), ('+', Glyph::Door), ('^', Glyph::Trap), ('@', Glyph::Spawn)];
+        Legend { glyphs: glyphs.iter().cloned().collect() }
+    }
+}
+
+impl Legend
+{
+    /******************************************************************************
+     *  glyph_for - Returns the first glyph of the legend with the input meaning
+     *---------------------------------------------------------------------------*/
+    pub fn glyph_for(&self, meaning: Glyph) -> Option<char>
+    {
+        self.glyphs.iter().find(|(_, value)| **value == meaning).map(|(key, _)| *key)
+    }
+}
+
+impl fmt::Display for AsciiError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AsciiError::Empty => write!(f, "map has no rows"),
+            AsciiError::RaggedRow { line, expected, found } =>
+                write!(f, "line {}: row has {} cells, expected {}", line, found, expected),
+            AsciiError::UnknownGlyph { line, column, glyph } =>
+                write!(f, "line {}, column {}: unknown glyph '{}'", line, column, glyph),
+            AsciiError::DuplicateToken { line, column, token } =>
+                write!(f, "line {}, column {}: token '{}' appears more than once", line, column, token),
+            AsciiError::GlyphClash(token) => write!(f, "token '{}' uses a glyph reserved by the legend", token)
+        }
+    }
+}
+
+impl GameSession
+{
+    /******************************************************************************
+     *  import_ascii - Replaces the layout of a map by the input text map
+     *
+     *  RETURNS: Every problem found in the text, the session is only changed
+     *           when the text is valid, see GameSession::replace_layout
+     *---------------------------------------------------------------------------*/
+    pub fn import_ascii(&mut self, map: &str, text: &str, legend: &Legend) -> Result<(), Vec<AsciiError>>
+    {
+        let mut errors = Vec::new();
+        let mut grid: Vec<Vec<char>> = Vec::new();
+        let mut objects = Vec::new();
+        let mut tokens: Vec<(usize, usize, char)> = Vec::new();
+
+        let lines: Vec<&str> = text.lines().map(|line| line.trim_end_matches('\r')).collect();
+        let used = lines.iter().rposition(|line| !line.trim().is_empty()).map_or(0, |n| n + 1);
+        for (row, line) in lines[..used].iter().enumerate() {
+            let mut cells = Vec::new();
+            for (col, glyph) in line.chars().enumerate() {
+                let (line, column) = (row + 1, col + 1);
+                match legend.glyphs.get(&glyph) {
+                    Some(Glyph::Wall) => { cells.push('1'); continue; },
+                    Some(Glyph::Floor) => {},
+                    Some(Glyph::Door) => objects.push((row, col, ObjectKind::Door)),
+                    Some(Glyph::Trap) => objects.push((row, col, ObjectKind::Trap)),
+                    Some(Glyph::Spawn) => objects.push((row, col, ObjectKind::Spawn(None))),
+                    None if self.is_token_glyph(glyph) => {
+                        if tokens.iter().any(|tok| tok.2 == glyph) {
+                            errors.push(AsciiError::DuplicateToken { line, column, token: glyph });
+                        }
+                        tokens.push((row, col, glyph));
+                    },
+                    None => errors.push(AsciiError::UnknownGlyph { line, column, glyph })
+                }
+                cells.push('0');
+            }
+            if !grid.is_empty() && cells.len() != grid[0].len() {
+                errors.push(AsciiError::RaggedRow { line: row + 1, expected: grid[0].len(), found: cells.len() });
+            }
+            grid.push(cells);
+        }
+        if grid.is_empty() || grid[0].is_empty() { errors.push(AsciiError::Empty); }
+        if !errors.is_empty() { return Err(errors); }
+
+        self.replace_layout(map, grid, objects, tokens);
+        return Ok(());
+    }
+
+    /******************************************************************************
+     *  export_ascii - Writes a map as multi-line text using the input legend
+     *---------------------------------------------------------------------------*/
+    pub fn export_ascii(&self, map: &str, legend: &Legend) -> Result<String, AsciiError>
+    {
+        let grid = match self.board(map) {
+            Some(grid) => grid,
+            None => return Err(AsciiError::Empty)
+        };
+        let wall = legend.glyph_for(Glyph::Wall).unwrap_or('#');
+        let floor = legend.glyph_for(Glyph::Floor).unwrap_or('.');
+
+        let mut result = String::new();
+        for (row, cells) in grid.iter().enumerate() {
+            for (column, c) in cells.iter().enumerate() {
+                let glyph = match *c {
+                    '1' => wall,
+                    '0' => {
+                        let here = self.objects_at(&Cell::new(map, row, column));
+                        let meaning = |kind: ObjectKind| here.iter().any(|obj| obj.kind == kind);
+                        let reserved = here.iter().find_map(|obj| match obj.kind { ObjectKind::Spawn(tok) => tok, _ => None });
+                        if meaning(ObjectKind::Door) { legend.glyph_for(Glyph::Door).unwrap_or(floor) }
+                        else if meaning(ObjectKind::Trap) { legend.glyph_for(Glyph::Trap).unwrap_or(floor) }
+                        else if let Some(token) = reserved { token_glyph(token, legend)? }
+                        else if meaning(ObjectKind::Spawn(None)) { legend.glyph_for(Glyph::Spawn).unwrap_or(floor) }
+                        else { floor }
+                    },
+                    token => token_glyph(token, legend)?
+                };
+                result.push(glyph);
+            }
+            result.push('\n');
+        }
+        return Ok(result);
+    }
+
+    /******************************************************************************
+     *  is_token_glyph - Letters and the chars of known tokens/sheets are tokens
+     *---------------------------------------------------------------------------*/
+    fn is_token_glyph(&self, glyph: char) -> bool
+    {
+        glyph.is_alphabetic() || self.characters.contains_key(&glyph) || self.sheets.contains_key(&glyph)
+    }
+}
+
+/******************************************************************************
+ *  token_glyph - Tokens are written as their own char if the legend allows it
+ *---------------------------------------------------------------------------*/
+fn token_glyph(token: char, legend: &Legend) -> Result<char, AsciiError>
+{
+    if legend.glyphs.contains_key(&token) { return Err(AsciiError::GlyphClash(token)); }
+    return Ok(token);
+}
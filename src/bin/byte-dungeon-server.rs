@@ -0,0 +1,55 @@
+/*/////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+ *
+ *      Game server for ByteDungeon, replaces the Node relay in server/ (see server.rs for the protocol)
+ *
+ *      Implementation and Assumptions
+ *          - Listens on 127.0.0.1 by default so a local game needs nothing else, --host 0.0.0.0 to open it up
+ *          - Rooms are stored in --data (./rooms by default) and restored when the server starts again, --memory
+ *            keeps them in memory only (see storage.rs)
+ *
+ */////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+use byte_dungeon::server::{serve, Server, DEFAULT_PORT};
+use byte_dungeon::storage::Store;
+
+use std::env;
+use std::process;
+use std::net::TcpListener;
+
+const USAGE: &str = "usage: byte-dungeon-server [--host ADDRESS] [--port PORT] [--data DIR | --memory]";
+
+fn main()
+{
+    let (mut host, mut port, mut data) = ("127.0.0.1".to_string(), DEFAULT_PORT, Some("rooms".to_string()));
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--memory" { data = None; continue; }
+        match (arg.as_str(), args.next()) {
+            ("--host", Some(value)) => host = value,
+            ("--port", Some(value)) => port = value.parse().unwrap_or_else(|_| fail(&format!("invalid port '{}'", value))),
+            ("--data", Some(value)) => data = Some(value),
+            _ => fail(USAGE)
+        }
+    }
+
+    let server = match data {
+        Some(dir) => {
+            let store = Store::open(&dir).unwrap_or_else(|err| fail(&format!("{}: {}", dir, err)));
+            let (server, warnings) = Server::with_store(store).unwrap_or_else(|err| fail(&format!("{}: {}", dir, err)));
+            for warning in warnings { eprintln!("byte-dungeon-server: {}", warning); }
+            println!("byte-dungeon-server restored {} room(s) from {}", server.rooms.len(), dir);
+            server
+        },
+        None => Server::default()
+    };
+
+    let listener = TcpListener::bind((host.as_str(), port)).unwrap_or_else(|err| fail(&format!("{}:{}: {}", host, port, err)));
+    println!("byte-dungeon-server listening on ws://{}", listener.local_addr().unwrap());
+    if let Err(err) = serve(listener, server) { fail(&err.to_string()); }
+}
+
+fn fail(message: &str) -> !
+{
+    eprintln!("byte-dungeon-server: {}", message);
+    process::exit(2);
+}
@@ -0,0 +1,347 @@
+/*/////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+ *
+ *      Command line tool for ByteDungeon game set files, works on saves without opening the web app
+ *
+ *      Implementation and Assumptions
+ *          - Every command reads saves in any format: JSON (upgraded with the migrations) or binary (see binary.rs)
+ *          - The output format follows the file extension: .json, .bdng (binary) or .txt (text map of one map)
+ *          - Results are printed on stdout, problems on stderr
+ *          - Exit codes: 0 success, 1 the command found problems (lint errors, differences), 2 bad usage or IO error
+ *          - Arguments are parsed by hand, the tool is small and the library has no CLI dependencies
+ *
+ */////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#![allow(clippy::needless_return)]
+
+use byte_dungeon::GameSession;
+use byte_dungeon::Request;
+use byte_dungeon::ascii::Legend;
+use byte_dungeon::binary::{self, MAGIC};
+use byte_dungeon::checksum::compare_states;
+use byte_dungeon::history::Command;
+use byte_dungeon::maps::MAIN_MAP;
+use byte_dungeon::migrate::load_save;
+use byte_dungeon::protocol;
+use byte_dungeon::sim::SimOptions;
+use byte_dungeon::tui;
+use byte_dungeon::validate::{validate_game, Severity};
+
+use std::fs;
+use std::env;
+use std::process;
+use std::path::Path;
+
+const USAGE: &str = "\
+usage: byte-dungeon <command> [options]
+
+commands:
+    validate <save>                         Lint a game set, exits with 1 if it has errors
+    show <save> [--map ID]                  Print a map of the game
+    convert <in> <out> [--map ID] [--base SAVE] [--no-compress]
+                                            Convert between .json, .bdng (binary) and .txt (text map)
+    run <save> <requests.json> [-o OUT]     Execute a JSON list of requests and print (or save) the result
+    diff <save> <save> [--limit N]          List the fields that differ, exits with 1 if any do
+    log <save> [--json]                     Print the combat log, as readable lines or as JSON records
+    simulate <save> [--runs N] [--seed N] [--rounds N] [--party TOKENS] [--json]
+                                            Play the encounter N times (100) and report how it went
+    play <save>                             Play the game in the terminal, w saves it back to <save>
+    typescript                              Print the TypeScript typings of the network protocol
+    help                                    Show this message";
+
+/***********************************************
+ * Args - Positional arguments and --options
+ **********************************************/
+struct Args
+{
+    positional: Vec<String>,
+    options: Vec<(String, Option<String>)>
+}
+
+/***********************************************
+ * Format - File formats told by extension
+ **********************************************/
+#[derive(Clone, Copy, PartialEq)]
+enum Format
+{
+    Json,
+    Binary,
+    Text
+}
+
+fn main()
+{
+    let args: Vec<String> = env::args().skip(1).collect();
+    let code = match run(&args) {
+        Ok(code) => code,
+        Err(message) => {
+            eprintln!("byte-dungeon: {}", message);
+            2
+        }
+    };
+    process::exit(code);
+}
+
+/******************************************************************************
+ *  run - Dispatches a command, returns the exit code
+ *---------------------------------------------------------------------------*/
+fn run(args: &[String]) -> Result<i32, String>
+{
+    let command = match args.first() {
+        Some(command) => command.as_str(),
+        None => return Err(USAGE.to_string())
+    };
+    let args = parse_args(&args[1..], &["--no-compress", "--json"])?;
+    match command {
+        "validate" => validate(&args),
+        "show" => show(&args),
+        "convert" => convert(&args),
+        "run" => run_requests(&args),
+        "diff" => diff(&args),
+        "log" => combat_log(&args),
+        "simulate" => simulate(&args),
+        "play" => play(&args),
+        "typescript" => {
+            print!("{}", protocol::typescript());
+            Ok(0)
+        },
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(0)
+        },
+        _ => Err(format!("unknown command '{}'\n\n{}", command, USAGE))
+    }
+}
+
+/******************************************************************************
+ *  validate - Prints every issue of a save, errors make the exit code 1
+ *---------------------------------------------------------------------------*/
+fn validate(args: &Args) -> Result<i32, String>
+{
+    let path = args.positional(0, "save")?;
+    let issues = validate_game(&read_game(path)?);
+    for issue in &issues { println!("{}", issue); }
+    let errors = issues.iter().filter(|issue| issue.severity == Severity::Error).count();
+    println!("{}: {} error(s), {} warning(s)", path, errors, issues.len() - errors);
+    return Ok(if errors > 0 { 1 } else { 0 });
+}
+
+/******************************************************************************
+ *  show - Prints a map, the main map by default
+ *---------------------------------------------------------------------------*/
+fn show(args: &Args) -> Result<i32, String>
+{
+    let game = read_game(args.positional(0, "save")?)?;
+    let map = args.option("--map").unwrap_or(MAIN_MAP);
+    if map == MAIN_MAP {
+        print!("{}", game);
+        return Ok(0);
+    }
+    let grid = game.board(map).ok_or_else(|| format!("no map named '{}'", map))?;
+    for row in grid { println!("{}", row.iter().collect::<String>()); }
+    return Ok(0);
+}
+
+/******************************************************************************
+ *  convert - Converts a save (or a text map) to the format of the output file
+ *---------------------------------------------------------------------------*/
+fn convert(args: &Args) -> Result<i32, String>
+{
+    let (input, output) = (args.positional(0, "input")?, args.positional(1, "output")?);
+    let map = args.option("--map").unwrap_or(MAIN_MAP);
+    let legend = Legend::default();
+
+    let game = if format_of(input)? == Format::Text {
+        let mut game = match args.option("--base") {
+            Some(base) => read_game(base)?,
+            None => GameSession::default()
+        };
+        let text = fs::read_to_string(input).map_err(|err| format!("{}: {}", input, err))?;
+        if let Err(errors) = game.import_ascii(map, &text, &legend) {
+            let lines: Vec<String> = errors.iter().map(|err| format!("{}: {}", input, err)).collect();
+            return Err(lines.join("\n"));
+        }
+        game
+    }
+    else { read_game(input)? };
+
+    match format_of(output)? {
+        Format::Text => {
+            let text = game.export_ascii(map, &legend).map_err(|err| err.to_string())?;
+            write_file(output, text.as_bytes())?;
+        },
+        format => write_game(output, format, &game, !args.flag("--no-compress"))?
+    }
+    return Ok(0);
+}
+
+/******************************************************************************
+ *  run_requests - Executes requests in order, refused requests are reported
+ *                 and skipped
+ *---------------------------------------------------------------------------*/
+fn run_requests(args: &Args) -> Result<i32, String>
+{
+    let mut game = read_game(args.positional(0, "save")?)?;
+    let path = args.positional(1, "requests")?;
+    let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+    let requests: Vec<Request> = serde_json::from_str(&text).map_err(|err| format!("{}: {}", path, err))?;
+
+    let mut refused = 0;
+    for (n, request) in requests.into_iter().enumerate() {
+        let result = game.check_request(&request)
+            .and_then(|_| game.apply(Command::Execute(request)).map_err(|err| err.to_string()));
+        if let Err(err) = result {
+            eprintln!("request {}: {}", n, err);
+            refused += 1;
+        }
+    }
+    match args.option("-o") {
+        Some(out) => write_game(out, format_of(out)?, &game, !args.flag("--no-compress"))?,
+        None => println!("{}", serde_json::to_string_pretty(&game.to_save()).unwrap())
+    }
+    return Ok(if refused > 0 { 1 } else { 0 });
+}
+
+/******************************************************************************
+ *  diff - Prints the fields that differ between two saves
+ *---------------------------------------------------------------------------*/
+fn diff(args: &Args) -> Result<i32, String>
+{
+    let left = read_game(args.positional(0, "save")?)?;
+    let right = read_game(args.positional(1, "save")?)?;
+    let limit = match args.option("--limit") {
+        Some(limit) => limit.parse().map_err(|_| format!("invalid limit '{}'", limit))?,
+        None => 50
+    };
+    let diffs = compare_states(&left, &right, limit);
+    let show = |value: &Option<serde_json::Value>| value.as_ref().map_or("(missing)".to_string(), |v| v.to_string());
+    for diff in &diffs { println!("{}: {} -> {}", diff.path, show(&diff.left), show(&diff.right)); }
+    return Ok(if diffs.is_empty() { 0 } else { 1 });
+}
+
+/******************************************************************************
+ *  combat_log - Prints what every executed request did, see combat.rs
+ *---------------------------------------------------------------------------*/
+fn combat_log(args: &Args) -> Result<i32, String>
+{
+    let game = read_game(args.positional(0, "save")?)?;
+    if args.flag("--json") { println!("{}", serde_json::to_string_pretty(&game.combat_log).unwrap()); }
+    else { for line in game.combat_lines() { println!("{}", line); } }
+    return Ok(0);
+}
+
+/******************************************************************************
+ *  simulate - Plays the encounter of a save many times, see sim.rs
+ *---------------------------------------------------------------------------*/
+fn simulate(args: &Args) -> Result<i32, String>
+{
+    let game = read_game(args.positional(0, "save")?)?;
+    let number = |name: &str, default: u64| match args.option(name) {
+        Some(value) => value.parse::<u64>().map_err(|_| format!("invalid {} '{}'", &name[2..], value)),
+        None => Ok(default)
+    };
+    let defaults = SimOptions::default();
+    let options = SimOptions {
+        runs: number("--runs", defaults.runs as u64)? as u32,
+        seed: number("--seed", defaults.seed)?,
+        max_rounds: number("--rounds", defaults.max_rounds as u64)? as u32,
+        party: args.option("--party").map_or(Vec::new(), |party| party.chars().collect())
+    };
+    let report = game.simulate(&options).map_err(|err| err.to_string())?;
+    if args.flag("--json") { println!("{}", serde_json::to_string_pretty(&report).unwrap()); }
+    else { print!("{}", report); }
+    return Ok(0);
+}
+
+/******************************************************************************
+ *  play - Opens a save in the terminal client, see tui.rs
+ *---------------------------------------------------------------------------*/
+fn play(args: &Args) -> Result<i32, String>
+{
+    let path = args.positional(0, "save")?;
+    let game = read_game(path)?;
+    tui::run(game, Some(path.to_string())).map_err(|err| format!("terminal: {}", err))?;
+    return Ok(0);
+}
+
+/******************************************************************************
+ *  read_game - Loads a save in any format, applying the migrations
+ *---------------------------------------------------------------------------*/
+fn read_game(path: &str) -> Result<GameSession, String>
+{
+    let bytes = fs::read(path).map_err(|err| format!("{}: {}", path, err))?;
+    let loaded = if bytes.starts_with(&MAGIC) { binary::decode_game(&bytes).map_err(|err| err.to_string()) }
+    else {
+        serde_json::from_slice(&bytes).map_err(|err| err.to_string())
+            .and_then(|value| load_save(value).map_err(|err| err.to_string()))
+    };
+    let (game, applied) = loaded.map_err(|err| format!("{}: {}", path, err))?;
+    for migration in applied { eprintln!("{}: upgraded ({})", path, migration); }
+    return Ok(game);
+}
+
+/******************************************************************************
+ *  write_game - Saves a session as JSON or binary
+ *---------------------------------------------------------------------------*/
+fn write_game(path: &str, format: Format, game: &GameSession, compress: bool) -> Result<(), String>
+{
+    match format {
+        Format::Json => write_file(path, serde_json::to_string_pretty(&game.to_save()).unwrap().as_bytes()),
+        Format::Binary => write_file(path, &binary::encode_game(game, compress)),
+        Format::Text => Err(format!("{}: a text map can't hold a whole game", path))
+    }
+}
+
+fn write_file(path: &str, bytes: &[u8]) -> Result<(), String>
+{
+    fs::write(path, bytes).map_err(|err| format!("{}: {}", path, err))
+}
+
+/******************************************************************************
+ *  format_of - File format told by the extension of PATH
+ *---------------------------------------------------------------------------*/
+fn format_of(path: &str) -> Result<Format, String>
+{
+    match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+        Some("json") => Ok(Format::Json),
+        Some("bdng") | Some("bin") => Ok(Format::Binary),
+        Some("txt") => Ok(Format::Text),
+        _ => Err(format!("{}: unknown format, use .json, .bdng or .txt", path))
+    }
+}
+
+/******************************************************************************
+ *  parse_args - Splits arguments into positionals and options, FLAGS are the
+ *               options that take no value
+ *---------------------------------------------------------------------------*/
+fn parse_args(args: &[String], flags: &[&str]) -> Result<Args, String>
+{
+    let mut result = Args { positional: Vec::new(), options: Vec::new() };
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if !arg.starts_with('-') || arg == "-" { result.positional.push(arg.clone()); }
+        else if flags.contains(&arg.as_str()) { result.options.push((arg.clone(), None)); }
+        else {
+            let value = iter.next().ok_or_else(|| format!("option {} needs a value", arg))?;
+            result.options.push((arg.clone(), Some(value.clone())));
+        }
+    }
+    return Ok(result);
+}
+
+impl Args
+{
+    fn positional(&self, index: usize, name: &str) -> Result<&str, String>
+    {
+        self.positional.get(index).map(|arg| arg.as_str()).ok_or_else(|| format!("missing <{}>\n\n{}", name, USAGE))
+    }
+
+    fn option(&self, name: &str) -> Option<&str>
+    {
+        self.options.iter().find(|(key, _)| key == name).and_then(|(_, value)| value.as_deref())
+    }
+
+    fn flag(&self, name: &str) -> bool
+    {
+        self.options.iter().any(|(key, _)| key == name)
+    }
+}
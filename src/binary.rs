@@ -0,0 +1,103 @@
+/*/////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+ *
+ *      Compact binary saves for ByteDungeon, the JSON save document encoded as MessagePack
+ *
+ *      Implementation and Assumptions
+ *          - A binary save holds exactly the document GameSession::to_save produces, decoding it gives back the same
+ *            JSON and goes through the same migrations as a JSON save (see migrate.rs)
+ *          - Layout: 4 byte magic "BDNG", 1 byte container version, 1 byte flags, then the MessagePack payload
+ *          - Flag bit 0 means the payload is deflate compressed (miniz_oxide, pure Rust so it also runs in wasm)
+ *          - Decompressed payloads are capped at MAX_PAYLOAD so a corrupted or hostile file can't exhaust memory
+ *
+ */////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+
+use std::fmt;
+
+use crate::GameSession;
+use crate::migrate::{load_save, LoadError};
+
+pub const MAGIC: [u8; 4] = *b"BDNG";
+pub const CONTAINER_VERSION: u8 = 1;
+pub const FLAG_DEFLATE: u8 = 0b0000_0001;
+const HEADER_LEN: usize = 6;
+const MAX_PAYLOAD: usize = 64 * 1024 * 1024;
+const DEFLATE_LEVEL: u8 = 6;
+
+/***********************************************
+ * BinaryError - Binary saves that were refused
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum BinaryError
+{
+    NotASave,                                   // Too short or wrong magic
+    UnknownContainer(u8),
+    UnknownFlags(u8),
+    Decompress,
+    Decode(String),
+    Load(LoadError)
+}
+
+impl fmt::Display for BinaryError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BinaryError::NotASave => write!(f, "not a ByteDungeon binary save"),
+            BinaryError::UnknownContainer(version) => write!(f, "unknown binary save version {}", version),
+            BinaryError::UnknownFlags(flags) => write!(f, "unknown binary save flags {:#04x}", flags),
+            BinaryError::Decompress => write!(f, "binary save is corrupted (decompression failed)"),
+            BinaryError::Decode(reason) => write!(f, "binary save is corrupted: {}", reason),
+            BinaryError::Load(err) => write!(f, "{}", err)
+        }
+    }
+}
+
+/******************************************************************************
+ *  encode_game - Encodes a session as a binary save, optionally compressed
+ *---------------------------------------------------------------------------*/
+pub fn encode_game(game: &GameSession, compress: bool) -> Vec<u8>
+{
+    let payload = rmp_serde::to_vec(&game.to_save()).unwrap();
+    let mut result = Vec::with_capacity(HEADER_LEN + payload.len());
+    result.extend_from_slice(&MAGIC);
+    result.push(CONTAINER_VERSION);
+    if compress {
+        result.push(FLAG_DEFLATE);
+        result.extend(miniz_oxide::deflate::compress_to_vec(&payload, DEFLATE_LEVEL));
+    }
+    else {
+        result.push(0);
+        result.extend(payload);
+    }
+    return result;
+}
+
+/******************************************************************************
+ *  decode_document - Decodes a binary save back into its JSON save document
+ *---------------------------------------------------------------------------*/
+pub fn decode_document(bytes: &[u8]) -> Result<Value, BinaryError>
+{
+    if bytes.len() < HEADER_LEN || bytes[..4] != MAGIC { return Err(BinaryError::NotASave); }
+    if bytes[4] != CONTAINER_VERSION { return Err(BinaryError::UnknownContainer(bytes[4])); }
+    let flags = bytes[5];
+    if flags & !FLAG_DEFLATE != 0 { return Err(BinaryError::UnknownFlags(flags)); }
+
+    let inflated;
+    let payload = if flags & FLAG_DEFLATE != 0 {
+        inflated = miniz_oxide::inflate::decompress_to_vec_with_limit(&bytes[HEADER_LEN..], MAX_PAYLOAD)
+            .map_err(|_| BinaryError::Decompress)?;
+        &inflated[..]
+    }
+    else { &bytes[HEADER_LEN..] };
+    return rmp_serde::from_slice(payload).map_err(|err| BinaryError::Decode(err.to_string()));
+}
+
+/******************************************************************************
+ *  decode_game - Decodes and loads a binary save, see migrate::load_save
+ *---------------------------------------------------------------------------*/
+pub fn decode_game(bytes: &[u8]) -> Result<(GameSession, Vec<String>), BinaryError>
+{
+    return load_save(decode_document(bytes)?).map_err(BinaryError::Load);
+}
@@ -0,0 +1,350 @@
+/*/////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+ *
+ *      Chat commands and dice expressions for ByteDungeon, what a line typed in the chat panel means
+ *
+ *      Implementation and Assumptions
+ *          - Lines starting with '/' are commands:
+ *              /roll EXPR, /r EXPR     roll a dice expression for everyone to see
+ *              /w USER TEXT            whisper to one user (by user id or display name), /whisper works too
+ *              /me TEXT                emote, ie: "/me waves" reads "Ana waves"
+ *            anything else is said to the room
+ *          - Text (said, whispered or emoted) can hold inline rolls between double brackets: "I hit for [[1d8+2]]"
+ *          - Dice expressions are terms added or subtracted: dice (2d6, d20), numbers (3) and stat names
+ *            (Dexterity) taken from the speaker's token, stat names are matched ignoring case
+ *          - At most MAX_DICE dice per term and MAX_SIDES sides per die, and at most MAX_LINE_DICE dice and MAX_TERMS
+ *            terms in a whole line (inline rolls added up), so a line can't stall the server or flood the event log
+ *          - An expression whose total could leave the i32 range with some dice is refused, totals never overflow
+ *          - Parsing never rolls, a line is parsed and its stats resolved before any die is rolled, so a refused line
+ *            doesn't use up the dice generator, dice are rolled and logged like any roll (see events.rs)
+ *
+ */////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+use serde::{Serialize, Deserialize};
+
+use std::fmt;
+
+use crate::GameSession;
+
+pub const MAX_DICE: u32 = 100;
+pub const MAX_SIDES: u32 = 1000;
+pub const MAX_LINE_DICE: u32 = 200;
+pub const MAX_TERMS: usize = 50;
+
+/***********************************************
+ * ChatKind - How a chat line is delivered
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(ts_rs::TS))]
+pub enum ChatKind
+{
+    Say,
+    Roll,
+    Whisper { to: String },                     // User id or display name of the recipient
+    Emote
+}
+
+/***********************************************
+ * Term - One term of a dice expression
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(ts_rs::TS))]
+pub enum Term
+{
+    Dice { count: u32, sides: u32 },
+    Flat(i32),
+    Stat(String)                                // Stat of the speaker's token
+}
+
+/***********************************************
+ * Expression - Parsed dice expression
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Expression
+{
+    pub source: String,                         // As typed, without spaces
+    pub terms: Vec<(bool, Term)>                // True for subtracted terms
+}
+
+/***********************************************
+ * ChatCommand - Parsed chat line, not rolled
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ChatCommand
+{
+    pub kind: ChatKind,
+    pub text: String,                           // Text with its inline rolls, the expression of a roll
+    pub expressions: Vec<Expression>            // Inline rolls in order, or the rolled expression
+}
+
+/***********************************************
+ * RolledTerm - Term with the values it gave
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(ts_rs::TS))]
+pub struct RolledTerm
+{
+    pub negative: bool,
+    pub term: Term,
+    pub values: Vec<i32>                        // Each die rolled, or the number or stat
+}
+
+/***********************************************
+ * DiceRoll - Rolled dice expression
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(ts_rs::TS))]
+pub struct DiceRoll
+{
+    pub expression: String,
+    pub terms: Vec<RolledTerm>,
+    pub total: i32
+}
+
+/***********************************************
+ * ChatMessage - Chat line ready to be routed
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(ts_rs::TS))]
+pub struct ChatMessage
+{
+    pub kind: ChatKind,
+    pub text: String,
+    pub rolls: Vec<DiceRoll>                    // Inline rolls in order, or the rolled expression
+}
+
+/***********************************************
+ * ChatError - Chat lines that were refused
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ChatError
+{
+    Empty,
+    UnknownCommand(String),
+    MissingArgument(String),                    // Command that is missing what follows it
+    BadDice(String),                            // Term that isn't dice, a number or a stat name
+    TooManyDice(String),
+    LineDice(u32),                              // Dice rolled by the whole line
+    TooManyTerms(usize),                        // Terms in the whole line
+    Overflow(String),                           // Expression whose total could overflow
+    NoToken(String),                            // Stat named by a speaker without a token
+    UnknownStat(String)
+}
+
+impl fmt::Display for ChatError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChatError::Empty => write!(f, "nothing to say"),
+            ChatError::UnknownCommand(command) => write!(f, "no chat command '{}'", command),
+            ChatError::MissingArgument(command) => write!(f, "'{}' needs more, ie: /roll 1d20, /w user text, /me text", command),
+            ChatError::BadDice(term) => write!(f, "'{}' isn't dice, a number or a stat", term),
+            ChatError::TooManyDice(term) => write!(f, "'{}' rolls too many dice (at most {}d{})", term, MAX_DICE, MAX_SIDES),
+            ChatError::LineDice(count) => write!(f, "the line rolls {} dice, at most {}", count, MAX_LINE_DICE),
+            ChatError::TooManyTerms(count) => write!(f, "the line has {} terms, at most {}", count, MAX_TERMS),
+            ChatError::Overflow(expression) => write!(f, "'{}' could add up to more than a total can hold", expression),
+            ChatError::NoToken(stat) => write!(f, "no token to take {} from", stat),
+            ChatError::UnknownStat(stat) => write!(f, "no stat named '{}'", stat)
+        }
+    }
+}
+
+impl fmt::Display for DiceRoll
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: ", self.expression)?;
+        for (n, rolled) in self.terms.iter().enumerate() {
+            if rolled.negative { write!(f, "-")?; } else if n > 0 { write!(f, "+")?; }
+            match rolled.term {
+                Term::Dice { .. } => {
+                    let faces: Vec<String> = rolled.values.iter().map(|value| value.to_string()).collect();
+                    write!(f, "[{}]", faces.join(","))?;
+                },
+                _ => write!(f, "{}", rolled.values[0])?
+            }
+        }
+        write!(f, " = {}", self.total)
+    }
+}
+
+impl ChatMessage
+{
+    /******************************************************************************
+     *  render - The line as shown in the chat panel, NAME is the speaker's
+     *---------------------------------------------------------------------------*/
+    pub fn render(&self, name: &str) -> String
+    {
+        let mut text = String::new();
+        let mut rolls = self.rolls.iter().peekable();
+        let mut rest = self.text.as_str();
+        while let (Some(start), Some(roll)) = (rest.find("[["), rolls.peek()) {
+            let end = match rest[start..].find("]]") { Some(end) => start + end, None => break };
+            text += &format!("{}[{}]", &rest[..start], roll);
+            rest = &rest[end + 2..];
+            rolls.next();
+        }
+        text += rest;
+        match &self.kind {
+            ChatKind::Say => format!("{}: {}", name, text),
+            ChatKind::Roll => format!("{} rolls {}", name, self.rolls[0]),
+            ChatKind::Whisper { to } => format!("{} whispers to {}: {}", name, to, text),
+            ChatKind::Emote => format!("{} {}", name, text)
+        }
+    }
+}
+
+/******************************************************************************
+ *  parse - Reads a chat line, see header
+ *---------------------------------------------------------------------------*/
+pub fn parse(input: &str) -> Result<ChatCommand, ChatError>
+{
+    let input = input.trim();
+    if input.is_empty() { return Err(ChatError::Empty); }
+    if !input.starts_with('/') { return text_command(ChatKind::Say, input); }
+
+    let (command, rest) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+    let rest = rest.trim();
+    if !["/roll", "/r", "/w", "/whisper", "/me"].contains(&command) { return Err(ChatError::UnknownCommand(command.to_string())); }
+    if rest.is_empty() { return Err(ChatError::MissingArgument(command.to_string())); }
+    match command {
+        "/roll" | "/r" => {
+            let expression = parse_expression(rest)?;
+            Ok(ChatCommand { kind: ChatKind::Roll, text: expression.source.clone(), expressions: vec![expression] })
+        },
+        "/w" | "/whisper" => {
+            let (to, text) = rest.split_once(char::is_whitespace).ok_or_else(|| ChatError::MissingArgument(command.to_string()))?;
+            text_command(ChatKind::Whisper { to: to.to_string() }, text.trim())
+        },
+        _ => text_command(ChatKind::Emote, rest)
+    }
+}
+
+/******************************************************************************
+ *  text_command - Text of KIND with its inline rolls, an unclosed "[[" is
+ *                 kept as text
+ *---------------------------------------------------------------------------*/
+fn text_command(kind: ChatKind, text: &str) -> Result<ChatCommand, ChatError>
+{
+    let mut expressions = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("[[") {
+        let end = match rest[start..].find("]]") { Some(end) => start + end, None => break };
+        expressions.push(parse_expression(&rest[start + 2..end])?);
+        rest = &rest[end + 2..];
+    }
+    return Ok(ChatCommand { kind, text: text.to_string(), expressions });
+}
+
+/******************************************************************************
+ *  parse_expression - Reads a dice expression, ie: 2d6+3, 1d20+Dexterity
+ *---------------------------------------------------------------------------*/
+pub fn parse_expression(input: &str) -> Result<Expression, ChatError>
+{
+    let source: String = input.chars().filter(|c| !c.is_whitespace()).collect();
+    if source.is_empty() { return Err(ChatError::BadDice(source)); }
+    let mut terms = Vec::new();
+    let mut negative = false;
+    let mut start = 0;
+    for (n, c) in source.char_indices().chain(std::iter::once((source.len(), '+'))) {
+        if c != '+' && c != '-' { continue; }
+        let term = &source[start..n];
+        if term.is_empty() && !(n == 0 && c == '-') { return Err(ChatError::BadDice(source.clone())); }
+        if !term.is_empty() { terms.push((negative, parse_term(term)?)); }
+        negative = c == '-';
+        start = n + 1;
+    }
+    return Ok(Expression { source, terms });
+}
+
+fn parse_term(term: &str) -> Result<Term, ChatError>
+{
+    let bad = || ChatError::BadDice(term.to_string());
+    let digits = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    if let Some((count, sides)) = term.split_once(['d', 'D']) {
+        if (count.is_empty() || digits(count)) && digits(sides) {
+            let count = if count.is_empty() { 1 } else { count.parse::<u32>().map_err(|_| bad())? };
+            let sides = sides.parse::<u32>().map_err(|_| bad())?;
+            if count == 0 || sides == 0 { return Err(bad()); }
+            if count > MAX_DICE || sides > MAX_SIDES { return Err(ChatError::TooManyDice(term.to_string())); }
+            return Ok(Term::Dice { count, sides });
+        }
+    }
+    if digits(term) { return term.parse().map(Term::Flat).map_err(|_| bad()); }
+    if term.chars().all(|c| c.is_alphabetic() || c == '_') { return Ok(Term::Stat(term.to_string())); }
+    return Err(bad());
+}
+
+impl GameSession
+{
+    /******************************************************************************
+     *  chat - Parses and rolls a chat line of the player of TOKEN (None if the
+     *         speaker has no token)
+     *---------------------------------------------------------------------------*/
+    pub fn chat(&mut self, token: Option<char>, input: &str) -> Result<ChatMessage, ChatError>
+    {
+        let command = parse(input)?;
+        return self.roll_chat(token, command);
+    }
+
+    /******************************************************************************
+     *  roll_chat - Rolls the dice of a parsed chat line, stats are taken from
+     *              TOKEN, they and the limits of the header are all checked
+     *              before the first die is rolled
+     *---------------------------------------------------------------------------*/
+    pub fn roll_chat(&mut self, token: Option<char>, command: ChatCommand) -> Result<ChatMessage, ChatError>
+    {
+        let terms: usize = command.expressions.iter().map(|expression| expression.terms.len()).sum();
+        if terms > MAX_TERMS { return Err(ChatError::TooManyTerms(terms)); }
+        let dice: u32 = command.expressions.iter().flat_map(|expression| &expression.terms).map(|(_, term)| match term {
+            Term::Dice { count, .. } => *count,
+            _ => 0
+        }).sum();
+        if dice > MAX_LINE_DICE { return Err(ChatError::LineDice(dice)); }
+
+        let mut stats = Vec::new();
+        for expression in &command.expressions {
+            let mut values = Vec::new();
+            let (mut low, mut high): (i64, i64) = (0, 0);
+            for (negative, term) in &expression.terms {
+                let (least, most) = match term {
+                    Term::Dice { count, sides } => (*count as i64, *count as i64 * *sides as i64),
+                    Term::Flat(value) => (*value as i64, *value as i64),
+                    Term::Stat(name) => {
+                        values.push(self.stat_of(token, name)?);
+                        (*values.last().unwrap() as i64, *values.last().unwrap() as i64)
+                    }
+                };
+                if *negative { low -= most; high -= least; } else { low += least; high += most; }
+            }
+            if low < i32::MIN as i64 || high > i32::MAX as i64 { return Err(ChatError::Overflow(expression.source.clone())); }
+            stats.push(values);
+        }
+
+        let mut rolls = Vec::new();
+        for (expression, values) in command.expressions.into_iter().zip(stats) {
+            let mut values = values.into_iter();
+            let mut terms = Vec::new();
+            for (negative, term) in expression.terms {
+                let values = match &term {
+                    Term::Dice { count, sides } => (0..*count).map(|_| self.roll(1, *sides as i32)).collect(),
+                    Term::Flat(value) => vec![*value],
+                    Term::Stat(_) => vec![values.next().unwrap()]
+                };
+                terms.push(RolledTerm { negative, term, values });
+            }
+            let total: i64 = terms.iter().map(|rolled| {
+                let sum: i64 = rolled.values.iter().map(|value| *value as i64).sum();
+                if rolled.negative { -sum } else { sum }
+            }).sum();
+            let total = total as i32;                   // Fits, the range of the total was checked above
+            rolls.push(DiceRoll { expression: expression.source, terms, total });
+        }
+        return Ok(ChatMessage { kind: command.kind, text: command.text, rolls });
+    }
+
+    fn stat_of(&self, token: Option<char>, name: &str) -> Result<i32, ChatError>
+    {
+        let tok = token.and_then(|token| self.characters.get(&token)).ok_or_else(|| ChatError::NoToken(name.to_string()))?;
+        tok.sheet.stats.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| *value as i32)
+            .ok_or_else(|| ChatError::UnknownStat(name.to_string()))
+    }
+}
@@ -0,0 +1,113 @@
+/*/////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+ *
+ *      Desync detection for ByteDungeon, a stable hash of the game state and a field by field comparison
+ *
+ *      Implementation and Assumptions
+ *          - The state is first turned into canonical JSON: object keys are sorted (serde_json maps are ordered)
+ *            and every HashSet is serialized sorted, so HashMap/HashSet iteration order never changes the hash
+ *          - Only the game state is hashed: requests, the event and combat logs (with base_seq) and the version are
+ *            left out, two clients that ran the same requests have the same hash however they got there
+ *          - The dice generator is part of the state, a matching hash also means the next rolls match, player copies
+ *            reset it (see layers.rs) so they only match other player copies
+ *          - The hash is 64 bit FNV-1a, stable across platforms and releases, it detects desyncs, it isn't secure
+ *          - Field paths use dots for object keys and brackets for array indexes (ie: characters.🧝.sheet.hitpoints,
+ *            grid[3][4])
+ *
+ */////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+use serde::{Serialize, Serializer, Deserialize};
+use serde_json::Value;
+
+use std::collections::HashSet;
+
+use crate::GameSession;
+
+const SKIPPED: [&str; 5] = ["requests", "events", "base_seq", "combat_log", "version"];
+const FNV_OFFSET: u64 = 0xCBF2_9CE4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01B3;
+
+/***********************************************
+ * FieldDiff - One field that differs
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FieldDiff
+{
+    pub path: String,
+    pub left: Option<Value>,                    // None if the field is missing on that side
+    pub right: Option<Value>
+}
+
+/******************************************************************************
+ *  sorted_set - Serializes a HashSet in sorted order, used with serialize_with
+ *---------------------------------------------------------------------------*/
+pub fn sorted_set<S: Serializer>(set: &HashSet<String>, serializer: S) -> Result<S::Ok, S::Error>
+{
+    let mut items: Vec<&String> = set.iter().collect();
+    items.sort();
+    items.serialize(serializer)
+}
+
+impl GameSession
+{
+    /******************************************************************************
+     *  canonical_state - Game state as canonical JSON, see header
+     *---------------------------------------------------------------------------*/
+    pub fn canonical_state(&self) -> Value
+    {
+        let mut value = serde_json::to_value(self).unwrap();
+        if let Value::Object(fields) = &mut value {
+            for key in SKIPPED.iter() { fields.remove(*key); }
+        }
+        return value;
+    }
+
+    /******************************************************************************
+     *  state_hash - Stable hash of the game state as 16 hex digits
+     *---------------------------------------------------------------------------*/
+    pub fn state_hash(&self) -> String
+    {
+        let text = self.canonical_state().to_string();
+        let mut hash = FNV_OFFSET;
+        for byte in text.bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+        return format!("{:016x}", hash);
+    }
+}
+
+/******************************************************************************
+ *  compare_states - Returns the first LIMIT fields that differ between two
+ *                   sessions, in path order
+ *---------------------------------------------------------------------------*/
+pub fn compare_states(left: &GameSession, right: &GameSession, limit: usize) -> Vec<FieldDiff>
+{
+    let mut result = Vec::new();
+    compare_values("", Some(&left.canonical_state()), Some(&right.canonical_state()), limit, &mut result);
+    return result;
+}
+
+/******************************************************************************
+ *  compare_values - Recursive walk of two JSON values collecting differences
+ *---------------------------------------------------------------------------*/
+fn compare_values(path: &str, left: Option<&Value>, right: Option<&Value>, limit: usize, result: &mut Vec<FieldDiff>)
+{
+    if result.len() >= limit || left == right { return; }
+    match (left, right) {
+        (Some(Value::Object(a)), Some(Value::Object(b))) => {
+            let mut keys: Vec<&String> = a.keys().chain(b.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let child = if path.is_empty() { key.to_string() } else { format!("{}.{}", path, key) };
+                compare_values(&child, a.get(key), b.get(key), limit, result);
+            }
+        },
+        (Some(Value::Array(a)), Some(Value::Array(b))) => {
+            for n in 0..a.len().max(b.len()) {
+                compare_values(&format!("{}[{}]", path, n), a.get(n), b.get(n), limit, result);
+            }
+        },
+        _ => result.push(FieldDiff { path: path.to_string(), left: left.cloned(), right: right.cloned() })
+    }
+}
@@ -0,0 +1,197 @@
+/*/////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+ *
+ *      Combat log for ByteDungeon, a structured record of what every executed request did
+ *
+ *      Implementation and Assumptions
+ *          - Executing a request (GameSession::execute_request) returns an ActionRecord and appends it to the
+ *            session's combat log, the record compares the caster and the targets before and after the request:
+ *            hitpoints, effects gained or lost and cells moved to (a link followed at the end of a move included)
+ *          - The rolls of a record are the dice logged from the request's rolled_at (the d20 the server or the
+ *            simulator rolled for the attempt) or else from when the request started, chat rolls, detection checks
+ *            and failed attempts logged earlier are never counted
+ *          - A request whose caster isn't on a map is refused instead of recorded
+ *          - Records are never rewritten: undoing a request leaves its record, the log tells what happened at the
+ *            table, like the event log
+ *          - The log is saved with the game but isn't game state, it isn't hashed (see checksum.rs) or synced,
+ *            players only get the records of tokens they may see (see layers.rs), spectators none
+ *          - Lines for the chat panel come from Display, the JSON export is the serialized records
+ *
+ */////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+use serde::{Serialize, Deserialize};
+
+use std::fmt;
+use std::collections::BTreeSet;
+
+use crate::{GameSession, Request};
+use crate::maps::Cell;
+use crate::events::EventKind;
+use crate::permissions::Action;
+
+type TokenState = (Cell, i32, BTreeSet<String>);   // Cell, hitpoints and effects of a token
+
+/***********************************************
+ * RollRecord - Die rolled for a request
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RollRecord
+{
+    pub low: i32,
+    pub high: i32,
+    pub result: i32
+}
+
+/***********************************************
+ * TokenChange - How a request changed a token
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct TokenChange
+{
+    pub token: char,
+    pub hitpoints: i32,                         // Hitpoints gained, negative for damage
+    pub added: Vec<String>,                     // Effects gained, sorted
+    pub removed: Vec<String>,                   // Effects lost (ended or unequipped), sorted
+    pub moved: Option<(Cell, Cell)>             // Cell before and after the request
+}
+
+/***********************************************
+ * ActionRecord - Outcome of an executed request
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ActionRecord
+{
+    pub seq: u64,                               // Event the request was executed at, see events.rs
+    pub actor: char,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,                   // User that made the request, None for the local session
+    pub action: Option<Action>,                 // None for an unknown request type, which does nothing
+    pub detail: Option<String>,                 // Ability, item or equipment slot used
+    pub rolls: Vec<RollRecord>,
+    pub targets: Vec<char>,
+    pub changes: Vec<TokenChange>               // Tokens that changed, in the order they were watched
+}
+
+/***********************************************
+ * Watched - Tokens as a request found them
+ **********************************************/
+pub(crate) struct Watched
+{
+    seq: u64,                                   // Next event when the request started
+    detail: Option<String>,
+    tokens: Vec<(char, Option<TokenState>)>     // None for tokens that weren't on a map
+}
+
+impl fmt::Display for TokenChange
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some((from, to)) = &self.moved {
+            let map = if from.map != to.map { format!(" of {}", to.map) } else { String::new() };
+            parts.push(format!("{} to {},{}{}", self.token, to.row, to.column, map));
+        }
+        if self.hitpoints != 0 { parts.push(format!("{} {:+} HP", self.token, self.hitpoints)); }
+        for effect in &self.added { parts.push(format!("{} gains {}", self.token, effect)); }
+        for effect in &self.removed { parts.push(format!("{} loses {}", self.token, effect)); }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+impl fmt::Display for ActionRecord
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let detail = self.detail.clone().unwrap_or_default();
+        match self.action {
+            Some(Action::Move) => write!(f, "{} moves", self.actor)?,
+            Some(Action::Item) => write!(f, "{} uses {}", self.actor, detail)?,
+            Some(Action::Ability) => write!(f, "{} uses {}", self.actor, detail)?,
+            Some(Action::Unequip) => write!(f, "{} unequips {}", self.actor, detail)?,
+            None => write!(f, "{} does nothing", self.actor)?
+        }
+        if !self.targets.is_empty() { write!(f, " on {}", self.targets.iter().collect::<String>())?; }
+        if !self.rolls.is_empty() {
+            let rolls: Vec<String> = self.rolls.iter().map(|roll| match roll.low {
+                1 => format!("{} on d{}", roll.result, roll.high),
+                _ => format!("{} on {}-{}", roll.result, roll.low, roll.high)
+            }).collect();
+            write!(f, " (rolled {})", rolls.join(", "))?;
+        }
+        if self.changes.is_empty() { return Ok(()); }
+        let changes: Vec<String> = self.changes.iter().map(|change| change.to_string()).collect();
+        write!(f, ": {}", changes.join(", "))
+    }
+}
+
+impl GameSession
+{
+    /******************************************************************************
+     *  watch - Notes the tokens a request may change (caster, then targets)
+     *          before it is executed
+     *---------------------------------------------------------------------------*/
+    pub(crate) fn watch(&self, token: char, req: &Request) -> Watched
+    {
+        let mut keys = vec![token];
+        for target in req.target_tokens.iter().flatten() { if !keys.contains(target) { keys.push(*target); } }
+        let tokens = keys.into_iter().map(|key| (key, self.characters.get(&key).map(|tok| {
+            (Cell::new(&tok.map, tok.row, tok.column), tok.sheet.hitpoints, tok.sheet.effects.keys().cloned().collect())
+        }))).collect();
+
+        let detail = match req.action_type {
+            1 => req.subtype_key.as_ref().and_then(|key| key.parse::<usize>().ok())
+                .and_then(|index| self.characters.get(&token)?.sheet.items.get(index)).map(|item| item.name.clone()),
+            _ => req.subtype_key.clone()
+        };
+        return Watched { seq: self.next_seq(), detail, tokens };
+    }
+
+    /******************************************************************************
+     *  log_action - Compares the watched tokens with what the request left and
+     *               appends the record to the combat log
+     *---------------------------------------------------------------------------*/
+    pub(crate) fn log_action(&mut self, token: char, req: &Request, watched: Watched) -> ActionRecord
+    {
+        let from = req.rolled_at.unwrap_or(watched.seq);
+        let rolls = self.events_since(from).iter().filter_map(|event| match event.kind {
+            EventKind::Roll { low, high, result, .. } => Some(RollRecord { low, high, result }),
+            _ => None
+        }).collect();
+
+        let mut changes = Vec::new();
+        for (key, before) in watched.tokens {
+            let (tok, (cell, hitpoints, effects)) = match (self.characters.get(&key), before) {
+                (Some(tok), Some(before)) => (tok, before),
+                _ => continue
+            };
+            let now: BTreeSet<String> = tok.sheet.effects.keys().cloned().collect();
+            let to = Cell::new(&tok.map, tok.row, tok.column);
+            let change = TokenChange {
+                token: key,
+                hitpoints: tok.sheet.hitpoints - hitpoints,
+                added: now.difference(&effects).cloned().collect(),
+                removed: effects.difference(&now).cloned().collect(),
+                moved: if to != cell { Some((cell, to)) } else { None }
+            };
+            if change != (TokenChange { token: key, ..Default::default() }) { changes.push(change); }
+        }
+
+        let record = ActionRecord {
+            seq: self.next_seq(),
+            actor: token,
+            user: req.user.clone(),
+            action: Action::of(req.action_type),
+            detail: watched.detail,
+            rolls,
+            targets: req.target_tokens.clone().unwrap_or_default(),
+            changes
+        };
+        self.combat_log.push(record.clone());
+        return record;
+    }
+
+    /******************************************************************************
+     *  combat_lines - The combat log as lines for the chat panel, oldest first
+     *---------------------------------------------------------------------------*/
+    pub fn combat_lines(&self) -> Vec<String>
+    {
+        self.combat_log.iter().map(|record| record.to_string()).collect()
+    }
+}
@@ -0,0 +1,172 @@
+/*/////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+ *
+ *      Event log for ByteDungeon, an append-only record of everything that changed a session
+ *
+ *      Implementation and Assumptions
+ *          - Every applied command (DM edits and executed requests), undo/redo, logged request and dice roll is an event
+ *          - Events are numbered by seq, starting at 0, and are saved with the game so an export carries its own log,
+ *            a stored snapshot drops the events it covers and numbering goes on from its base_seq (see storage.rs)
+ *          - Only commands and undo/redo bump GameSession.version, requests, sorts, groups and rolls don't change what
+ *            a delta carries (see sync.rs) and may happen on a client alone
+ *          - Rolls store the generator state they started from (the seed), replaying a roll reproduces the same result
+ *          - A snapshot is any saved game, replay starts from it and applies the events logged after its last seq
+ *          - Replay goes through the same code as the live session, so the rebuilt game logs the same events again
+ *          - Undo during replay only reaches edits made after the snapshot, a replayed undo that doesn't match the
+ *            recorded one is reported as a divergence instead of silently producing another state
+ *
+ */////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+use serde::{Serialize, Deserialize};
+
+use std::fmt;
+
+use crate::{GameSession, Request};
+use crate::history::Command;
+use crate::rng::Rng;
+
+/***********************************************
+ * EventKind - What changed the session
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum EventKind
+{
+    Command(Box<Command>),                      // Edit or executed request, see history.rs
+    Undo(String),                               // Label of the entry that was undone
+    Redo(String),
+    BeginGroup(String),
+    EndGroup,
+    Requested { token: char, requests: Vec<Request> },
+    SortRequests,
+    Roll { seed: u64, low: i32, high: i32, result: i32 }
+}
+
+/***********************************************
+ * Event - Numbered entry of the event log
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Event
+{
+    pub seq: u64,
+    pub kind: EventKind
+}
+
+/***********************************************
+ * ReplayError - Events that couldn't be replayed
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ReplayError
+{
+    Gap { expected: u64, found: u64 },          // Events are missing between the snapshot and the log
+    Diverged { seq: u64, reason: String }       // The event didn't have the same outcome as when it was recorded
+}
+
+impl fmt::Display for ReplayError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Gap { expected, found } => write!(f, "event log jumps from {} to {}", expected, found),
+            ReplayError::Diverged { seq, reason } => write!(f, "event {} diverged: {}", seq, reason)
+        }
+    }
+}
+
+impl EventKind
+{
+    /******************************************************************************
+     *  is_synced - True if the event changes state that deltas carry
+     *---------------------------------------------------------------------------*/
+    pub fn is_synced(&self) -> bool
+    {
+        matches!(self, EventKind::Command(_) | EventKind::Undo(_) | EventKind::Redo(_))
+    }
+}
+
+impl GameSession
+{
+    /******************************************************************************
+     *  next_seq - Sequence number the next event will get
+     *---------------------------------------------------------------------------*/
+    pub fn next_seq(&self) -> u64
+    {
+        self.events.last().map_or(self.base_seq, |event| event.seq + 1)
+    }
+
+    /******************************************************************************
+     *  record - Appends an event to the log, bumps the session version if it
+     *           changed synced state
+     *---------------------------------------------------------------------------*/
+    pub fn record(&mut self, kind: EventKind)
+    {
+        let seq = self.next_seq();
+        if kind.is_synced() { self.version += 1; }
+        self.events.push(Event { seq, kind });
+    }
+
+    /******************************************************************************
+     *  roll - Rolls a number in [LOW, HIGH] with the session generator and logs it
+     *---------------------------------------------------------------------------*/
+    pub fn roll(&mut self, low: i32, high: i32) -> i32
+    {
+        let seed = self.rng.state();
+        let result = self.rng.range(low, high);
+        self.record(EventKind::Roll { seed, low, high, result });
+        return result;
+    }
+
+    /******************************************************************************
+     *  log_request - Logs a player's requests for the DM to review
+     *---------------------------------------------------------------------------*/
+    pub fn log_request(&mut self, token: char, requests: Vec<Request>)
+    {
+        self.requests.push((token, requests.clone()));
+        self.record(EventKind::Requested { token, requests });
+    }
+
+    /******************************************************************************
+     *  events_since - Returns the events with a seq of at least FROM
+     *---------------------------------------------------------------------------*/
+    pub fn events_since(&self, from: u64) -> &[Event]
+    {
+        let start = self.events.iter().position(|event| event.seq >= from).unwrap_or(self.events.len());
+        &self.events[start..]
+    }
+}
+
+/******************************************************************************
+ *  replay - Rebuilds a session from a snapshot and the events logged after it
+ *
+ *  PARAMS: SNAPSHOT is any saved game, EVENTS a log that continues it (events
+ *          already in the snapshot are skipped), UPTO the last seq to apply
+ *  RETURN: The rebuilt session, its log ends with the last replayed event
+ *---------------------------------------------------------------------------*/
+pub fn replay(snapshot: &GameSession, events: &[Event], upto: Option<u64>) -> Result<GameSession, ReplayError>
+{
+    let mut game = snapshot.clone();
+    for event in events {
+        if event.seq < game.next_seq() { continue; }
+        if upto.is_some_and(|last| event.seq > last) { break; }
+        if event.seq != game.next_seq() { return Err(ReplayError::Gap { expected: game.next_seq(), found: event.seq }); }
+        let diverged = |reason: String| Err(ReplayError::Diverged { seq: event.seq, reason });
+
+        match &event.kind {
+            EventKind::Command(command) => {
+                if let Err(err) = game.apply((**command).clone()) { return diverged(err.to_string()); }
+            },
+            EventKind::Undo(label) => {
+                if game.undo().as_ref() != Some(label) { return diverged(format!("can't undo '{}'", label)); }
+            },
+            EventKind::Redo(label) => {
+                if game.redo().as_ref() != Some(label) { return diverged(format!("can't redo '{}'", label)); }
+            },
+            EventKind::BeginGroup(label) => game.begin_group(label),
+            EventKind::EndGroup => game.end_group(),
+            EventKind::Requested { token, requests } => game.log_request(*token, requests.clone()),
+            EventKind::SortRequests => game.sort_requests(),
+            EventKind::Roll { seed, low, high, result } => {
+                game.rng = Rng::new(*seed);
+                if game.roll(*low, *high) != *result { return diverged(format!("roll didn't give {}", result)); }
+            }
+        }
+    }
+    return Ok(game);
+}
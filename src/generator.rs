@@ -0,0 +1,419 @@
+/*/////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+ *
+ *      Procedural dungeon generator for ByteDungeon, writes layouts straight into a session map
+ *
+ *      Implementation and Assumptions
+ *          - Three layouts: rooms and corridors (BSP), caves (cellular automata) and mazes (recursive backtracker)
+ *          - Walls are '1' and floors are '0', exactly like the cells toggled by hand in the editor
+ *          - The outer border is always wall and every floor cell is reachable from every other floor cell
+ *          - Floor regions are found once, each is joined to the nearest connected cell found by searching outward from it
+ *          - All randomness comes from rng::Rng seeded with GeneratorOptions.seed, the same options give the same map
+ *          - Doors, traps and spawn points are returned as map objects, they are only placed on floor cells
+ *
+ */////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+use serde::{Serialize, Deserialize};
+
+use std::fmt;
+use std::collections::VecDeque;
+
+use crate::GameSession;
+use crate::maps::ObjectKind;
+use crate::rng::Rng;
+
+const MIN_SIZE: usize = 5;                      // Smallest map that still has an interior
+pub const MAX_CELLS: usize = 1 << 20;               // Keeps a typo in the size from freezing the client
+const MIN_LEAF: usize = 5;                      // Smallest BSP leaf, a 3x3 room plus its walls
+
+/***********************************************
+ * Layout - Family of layouts to generate
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Layout
+{
+    Rooms,
+    Caves,
+    Maze
+}
+
+/***********************************************
+ * GeneratorOptions - Parameters of a dungeon
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct GeneratorOptions
+{
+    pub layout: Layout,
+    pub rows: usize,
+    pub cols: usize,
+    pub seed: u64,
+    pub room_count: usize,                      // Rooms only, number of rooms wanted (fewer if the map is too small)
+    pub density: Option<u32>,                   // Percent, rooms: wall left in each BSP leaf (35), caves: starting rock (45),
+                                                // maze: inner walls kept, 100 is a perfect maze (90)
+    pub doors: bool,                            // Rooms only, places a door where a corridor enters a room
+    pub traps: usize,
+    pub spawns: usize
+}
+
+/***********************************************
+ * GeneratedMap - Grid and objects of a dungeon
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct GeneratedMap
+{
+    pub grid: Vec<Vec<char>>,
+    pub objects: Vec<(usize, usize, ObjectKind)>
+}
+
+/***********************************************
+ * GeneratorError - Options that can't be used
+ **********************************************/
+#[derive(Clone, Debug, PartialEq)]
+pub enum GeneratorError
+{
+    TooSmall { rows: usize, cols: usize },
+    TooLarge { rows: usize, cols: usize },
+    InvalidDensity(u32),
+    NoFloor { wanted: usize, available: usize }  // More traps/spawns requested than free floor cells
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Rect
+{
+    row: usize,
+    col: usize,
+    height: usize,
+    width: usize
+}
+
+impl Default for GeneratorOptions
+{
+    fn default() -> GeneratorOptions {
+        GeneratorOptions { layout: Layout::Rooms, rows: 24, cols: 32, seed: 0, room_count: 8, density: None,
+            doors: true, traps: 0, spawns: 0 }
+    }
+}
+
+impl fmt::Display for GeneratorError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GeneratorError::TooSmall { rows, cols } =>
+                write!(f, "map of {}x{} is too small, at least {}x{} is needed", rows, cols, MIN_SIZE, MIN_SIZE),
+            GeneratorError::TooLarge { rows, cols } =>
+                write!(f, "map of {}x{} is too large, at most {} cells are allowed", rows, cols, MAX_CELLS),
+            GeneratorError::InvalidDensity(density) => write!(f, "density {} is not a percentage (0-100)", density),
+            GeneratorError::NoFloor { wanted, available } =>
+                write!(f, "{} traps/spawns requested but only {} floor cells are free", wanted, available)
+        }
+    }
+}
+
+impl GameSession
+{
+    /******************************************************************************
+     *  generate_map - Replaces the map with the input id by a generated dungeon,
+     *                 see GameSession::replace_layout
+     *---------------------------------------------------------------------------*/
+    pub fn generate_map(&mut self, map: &str, options: &GeneratorOptions) -> Result<(), GeneratorError>
+    {
+        let generated = generate(options)?;
+        self.replace_layout(map, generated.grid, generated.objects, Vec::new());
+        return Ok(());
+    }
+}
+
+/******************************************************************************
+ *  generate - Generates a dungeon layout from the input options
+ *---------------------------------------------------------------------------*/
+pub fn generate(options: &GeneratorOptions) -> Result<GeneratedMap, GeneratorError>
+{
+    let (rows, cols) = (options.rows, options.cols);
+    if rows < MIN_SIZE || cols < MIN_SIZE { return Err(GeneratorError::TooSmall { rows, cols }); }
+    if rows.saturating_mul(cols) > MAX_CELLS { return Err(GeneratorError::TooLarge { rows, cols }); }
+    let density = options.density.unwrap_or(match options.layout {
+        Layout::Rooms => 35,
+        Layout::Caves => 45,
+        Layout::Maze => 90
+    });
+    if density > 100 { return Err(GeneratorError::InvalidDensity(density)); }
+
+    let mut rng = Rng::new(options.seed);
+    let mut grid = vec![vec!['1'; cols]; rows];
+    let mut objects = Vec::new();
+
+    match options.layout {
+        Layout::Rooms => {
+            let inner = Rect { row: 1, col: 1, height: rows - 2, width: cols - 2 };
+            let rooms = carve_rooms(&mut grid, inner, options.room_count.max(1), density, &mut rng);
+            if options.doors {
+                for (row, col) in find_doorways(&grid, &rooms) { objects.push((row, col, ObjectKind::Door)); }
+            }
+        },
+        Layout::Caves => carve_caves(&mut grid, density, &mut rng),
+        Layout::Maze => carve_maze(&mut grid, density, &mut rng)
+    }
+    connect_regions(&mut grid, &mut rng);
+
+    let mut free = Vec::new();
+    for (row, cells) in grid.iter().enumerate() {
+        for (col, cell) in cells.iter().enumerate() {
+            if *cell == '0' && !objects.iter().any(|obj| obj.0 == row && obj.1 == col) { free.push((row, col)); }
+        }
+    }
+    let wanted = options.spawns + options.traps;
+    if wanted > free.len() { return Err(GeneratorError::NoFloor { wanted, available: free.len() }); }
+    rng.shuffle(&mut free);
+    for (n, (row, col)) in free.into_iter().take(wanted).enumerate() {
+        let kind = if n < options.spawns { ObjectKind::Spawn(None) } else { ObjectKind::Trap };
+        objects.push((row, col, kind));
+    }
+    return Ok(GeneratedMap { grid, objects });
+}
+
+/******************************************************************************
+ *  carve_rooms - BSP, splits RECT until WANTED leaves, carves a room in each
+ *                leaf and joins the two halves of every split with a corridor
+ *---------------------------------------------------------------------------*/
+fn carve_rooms(grid: &mut [Vec<char>], rect: Rect, wanted: usize, density: u32, rng: &mut Rng) -> Vec<Rect>
+{
+    let vertical = rect.width > rect.height;
+    let size = if vertical { rect.width } else { rect.height };
+    if wanted <= 1 || size < MIN_LEAF * 2 {
+        let room = carve_room(grid, rect, density, rng);
+        return vec![room];
+    }
+
+    let cut = MIN_LEAF + rng.below(size - MIN_LEAF * 2 + 1);
+    let (first, second) = if vertical {
+        (Rect { width: cut, ..rect }, Rect { col: rect.col + cut, width: rect.width - cut, ..rect })
+    } else {
+        (Rect { height: cut, ..rect }, Rect { row: rect.row + cut, height: rect.height - cut, ..rect })
+    };
+    let mut rooms = carve_rooms(grid, first, wanted / 2, density, rng);
+    let others = carve_rooms(grid, second, wanted - wanted / 2, density, rng);
+
+    let mut best = (usize::MAX, rooms[0], others[0]);
+    for a in &rooms {
+        for b in &others {
+            let (ar, ac) = center(a);
+            let (br, bc) = center(b);
+            let distance = ar.abs_diff(br) + ac.abs_diff(bc);
+            if distance < best.0 { best = (distance, *a, *b); }
+        }
+    }
+    carve_corridor(grid, center(&best.1), center(&best.2), rng);
+    rooms.extend(others);
+    return rooms;
+}
+
+/******************************************************************************
+ *  carve_room - Carves a random room inside LEAF, keeping a wall around it
+ *---------------------------------------------------------------------------*/
+fn carve_room(grid: &mut [Vec<char>], leaf: Rect, density: u32, rng: &mut Rng) -> Rect
+{
+    let max_height = leaf.height.saturating_sub(2).max(1);
+    let max_width = leaf.width.saturating_sub(2).max(1);
+    let fill = 100 - density as usize;
+    let height = (max_height * fill / 100).clamp(3.min(max_height), max_height);
+    let width = (max_width * fill / 100).clamp(3.min(max_width), max_width);
+    let row = leaf.row + 1 + rng.below(max_height - height + 1);
+    let col = leaf.col + 1 + rng.below(max_width - width + 1);
+    let room = Rect { row, col, height, width };
+
+    for cells in &mut grid[room.row..room.row + room.height] {
+        for cell in &mut cells[room.col..room.col + room.width] { *cell = '0'; }
+    }
+    return room;
+}
+
+/******************************************************************************
+ *  find_doorways - Floor cells just outside a room with walls on both sides
+ *---------------------------------------------------------------------------*/
+fn find_doorways(grid: &[Vec<char>], rooms: &[Rect]) -> Vec<(usize, usize)>
+{
+    let mut result = Vec::new();
+    for room in rooms {
+        let mut ring = Vec::new();
+        for c in room.col..room.col + room.width {
+            ring.push((room.row - 1, c, true));
+            ring.push((room.row + room.height, c, true));
+        }
+        for r in room.row..room.row + room.height {
+            ring.push((r, room.col - 1, false));
+            ring.push((r, room.col + room.width, false));
+        }
+        for (r, c, horizontal) in ring {
+            if r == 0 || c == 0 || r + 1 >= grid.len() || c + 1 >= grid[0].len() || grid[r][c] != '0' { continue; }
+            let walled = if horizontal { grid[r][c - 1] == '1' && grid[r][c + 1] == '1' }
+                         else          { grid[r - 1][c] == '1' && grid[r + 1][c] == '1' };
+            if walled && !result.contains(&(r, c)) { result.push((r, c)); }
+        }
+    }
+    return result;
+}
+
+/******************************************************************************
+ *  carve_caves - Random rock at DENSITY percent smoothed by cellular automata
+ *---------------------------------------------------------------------------*/
+fn carve_caves(grid: &mut Vec<Vec<char>>, density: u32, rng: &mut Rng)
+{
+    let (rows, cols) = (grid.len(), grid[0].len());
+    for cells in &mut grid[1..rows - 1] {
+        for cell in &mut cells[1..cols - 1] {
+            *cell = if rng.chance(density) { '1' } else { '0' };
+        }
+    }
+    for _ in 0..4 {
+        let mut next = grid.clone();
+        for (row, cells) in next.iter_mut().enumerate().take(rows - 1).skip(1) {
+            for (col, cell) in cells.iter_mut().enumerate().take(cols - 1).skip(1) {
+                let around: usize = grid[row - 1..=row + 1].iter().map(|near| near[col - 1..=col + 1].iter().filter(|c| **c == '1').count()).sum();
+                let walls = around - (grid[row][col] == '1') as usize;
+                if walls >= 5 { *cell = '1'; }
+                else if walls < 4 { *cell = '0'; }
+            }
+        }
+        *grid = next;
+    }
+    if grid.iter().all(|row| row.iter().all(|c| *c == '1')) { grid[rows / 2][cols / 2] = '0'; }
+}
+
+/******************************************************************************
+ *  carve_maze - Recursive backtracker over odd cells, then knocks down inner
+ *               walls so that only DENSITY percent of them remain
+ *---------------------------------------------------------------------------*/
+fn carve_maze(grid: &mut [Vec<char>], density: u32, rng: &mut Rng)
+{
+    let (rows, cols) = (grid.len(), grid[0].len());
+    let mut stack = vec![(1, 1)];
+    grid[1][1] = '0';
+    while let Some(&(row, col)) = stack.last() {
+        let mut next = Vec::new();
+        if row >= 3 && grid[row - 2][col] == '1' { next.push((row - 2, col)); }
+        if row + 2 < rows - 1 && grid[row + 2][col] == '1' { next.push((row + 2, col)); }
+        if col >= 3 && grid[row][col - 2] == '1' { next.push((row, col - 2)); }
+        if col + 2 < cols - 1 && grid[row][col + 2] == '1' { next.push((row, col + 2)); }
+        if next.is_empty() { stack.pop(); continue; }
+
+        let (r, c) = next[rng.below(next.len())];
+        grid[(row + r) / 2][(col + c) / 2] = '0';
+        grid[r][c] = '0';
+        stack.push((r, c));
+    }
+
+    for row in 1..rows - 1 {
+        for col in 1..cols - 1 {
+            if grid[row][col] != '1' || (row % 2 == 1) == (col % 2 == 1) { continue; }
+            let joins_rows = row % 2 == 0 && row + 1 < rows - 1 && grid[row - 1][col] == '0' && grid[row + 1][col] == '0';
+            let joins_cols = col % 2 == 0 && col + 1 < cols - 1 && grid[row][col - 1] == '0' && grid[row][col + 1] == '0';
+            if (joins_rows || joins_cols) && !rng.chance(density) { grid[row][col] = '0'; }
+        }
+    }
+}
+
+/******************************************************************************
+ *  connect_regions - Joins every floor region to the largest one. Regions are
+ *                    found once, each other region searches outward for the
+ *                    nearest joined cell and a corridor is carved to it
+ *---------------------------------------------------------------------------*/
+fn connect_regions(grid: &mut [Vec<char>], rng: &mut Rng)
+{
+    let mut regions = floor_regions(grid);
+    if regions.len() <= 1 { return; }
+    regions.sort_by_key(|region| std::cmp::Reverse(region.len()));
+
+    let (rows, cols) = (grid.len(), grid[0].len());
+    let mut label = vec![vec![usize::MAX; cols]; rows];
+    for (n, region) in regions.iter().enumerate() {
+        for (row, col) in region { label[*row][*col] = n; }
+    }
+    let mut joined = vec![vec![false; cols]; rows];
+    for (row, col) in &regions[0] { joined[*row][*col] = true; }
+
+    let mut searched = vec![vec![0; cols]; rows];
+    for n in 1..regions.len() {
+        let (first_row, first_col) = regions[n][0];
+        if joined[first_row][first_col] { continue; }
+
+        // Breadth first from every cell of the region, walls included, so the first joined cell is the closest
+        let mut queue: VecDeque<((usize, usize), (usize, usize))> = VecDeque::new();
+        for cell in &regions[n] {
+            searched[cell.0][cell.1] = n;
+            queue.push_back((*cell, *cell));
+        }
+        let mut path = None;
+        while let Some(((r, c), from)) = queue.pop_front() {
+            if joined[r][c] { path = Some((from, (r, c))); break; }
+            let neighbors = [(r.wrapping_sub(1), c), (r + 1, c), (r, c.wrapping_sub(1)), (r, c + 1)];
+            for (nr, nc) in neighbors {
+                if nr < rows && nc < cols && searched[nr][nc] != n {
+                    searched[nr][nc] = n;
+                    queue.push_back(((nr, nc), from));
+                }
+            }
+        }
+
+        let (from, to) = path.unwrap();
+        for (row, col) in carve_corridor(grid, from, to, rng) {
+            joined[row][col] = true;
+            let crossed = label[row][col];
+            if crossed != usize::MAX && crossed != 0 {
+                for (r, c) in &regions[crossed] { joined[*r][*c] = true; }
+                label[row][col] = 0;
+            }
+        }
+        for (row, col) in &regions[n] { joined[*row][*col] = true; }
+    }
+}
+
+/******************************************************************************
+ *  floor_regions - Flood fill, returns the cells of every connected region
+ *---------------------------------------------------------------------------*/
+fn floor_regions(grid: &[Vec<char>]) -> Vec<Vec<(usize, usize)>>
+{
+    let (rows, cols) = (grid.len(), grid[0].len());
+    let mut seen = vec![vec![false; cols]; rows];
+    let mut result = Vec::new();
+    for row in 0..rows {
+        for col in 0..cols {
+            if grid[row][col] != '0' || seen[row][col] { continue; }
+            let mut region = Vec::new();
+            let mut stack = vec![(row, col)];
+            seen[row][col] = true;
+            while let Some((r, c)) = stack.pop() {
+                region.push((r, c));
+                let neighbors = [(r.wrapping_sub(1), c), (r + 1, c), (r, c.wrapping_sub(1)), (r, c + 1)];
+                for (nr, nc) in neighbors {
+                    if nr < rows && nc < cols && grid[nr][nc] == '0' && !seen[nr][nc] {
+                        seen[nr][nc] = true;
+                        stack.push((nr, nc));
+                    }
+                }
+            }
+            result.push(region);
+        }
+    }
+    return result;
+}
+
+/******************************************************************************
+ *  carve_corridor - Carves an L shaped corridor, random elbow direction
+ *  RETURN: The cells of the corridor
+ *---------------------------------------------------------------------------*/
+fn carve_corridor(grid: &mut [Vec<char>], from: (usize, usize), to: (usize, usize), rng: &mut Rng) -> Vec<(usize, usize)>
+{
+    let elbow = if rng.chance(50) { (from.0, to.1) } else { (to.0, from.1) };
+    let mut carved = Vec::new();
+    for (a, b) in [(from, elbow), (elbow, to)] {
+        for (row, cells) in grid.iter_mut().enumerate().take(a.0.max(b.0) + 1).skip(a.0.min(b.0)) {
+            for (col, cell) in cells.iter_mut().enumerate().take(a.1.max(b.1) + 1).skip(a.1.min(b.1)) {
+                *cell = '0';
+                carved.push((row, col));
+            }
+        }
+    }
+    return carved;
+}
+
+fn center(rect: &Rect) -> (usize, usize) { (rect.row + rect.height / 2, rect.col + rect.width / 2) }
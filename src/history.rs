@@ -0,0 +1,498 @@
+/*/////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+ *
+ *      Undo/redo history for ByteDungeon, every edit to a session goes through a Command
+ *
+ *      Implementation and Assumptions
+ *          - The editing bindings build a Command and hand it to GameSession::apply instead of mutating the session
+ *          - Each applied command records the session as it was before, undo swaps it with the current one and the
+ *            swapped out state becomes the redo entry, so every entry holds a single snapshot
+ *          - The undo stack is bounded (oldest entries are dropped first), any new command clears the redo stack
+ *          - Commands applied between begin_group/end_group are undone as a single unit, groups can be nested, a
+ *            command refused inside a group is still rolled back on its own
+ *          - Every entry is a full copy of the session, a limit of 100 on a large session costs 100 copies, the
+ *            server keeps a single entry per room since it has no undo (see server::HISTORY_LIMIT)
+ *          - Logged requests, the event log, the combat log and the dice generator are not part of the snapshots,
+ *            undoing an edit never drops a player's request or rewinds the logs
+ *          - The history itself is never saved or exported, loading a game starts with an empty history
+ *
+ */////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+use serde::{Serialize, Deserialize};
+
+use std::fmt;
+use std::collections::VecDeque;
+
+use crate::{GameSession, Character, Item, Ability, Effect, Request, copy_session};
+use crate::events::EventKind;
+use crate::maps::{Cell, MapLink};
+use crate::generator::{GeneratorOptions, GeneratorError, MAX_CELLS};
+use crate::ascii::{Legend, AsciiError};
+use crate::tiled::{TiledMapping, TiledReport, TiledError};
+use crate::resize::{Anchor, OffBoard, ResizeReport, ResizeError};
+use crate::permissions::{Role, Action, PermissionError};
+use crate::layers::{Visibility, MapNote};
+use crate::combat::ActionRecord;
+use crate::ai::Behaviour;
+
+pub const DEFAULT_LIMIT: usize = 100;
+
+/***********************************************
+ * Command - One undoable edit of a session
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum Command
+{
+    ToggleCell(Cell),
+    PlaceToken { token: char, cell: Cell },
+    Resize { map: String, rows: usize, cols: usize, anchor: Anchor, policy: OffBoard },
+    AddCharacter { token: char, sheet: Character },
+    AddItem(Item),
+    AddAbility(Ability),
+    AddEffect(Effect),
+    GiveItem { token: char, item: String },
+    GiveAbility { token: char, ability: String },
+    AddMap { id: String, rows: usize, cols: usize },
+    RemoveMap(String),
+    LinkCells(MapLink),
+    UnlinkCell(Cell),
+    GenerateMap { map: String, options: GeneratorOptions },
+    ImportAscii { map: String, text: String, legend: Legend },
+    ImportTiled { map: String, json: String, mapping: TiledMapping },
+    Execute(Request),                           // Approved player request, changes the game state
+    SetRole { user: String, role: Option<Role> },   // None removes the user, see permissions.rs
+    Grant { user: String, token: char },
+    Revoke { user: String, token: char },
+    SetAllowed { user: String, action: Action, allowed: bool },
+    SetVisibility { token: char, visibility: Visibility },  // See layers.rs
+    AddNote(MapNote),
+    RemoveNotes(Cell),                          // Every GM note pinned on the cell
+    SetBehaviour { token: char, behaviour: Option<Behaviour> }  // None goes back to the default, see ai.rs
+}
+
+/***********************************************
+ * Applied - What a successful command returned
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum Applied
+{
+    Done,
+    Cell(char),                                 // New content of a toggled cell
+    Executed(ActionRecord),                     // What an executed request did, see combat.rs
+    Resized(ResizeReport),
+    Imported(TiledReport)
+}
+
+/***********************************************
+ * CommandError - Commands that were refused
+ **********************************************/
+#[derive(Clone, Debug, PartialEq)]
+pub enum CommandError
+{
+    Invalid(String),                            // The command doesn't apply to this session (unknown key, bad cell)
+    Resize(ResizeError),
+    Generator(GeneratorError),
+    Ascii(Vec<AsciiError>),
+    Tiled(TiledError),
+    Permission(PermissionError)
+}
+
+/***********************************************
+ * HistoryList - Labels of the undo/redo stacks
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct HistoryList
+{
+    pub undo: Vec<String>,                      // Oldest first, the last label is undone next
+    pub redo: Vec<String>                       // The last label is redone next
+}
+
+/***********************************************
+ * Entry - Labeled snapshot of the other side
+ **********************************************/
+#[derive(Debug)]
+struct Entry
+{
+    label: String,
+    state: Box<GameSession>                     // State before the edit on the undo stack, after it on the redo stack
+}
+
+/***********************************************
+ * Group - Edits being merged into one entry
+ **********************************************/
+#[derive(Debug)]
+struct Group
+{
+    label: String,
+    state: Box<GameSession>,                    // State when the outermost group began
+    depth: usize,
+    changed: bool
+}
+
+/***********************************************
+ * History - Bounded undo/redo stacks
+ **********************************************/
+#[derive(Debug)]
+pub struct History
+{
+    undo: VecDeque<Entry>,
+    redo: Vec<Entry>,
+    group: Option<Group>,
+    limit: usize
+}
+
+impl Default for History
+{
+    fn default() -> History {
+        History { undo: VecDeque::new(), redo: Vec::new(), group: None, limit: DEFAULT_LIMIT }
+    }
+}
+
+/******************************************************************************
+ *  History::Clone - Copies of a session (exports, snapshots) start with an
+ *                   empty history that keeps the same limit
+ *---------------------------------------------------------------------------*/
+impl Clone for History
+{
+    fn clone(&self) -> History {
+        History { limit: self.limit, ..Default::default() }
+    }
+}
+
+impl fmt::Display for CommandError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandError::Invalid(reason) => write!(f, "{}", reason),
+            CommandError::Resize(err) => write!(f, "{}", err),
+            CommandError::Generator(err) => write!(f, "{}", err),
+            CommandError::Ascii(errors) => {
+                let lines: Vec<String> = errors.iter().map(|err| err.to_string()).collect();
+                write!(f, "{}", lines.join("\n"))
+            },
+            CommandError::Tiled(err) => write!(f, "{}", err),
+            CommandError::Permission(err) => write!(f, "{}", err)
+        }
+    }
+}
+
+impl Command
+{
+    /******************************************************************************
+     *  label - Short description shown in the history list
+     *---------------------------------------------------------------------------*/
+    pub fn label(&self) -> String
+    {
+        match self {
+            Command::ToggleCell(cell) => format!("Toggle cell {},{} on {}", cell.row, cell.column, cell.map),
+            Command::PlaceToken { token, cell } => format!("Place {} on {}", token, cell.map),
+            Command::Resize { map, rows, cols, .. } => format!("Resize {} to {}x{}", map, rows, cols),
+            Command::AddCharacter { token, sheet } => format!("Add character {} ({})", sheet.name, token),
+            Command::AddItem(item) => format!("Add item {}", item.name),
+            Command::AddAbility(ability) => format!("Add ability {}", ability.name),
+            Command::AddEffect(effect) => format!("Add effect {}", effect.name),
+            Command::GiveItem { token, item } => format!("Give {} to {}", item, token),
+            Command::GiveAbility { token, ability } => format!("Give {} to {}", ability, token),
+            Command::AddMap { id, .. } => format!("Add map {}", id),
+            Command::RemoveMap(id) => format!("Remove map {}", id),
+            Command::LinkCells(link) => format!("Link {}", link.name),
+            Command::UnlinkCell(cell) => format!("Unlink cell {},{} on {}", cell.row, cell.column, cell.map),
+            Command::GenerateMap { map, .. } => format!("Generate {}", map),
+            Command::ImportAscii { map, .. } => format!("Import text map into {}", map),
+            Command::ImportTiled { map, .. } => format!("Import Tiled map into {}", map),
+            Command::Execute(req) => format!("Execute request of {}", req.caster),
+            Command::SetRole { user, role: Some(role) } => format!("Make {} {}", user, role),
+            Command::SetRole { user, role: None } => format!("Remove {}", user),
+            Command::Grant { user, token } => format!("Give {} control of {}", user, token),
+            Command::Revoke { user, token } => format!("Take {} from {}", token, user),
+            Command::SetAllowed { user, action, allowed: true } => format!("Allow {} to {}", user, action),
+            Command::SetAllowed { user, action, allowed: false } => format!("Forbid {} to {}", user, action),
+            Command::SetVisibility { token, visibility } => format!("Make {} {}", token, visibility),
+            Command::AddNote(note) => format!("Add note on {},{} of {}", note.cell.row, note.cell.column, note.cell.map),
+            Command::RemoveNotes(cell) => format!("Remove notes on {},{} of {}", cell.row, cell.column, cell.map),
+            Command::SetBehaviour { token, behaviour: Some(behaviour) } => format!("Make {} {}", token, behaviour),
+            Command::SetBehaviour { token, behaviour: None } => format!("Clear the behaviour of {}", token)
+        }
+    }
+}
+
+impl GameSession
+{
+    /******************************************************************************
+     *  apply - Runs a command and records it in the history, a refused command
+     *          leaves the session and the history unchanged
+     *---------------------------------------------------------------------------*/
+    pub fn apply(&mut self, command: Command) -> Result<Applied, CommandError>
+    {
+        let before = self.snapshot();
+        let label = command.label();
+        let event = EventKind::Command(Box::new(command.clone()));
+        let result = self.run(command);
+        if result.is_ok() { self.record(event); }
+        match (&result, self.history.group.as_mut()) {
+            (Ok(_), None) => {
+                self.history.redo.clear();
+                self.history.undo.push_back(Entry { label, state: before });
+                if self.history.undo.len() > self.history.limit { self.history.undo.pop_front(); }
+            },
+            (Ok(_), Some(group)) => { group.changed = true; },
+            (Err(_), _) => { self.restore(&before); }
+        }
+        return result;
+    }
+
+    /******************************************************************************
+     *  undo - Reverts the last edit (or group), returns its label
+     *---------------------------------------------------------------------------*/
+    pub fn undo(&mut self) -> Option<String>
+    {
+        self.close_groups();
+        let mut entry = self.history.undo.pop_back()?;
+        entry.state = self.swap_state(&entry.state);
+        let label = entry.label.clone();
+        self.history.redo.push(entry);
+        self.record(EventKind::Undo(label.clone()));
+        return Some(label);
+    }
+
+    /******************************************************************************
+     *  redo - Applies the last undone edit again, returns its label
+     *---------------------------------------------------------------------------*/
+    pub fn redo(&mut self) -> Option<String>
+    {
+        self.close_groups();
+        let mut entry = self.history.redo.pop()?;
+        entry.state = self.swap_state(&entry.state);
+        let label = entry.label.clone();
+        self.history.undo.push_back(entry);
+        self.record(EventKind::Redo(label.clone()));
+        return Some(label);
+    }
+
+    /******************************************************************************
+     *  begin_group - Starts merging the next commands into one undo entry, the
+     *                label of the outermost group is the one kept
+     *---------------------------------------------------------------------------*/
+    pub fn begin_group(&mut self, label: &str)
+    {
+        self.record(EventKind::BeginGroup(label.to_string()));
+        if let Some(group) = self.history.group.as_mut() {
+            group.depth += 1;
+            return;
+        }
+        let state = self.snapshot();
+        self.history.group = Some(Group { label: label.to_string(), state, depth: 1, changed: false });
+    }
+
+    /******************************************************************************
+     *  end_group - Closes a group, the outermost one becomes a single entry if
+     *              any of its commands changed the session
+     *---------------------------------------------------------------------------*/
+    pub fn end_group(&mut self)
+    {
+        self.record(EventKind::EndGroup);
+        self.close_group();
+    }
+
+    /******************************************************************************
+     *  history_list - Returns the labels of the undo and redo stacks
+     *---------------------------------------------------------------------------*/
+    pub fn history_list(&self) -> HistoryList
+    {
+        HistoryList {
+            undo: self.history.undo.iter().map(|entry| entry.label.clone()).collect(),
+            redo: self.history.redo.iter().map(|entry| entry.label.clone()).collect()
+        }
+    }
+
+    /******************************************************************************
+     *  set_history_limit - Changes how many entries undo keeps, at least one
+     *---------------------------------------------------------------------------*/
+    pub fn set_history_limit(&mut self, limit: usize)
+    {
+        self.history.limit = limit.max(1);
+        while self.history.undo.len() > self.history.limit { self.history.undo.pop_front(); }
+    }
+
+    /******************************************************************************
+     *  run - Performs the edit described by a command
+     *---------------------------------------------------------------------------*/
+    fn run(&mut self, command: Command) -> Result<Applied, CommandError>
+    {
+        let invalid = |reason: String| Err(CommandError::Invalid(reason));
+        match command {
+            Command::ToggleCell(cell) => match self.toggle_cell(&cell) {
+                Some(c) => Ok(Applied::Cell(c)),
+                None => invalid(format!("no cell {},{} on map '{}'", cell.row, cell.column, cell.map))
+            },
+            Command::PlaceToken { token, cell } => {
+                if self.place_token(token, &cell) { return Ok(Applied::Done); }
+                invalid(format!("can't place '{}' on cell {},{} of map '{}'", token, cell.row, cell.column, cell.map))
+            },
+            Command::Resize { map, rows, cols, anchor, policy } =>
+                self.resize_map(&map, rows, cols, anchor, policy).map(Applied::Resized).map_err(CommandError::Resize),
+            Command::AddCharacter { token, sheet } => {
+                self.sheets.insert(token, sheet);
+                Ok(Applied::Done)
+            },
+            Command::AddItem(item) => {
+                self.items.insert(item.name.clone(), item);
+                Ok(Applied::Done)
+            },
+            Command::AddAbility(ability) => {
+                self.abilities.insert(ability.name.clone(), ability);
+                Ok(Applied::Done)
+            },
+            Command::AddEffect(effect) => {
+                self.effects.insert(effect.name.clone(), effect);
+                Ok(Applied::Done)
+            },
+            Command::GiveItem { token, item } => {
+                let new_item = match self.items.get(&item) {
+                    Some(new_item) => new_item.clone(),
+                    None => return invalid(format!("no item named '{}'", item))
+                };
+                match self.sheets.get_mut(&token) {
+                    Some(sheet) => sheet.items.push(new_item),
+                    None => return invalid(format!("no unassigned sheet for '{}'", token))
+                }
+                Ok(Applied::Done)
+            },
+            Command::GiveAbility { token, ability } => {
+                if !self.abilities.contains_key(&ability) { return invalid(format!("no ability named '{}'", ability)); }
+                match self.sheets.get_mut(&token) {
+                    Some(sheet) => sheet.abilities.insert(ability),
+                    None => return invalid(format!("no unassigned sheet for '{}'", token))
+                };
+                Ok(Applied::Done)
+            },
+            Command::AddMap { id, rows, cols } => {
+                if rows.saturating_mul(cols) > MAX_CELLS {
+                    return invalid(format!("map of {}x{} is too large, at most {} cells are allowed", rows, cols, MAX_CELLS));
+                }
+                if self.add_map(&id, rows, cols) { return Ok(Applied::Done); }
+                invalid(format!("map id '{}' is empty or already taken", id))
+            },
+            Command::RemoveMap(id) => {
+                if self.remove_map(&id) { return Ok(Applied::Done); }
+                invalid(format!("map '{}' can't be removed", id))
+            },
+            Command::LinkCells(link) => {
+                if self.link_cells(link) { return Ok(Applied::Done); }
+                invalid("both ends of a link must be existing cells".to_string())
+            },
+            Command::UnlinkCell(cell) => {
+                self.unlink_cell(&cell);
+                Ok(Applied::Done)
+            },
+            Command::GenerateMap { map, options } =>
+                self.generate_map(&map, &options).map(|_| Applied::Done).map_err(CommandError::Generator),
+            Command::ImportAscii { map, text, legend } =>
+                self.import_ascii(&map, &text, &legend).map(|_| Applied::Done).map_err(CommandError::Ascii),
+            Command::ImportTiled { map, json, mapping } =>
+                self.import_tiled(&map, &json, &mapping).map(Applied::Imported).map_err(CommandError::Tiled),
+            Command::Execute(req) => self.execute_request(req.caster, &req).map(Applied::Executed),
+            Command::SetRole { user, role } =>
+                self.permissions.set_role(&user, role).map(|_| Applied::Done).map_err(CommandError::Permission),
+            Command::Grant { user, token } => {
+                if !self.characters.contains_key(&token) { return invalid(format!("'{}' is not on a map", token)); }
+                self.permissions.grant(&user, token).map(|_| Applied::Done).map_err(CommandError::Permission)
+            },
+            Command::Revoke { user, token } =>
+                self.permissions.revoke(&user, token).map(|_| Applied::Done).map_err(CommandError::Permission),
+            Command::SetAllowed { user, action, allowed } =>
+                self.permissions.set_allowed(&user, action, allowed).map(|_| Applied::Done).map_err(CommandError::Permission),
+            Command::SetVisibility { token, visibility } => {
+                if self.set_visibility(token, visibility) { return Ok(Applied::Done); }
+                invalid(format!("'{}' is not on a map", token))
+            },
+            Command::AddNote(note) => {
+                if !self.in_bounds(&note.cell) { return invalid(format!("no cell {},{} on map '{}'", note.cell.row, note.cell.column, note.cell.map)); }
+                self.notes.push(note);
+                Ok(Applied::Done)
+            },
+            Command::RemoveNotes(cell) => {
+                if !self.notes.iter().any(|note| note.cell == cell) { return invalid(format!("no note on {},{} of map '{}'", cell.row, cell.column, cell.map)); }
+                self.notes.retain(|note| note.cell != cell);
+                Ok(Applied::Done)
+            },
+            Command::SetBehaviour { token, behaviour } => {
+                if !self.characters.contains_key(&token) { return invalid(format!("'{}' is not on a map", token)); }
+                match behaviour {
+                    Some(behaviour) => self.behaviours.insert(token, behaviour),
+                    None => self.behaviours.remove(&token)
+                };
+                Ok(Applied::Done)
+            }
+        }
+    }
+
+    /******************************************************************************
+     *  close_groups - Ends every open group before moving through the history
+     *---------------------------------------------------------------------------*/
+    fn close_groups(&mut self)
+    {
+        if let Some(group) = self.history.group.as_mut() { group.depth = 1; }
+        self.close_group();
+    }
+
+    /******************************************************************************
+     *  close_group - Leaves one level of grouping, see end_group
+     *---------------------------------------------------------------------------*/
+    fn close_group(&mut self)
+    {
+        let group = match self.history.group.as_mut() {
+            Some(group) => group,
+            None => return
+        };
+        group.depth -= 1;
+        if group.depth > 0 { return; }
+        let group = self.history.group.take().unwrap();
+        if !group.changed { return; }
+        self.history.redo.clear();
+        self.history.undo.push_back(Entry { label: group.label, state: group.state });
+        if self.history.undo.len() > self.history.limit { self.history.undo.pop_front(); }
+    }
+
+    /******************************************************************************
+     *  snapshot - Copy of the session for the history, without requests or log
+     *---------------------------------------------------------------------------*/
+    fn snapshot(&mut self) -> Box<GameSession>
+    {
+        let events = std::mem::take(&mut self.events);
+        let combat_log = std::mem::take(&mut self.combat_log);
+        let state = Box::new(copy_session(self));
+        self.events = events;
+        self.combat_log = combat_log;
+        return state;
+    }
+
+    /******************************************************************************
+     *  swap_state - Replaces the session with STATE, returns the replaced state
+     *---------------------------------------------------------------------------*/
+    fn swap_state(&mut self, state: &GameSession) -> Box<GameSession>
+    {
+        let current = self.snapshot();
+        self.restore(state);
+        return current;
+    }
+
+    /******************************************************************************
+     *  restore - Copies a snapshot into the session, the history, requests, event
+     *            and combat logs, dice generator and version stay as they are
+     *---------------------------------------------------------------------------*/
+    fn restore(&mut self, state: &GameSession)
+    {
+        let history = std::mem::take(&mut self.history);
+        let requests = std::mem::take(&mut self.requests);
+        let events = std::mem::take(&mut self.events);
+        let combat_log = std::mem::take(&mut self.combat_log);
+        let (rng, version) = (self.rng.clone(), self.version);
+        *self = state.clone();
+        self.history = history;
+        self.requests = requests;
+        self.events = events;
+        self.combat_log = combat_log;
+        self.rng = rng;
+        self.version = version;
+    }
+}
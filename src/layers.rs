@@ -0,0 +1,232 @@
+/*/////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+ *
+ *      Hidden tokens and map layers for ByteDungeon, what the DM keeps off the players' board
+ *
+ *      Implementation and Assumptions
+ *          - Each token has a visibility: visible, hidden (only the DM knows it is there, ie: an ambush) or
+ *            invisible (hidden until a detection check beats its DC, the token is then visible to everyone)
+ *          - A detection check is a logged d20 roll plus the DETECT_STAT of the searching token, which must have
+ *            the invisible token in sight (see viewer.rs)
+ *          - Maps are drawn in layers, bottom to top: background (terrain), object (doors, traps, spawns),
+ *            token and GM notes, the GM notes layer holds text the DM pins on cells
+ *          - Players receive player_copy: the game without hidden/invisible tokens (their cell reads as floor),
+ *            GM notes, traps, AI behaviours (see ai.rs), unplaced sheets, requests, the event log and the dice
+ *            generator, tokens a player controls are always kept so an invisible hero still sees themself, combat
+ *            records and executed requests only keep the tokens players see
+ *          - Monsters (tokens no player controls) keep only their health band in player_copy: hitpoints out of
+ *            BAND_MAX_HP, the same redaction views get (see viewer.rs)
+ *          - Hidden tokens still take their cell, a move onto it is refused like any other occupied cell
+ *
+ */////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+use serde::{Serialize, Deserialize};
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{GameSession, Request, copy_session};
+use crate::maps::{Cell, MapObject, ObjectKind};
+use crate::history::Command;
+use crate::rng::Rng;
+use crate::viewer::{sight, HealthBand, BAND_MAX_HP, SIGHT};
+use crate::combat::ActionRecord;
+
+pub const DETECT_STAT: &str = "Wisdom";
+
+/***********************************************
+ * Visibility - Who sees a token
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(ts_rs::TS))]
+pub enum Visibility
+{
+    #[default]
+    Visible,
+    Hidden,                                     // Only the DM sees it until it is revealed
+    Invisible { dc: i32 }                       // Hidden until a detection check reaches DC
+}
+
+/***********************************************
+ * Layer - Drawing layers of a map, bottom first
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Layer
+{
+    Background,                                 // Terrain objects
+    Object,                                     // Doors, traps and spawn points
+    Token,
+    GmNotes                                     // DM only
+}
+
+/***********************************************
+ * MapNote - Text the DM pins on a cell
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(ts_rs::TS))]
+pub struct MapNote
+{
+    pub cell: Cell,
+    pub text: String
+}
+
+/***********************************************
+ * LayerEntry - One thing drawn on a layer
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum LayerEntry
+{
+    Object(MapObject),
+    Token { token: char, cell: Cell, visibility: Visibility },
+    Note(MapNote)
+}
+
+impl FromStr for Layer
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Layer, String> {
+        match s {
+            "background" => Ok(Layer::Background),
+            "object" => Ok(Layer::Object),
+            "token" => Ok(Layer::Token),
+            "gm-notes" => Ok(Layer::GmNotes),
+            _ => Err(format!("no layer named '{}'", s))
+        }
+    }
+}
+
+impl fmt::Display for Visibility
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Visibility::Visible => write!(f, "visible"),
+            Visibility::Hidden => write!(f, "hidden"),
+            Visibility::Invisible { dc } => write!(f, "invisible (DC {})", dc)
+        }
+    }
+}
+
+impl GameSession
+{
+    /******************************************************************************
+     *  seen_by_players - True if players may know where TOKEN is
+     *---------------------------------------------------------------------------*/
+    pub fn seen_by_players(&self, token: char) -> bool
+    {
+        match self.characters.get(&token) {
+            Some(tok) => tok.visibility == Visibility::Visible || !self.permissions.controllers_of(token).is_empty(),
+            None => false
+        }
+    }
+
+    /******************************************************************************
+     *  set_visibility - Changes who sees a placed token
+     *---------------------------------------------------------------------------*/
+    pub fn set_visibility(&mut self, token: char, visibility: Visibility) -> bool
+    {
+        match self.characters.get_mut(&token) {
+            Some(tok) => { tok.visibility = visibility; true },
+            None => false
+        }
+    }
+
+    /******************************************************************************
+     *  detect - Rolls a detection check of BY against an invisible TOKEN, a
+     *           success makes TOKEN visible
+     *
+     *  RETURN: True if the check succeeded, why it can't be rolled otherwise
+     *---------------------------------------------------------------------------*/
+    pub fn detect(&mut self, token: char, by: char) -> Result<bool, String>
+    {
+        let target = self.characters.get(&token).ok_or_else(|| format!("'{}' is not on a map", token))?;
+        let dc = match target.visibility {
+            Visibility::Invisible { dc } => dc,
+            _ => return Err(format!("'{}' isn't invisible", token))
+        };
+        let searcher = self.characters.get(&by).ok_or_else(|| format!("'{}' is not on a map", by))?;
+        let in_sight = searcher.map == target.map
+            && sight(self.board(&searcher.map).unwrap(), searcher.row, searcher.column, SIGHT).contains(&(target.row, target.column));
+        if !in_sight { return Err(format!("'{}' is out of sight of {}", token, by)); }
+
+        let modifier = searcher.sheet.stats.get(DETECT_STAT).cloned().unwrap_or(0) as i32;
+        let found = self.roll(1, 20) + modifier >= dc;
+        if found { self.apply(Command::SetVisibility { token, visibility: Visibility::Visible }).map_err(|err| err.to_string())?; }
+        return Ok(found);
+    }
+
+    /******************************************************************************
+     *  layer - What is drawn on one layer of a map
+     *---------------------------------------------------------------------------*/
+    pub fn layer(&self, map: &str, layer: Layer) -> Vec<LayerEntry>
+    {
+        match layer {
+            Layer::Background | Layer::Object => self.objects_on(map).into_iter()
+                .filter(|obj| matches!(obj.kind, ObjectKind::Terrain(_)) == (layer == Layer::Background))
+                .map(|obj| LayerEntry::Object(obj.clone())).collect(),
+            Layer::Token => {
+                let mut keys: Vec<&char> = self.characters.iter().filter(|(_, tok)| tok.map == map).map(|(key, _)| key).collect();
+                keys.sort();
+                keys.into_iter().map(|key| {
+                    let tok = &self.characters[key];
+                    LayerEntry::Token { token: *key, cell: Cell::new(map, tok.row, tok.column), visibility: tok.visibility.clone() }
+                }).collect()
+            },
+            Layer::GmNotes => self.notes.iter().filter(|note| note.cell.map == map).map(|note| LayerEntry::Note(note.clone())).collect()
+        }
+    }
+
+    /******************************************************************************
+     *  player_copy - The session as players may receive it, see header
+     *---------------------------------------------------------------------------*/
+    pub fn player_copy(&self) -> GameSession
+    {
+        let mut copy = copy_session(self);
+        copy.sheets.clear();
+        copy.events.clear();
+        copy.notes.clear();
+        copy.behaviours.clear();
+        copy.rng = Rng::default();
+        copy.permissions.withheld.clear();
+        copy.objects.retain(|object| object.kind != ObjectKind::Trap);
+        for (key, token) in copy.characters.iter_mut() {
+            if !self.permissions.controllers_of(*key).is_empty() { continue; }
+            token.sheet.hitpoints = HealthBand::of(token.sheet.hitpoints, token.sheet.max_hp).hitpoints();
+            token.sheet.max_hp = BAND_MAX_HP;
+        }
+        let hidden: Vec<char> = self.characters.keys().filter(|key| !self.seen_by_players(**key)).cloned().collect();
+        for key in hidden {
+            let token = copy.characters.remove(&key).unwrap();
+            if let Some(grid) = copy.board_mut(&token.map) { grid[token.row][token.column] = '0'; }
+        }
+        copy.combat_log = self.combat_log.iter().filter_map(|record| self.record_for_players(record)).collect();
+        return copy;
+    }
+
+    /******************************************************************************
+     *  record_for_players - A combat record without the tokens players don't see,
+     *                       None if they don't see its actor
+     *---------------------------------------------------------------------------*/
+    pub fn record_for_players(&self, record: &ActionRecord) -> Option<ActionRecord>
+    {
+        if !self.seen_by_players(record.actor) { return None; }
+        let mut record = record.clone();
+        record.targets.retain(|target| self.seen_by_players(*target));
+        record.changes.retain(|change| self.seen_by_players(change.token));
+        return Some(record);
+    }
+
+    /******************************************************************************
+     *  request_for_players - A request without the targets players don't see, and
+     *                        without its cell unless it is a move (the caster is
+     *                        seen on it anyway), None if they don't see its caster
+     *---------------------------------------------------------------------------*/
+    pub fn request_for_players(&self, request: &Request) -> Option<Request>
+    {
+        if !self.seen_by_players(request.caster) { return None; }
+        let mut request = request.clone();
+        if let Some(targets) = request.target_tokens.as_mut() { targets.retain(|target| self.seen_by_players(*target)); }
+        if request.action_type != 0 { request.target_cell = None; }
+        return Some(request);
+    }
+}
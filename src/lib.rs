@@ -11,25 +11,89 @@
  *          - Each character must be represented using a unique character except for '1' and '0'
  *          - Diagonal movements are 2 separate movements (ie up + left, down + right)
  *          - Each player's actions are logged and sent to the DM to await approval before execution
+ *          - A session can hold several named maps (floors, areas) linked by stairs/portals, see maps.rs
+ *          - Edits from the JS wrapper go through undoable commands, see history.rs
+ *          - Everything that changes a session is kept in an event log that can be replayed, see events.rs
+ *          - Clients are kept in sync with versioned deltas instead of whole exports, see sync.rs
+ *          - Sets are serialized sorted so the same state always gives the same JSON and hash, see checksum.rs
+ *          - Saved games carry a format version and are upgraded when loaded, see migrate.rs
+ *          - Games can also be saved as compact (compressed) binary, see binary.rs
+ *          - Sessions can also be played locally in a terminal, see tui.rs
+ *          - Online games run on an authoritative server that holds the session, see server.rs and protocol.rs
+ *          - Who may act for which token is part of the session, see permissions.rs
+ *          - Players and spectators can be sent a redacted view instead of the whole game, see viewer.rs
+ *          - Tokens can be hidden from players and the DM can pin notes on a GM-only layer, see layers.rs
+ *          - Conflicts between the requests of a turn are settled before they are replayed, see resolve.rs
+ *          - Executed requests return what they did and are kept in a combat log, see combat.rs
+ *          - Chat lines are parsed here, commands like /roll and /w and inline [[1d8]] rolls, see chat.rs
+ *          - Encounters can be played out many times natively to see how deadly they are, see sim.rs
+ *          - The DM can have the AI propose requests for the tokens no player controls, see ai.rs
  * 
  */////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
 
 mod utils;
+pub mod maps;
+pub mod rng;
+pub mod generator;
+pub mod ascii;
+pub mod tiled;
+pub mod resize;
+pub mod history;
+pub mod events;
+pub mod sync;
+pub mod checksum;
+pub mod migrate;
+pub mod binary;
+pub mod validate;
+pub mod protocol;
+pub mod permissions;
+pub mod viewer;
+pub mod layers;
+pub mod resolve;
+pub mod combat;
+pub mod chat;
+pub mod ai;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod tui;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod server;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod storage;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod sim;
 
 use wasm_bindgen::prelude::*;
 use serde::{Serialize, Deserialize};
+use maps::{Cell, MapLink, MapObject, map_key};
+use generator::GeneratorOptions;
+use ascii::Legend;
+use tiled::TiledMapping;
+use resize::{Anchor, OffBoard};
+use history::{Command, Applied, CommandError, History};
+use events::Event;
+use rng::Rng;
+use protocol::{ClientMessage, ServerMessage};
+use permissions::{Permissions, Role, Action};
+use layers::{Visibility, Layer, MapNote};
+use resolve::Policy;
+use combat::ActionRecord;
+use ai::Behaviour;
 
 use std::fmt;
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::collections::HashSet;
 
+pub const ROLL_TO_SUCCEED: i32 = 14;            // A d20 roll of at least this succeeds, on the server and in simulations
+
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global allocator.
 #[cfg(feature = "wee_alloc")]
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
 thread_local!(static GLOBAL_SESSION: RefCell<GameSession> = RefCell::new(Default::default()));
+thread_local!(static SYNCED_SESSION: RefCell<Option<GameSession>> = const { RefCell::new(None) });   // Last state sent by export_delta
 
 #[wasm_bindgen]
 extern {
@@ -38,12 +102,22 @@ extern {
     fn alert(s: &str);
 }
 
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+extern {
+    #[wasm_bindgen(js_namespace = Math, js_name = random)]
+    fn math_random() -> f64;
+    #[wasm_bindgen(js_namespace = Date, js_name = now)]
+    fn date_now() -> f64;
+}
+
 ////////////////////////////////////////////////    STRUCTS    ////////////////////////////////////////////////////////////////
 
 /***********************************************
  * Character - Essential character sheet info
  **********************************************/
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(ts_rs::TS))]
 pub struct Character    
 {                                               // stats, numerical stats of a character like attributes, initiative
     name: String,                               // traits, (practically) permanent qualities of a character, race, class...
@@ -51,32 +125,47 @@ pub struct Character
     initiative: i8,
     hitpoints: i32,
     max_hp: i32,
+    #[serde(default)]
     stats: HashMap<String, i16>,                // DND equivalent are attributes. These are stats like strength, intelligence
+    #[serde(default)]
+    #[serde(serialize_with = "checksum::sorted_set")]
     traits: HashSet<String>,                    // Permanent qualities of a character (ie: languages, material they're made of)
+    #[serde(default)]
     items: Vec<Item>,                           // Vector of items instead of another map because each player item changes
+    #[serde(default)]
     equipment: HashMap<String, Item>,           // Hashmap to limit one item per slot (ie: "head" -> Item:helmet)
+    #[serde(default)]
+    #[serde(serialize_with = "checksum::sorted_set")]
     abilities: HashSet<String>,                 // Collection of keys to look up which abilities this character can use
+    #[serde(default)]
     effects: HashMap<String, Effect>            // Temporary qualities of a character (ie: poisoned, stunned)
 }
 
 /***********************************************
  * Item - Consumables and equipment data
  **********************************************/
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(ts_rs::TS))]
 pub struct Item               
 {
     name: String,
     uses: i32,
     weight: u16,
+    #[serde(default)]
     slots: Vec<String>,                         // Key that represents equipment slot this item takes up, like "head"
+    #[serde(default)]
+    #[serde(serialize_with = "checksum::sorted_set")]
     effects: HashSet<String>,                   // Effect this item applies when consumed / used
+    #[serde(default)]
+    #[serde(serialize_with = "checksum::sorted_set")]
     abilities: HashSet<String>                  // Abilities this item grants the consumer when used, only for equippables
 }
 
 /***********************************************
  * Ability - Special actions (spell/attack/etc)
  **********************************************/
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(ts_rs::TS))]
 pub struct Ability
 {
     name: String,
@@ -84,15 +173,21 @@ pub struct Ability
     action_points: i8,                          // Action point cost to cast this ability
     casting_roll: [i32; 2],                     // Lower bound, upper bound
     stat_modifier: Option<String>,              // Identifier of the stat being affected
+    #[serde(default)]
     requirements: Vec<Vec<String>>,             // At least one of these vecs must be completely true to use ability
+    #[serde(default)]
+    #[serde(serialize_with = "checksum::sorted_set")]
     target_effects: HashSet<String>,            // Effect to be applied on target upon success
+    #[serde(default)]
+    #[serde(serialize_with = "checksum::sorted_set")]
     caster_effects: HashSet<String>             // Effect to be applied on caster upon success
 }
 
 /***********************************************
  * Effect - Temporary stat modifier (de)buff
  **********************************************/
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(ts_rs::TS))]
 pub struct Effect
 {
     name: String,                               
@@ -105,19 +200,25 @@ pub struct Effect
 /***********************************************
  * Token - Stores position and sheet for tokens
  **********************************************/
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(ts_rs::TS))]
 pub struct Token      
 {
     row: usize,
     column: usize,
+    #[serde(default = "maps::default_map")]
+    map: String,                                // Id of the map the token is on, see maps::MAIN_MAP
     initiative: Option<i8>,                     // Used to sort requests by turn order
-    sheet: Character                            // Character sheet associated with this token
+    sheet: Character,                           // Character sheet associated with this token
+    #[serde(default)]
+    visibility: Visibility                      // Who sees the token, see layers.rs
 }
 
 /***********************************************
  * MasterList - Stores game rules and characters
  **********************************************/
-#[derive(Serialize, Deserialize, Default, Debug)]
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(default)]
 pub struct GameSession
 {
     pub characters: HashMap<char, Token>,       // Map of all characters in a game
@@ -125,21 +226,41 @@ pub struct GameSession
     pub abilities: HashMap<String, Ability>,    // Map of all abilities in a game
     pub effects: HashMap<String, Effect>,       // Map of all ...
     pub items: HashMap<String, Item>,           
-    pub grid: Vec<Vec<char>>,                   // 2D array representing the board
-    pub requests: Vec<(char, Vec<Request>)>     // Vector of token to set of requests in order
+    pub grid: Vec<Vec<char>>,                   // 2D array representing the board (the main map)
+    pub maps: HashMap<String, Vec<Vec<char>>>,  // Additional named maps (floors, separate areas)
+    pub links: Vec<MapLink>,                    // Stairs and portals connecting cells between maps
+    pub objects: Vec<MapObject>,                // Doors, traps and spawn points laid over floor cells
+    pub permissions: Permissions,               // Users, roles and who controls each token, see permissions.rs
+    pub notes: Vec<MapNote>,                    // GM notes layer, never sent to players
+    pub behaviours: BTreeMap<char, Behaviour>,  // AI of the tokens the DM plays, never sent to players, see ai.rs
+    pub requests: Vec<(char, Vec<Request>)>,    // Vector of token to set of requests in order
+    pub events: Vec<Event>,                     // Append-only log of every change, see events.rs
+    pub base_seq: u64,                          // Seq of the first event, the ones before were compacted away (see storage.rs)
+    pub combat_log: Vec<ActionRecord>,          // Outcome of every executed request, see combat.rs
+    pub rng: Rng,                               // Generator for dice rolls, its state is logged with each roll
+    pub version: u64,                           // Bumped by every event that changes synced state, see sync.rs
+    #[serde(skip)]
+    pub history: History                        // Undo/redo stacks, never saved
 }
 
 /***********************************************
  * Request - Player's requested action for DM
  **********************************************/
 #[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(ts_rs::TS))]
 pub struct Request
 {
     caster: char,                               // Char representation of the casting token
     action_type: i32,                           // Type of action (0 - move, 1 - use item, 2 - use ability, 3 - remove equipped)
     subtype_key: Option<String>,                // How the action is done (ability, equipment)
     target_cell: Option<(usize, usize)>,        // Target coordinates, if null apply on self
-    target_tokens: Option<Vec<char>>            // Target tokens of action
+    target_tokens: Option<Vec<char>>,           // Target tokens of action
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(not(target_arch = "wasm32"), ts(optional))]
+    user: Option<String>,                       // User that made the request, None for the local session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(not(target_arch = "wasm32"), ts(optional))]
+    rolled_at: Option<u64>                      // Seq of the d20 rolled for the request, see combat.rs
 }
 
 
@@ -148,14 +269,19 @@ pub struct Request
 /******************************************************************************
  *  load_game - Loads a previously exported game to the current session
  *                                                                             
- *  PARAMS: DATA contains the serialized (JSON) game data to be loaded in
+ *  PARAMS: DATA contains the serialized (JSON) game data to be loaded in, saves
+ *          of an older format are upgraded first, see migrate.rs. Games whose
+ *          dice were never seeded get a fresh seed
+ *  RETURN: Names of the migrations that were applied, throws if DATA is invalid
  *---------------------------------------------------------------------------*/
 #[wasm_bindgen]
-pub fn load_game(data: JsValue) {
+pub fn load_game(data: JsValue) -> Result<JsValue, JsValue> {
+    let (mut my_game, applied) = read_game(data)?;
+    seed_dice(&mut my_game);
     GLOBAL_SESSION.with(|session| {
-        let my_game: GameSession = data.into_serde().unwrap();
         *session.borrow_mut() = my_game;
     });
+    return Ok(JsValue::from_serde(&applied).unwrap());
 }
 
 /******************************************************************************
@@ -168,19 +294,84 @@ pub fn export_game() -> JsValue
 {
     let mut result = None;
     GLOBAL_SESSION.with(|session| {
-        result = Some(JsValue::from_serde(&copy_session(&*session.borrow())).unwrap());
+        result = Some(JsValue::from_serde(&copy_session(&*session.borrow()).to_save()).unwrap());
     });
     return result.unwrap();
 }
 
 /******************************************************************************
- *  reset_session - Resets current game session data to default values
+ *  export_for_viewer - Exports what a user with ROLE may see of the session
+ *
+ *  PARAMS: ROLE is "host", "co-dm", "player" or "spectator"
+ *  RETURN: The whole game for the host and co-DMs, a redacted view that can't
+ *          be loaded back as a game otherwise, see viewer.rs
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn export_for_viewer(role: String) -> Result<JsValue, JsValue>
+{
+    let role = role.parse::<Role>().map_err(|err| JsValue::from_str(&err.to_string()))?;
+    let mut result = JsValue::default();
+    GLOBAL_SESSION.with(|session| {
+        result = JsValue::from_serde(&session.borrow().export_for_viewer(role)).unwrap();
+    });
+    return Ok(result);
+}
+
+/******************************************************************************
+ *  export_player_copy - Exports the session as players may receive it, a game
+ *                       without hidden tokens, GM notes or traps that can be
+ *                       loaded back, see layers.rs
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn export_player_copy() -> JsValue
+{
+    GLOBAL_SESSION.with(|session| JsValue::from_serde(&session.borrow().player_copy().to_save()).unwrap())
+}
+
+/******************************************************************************
+ *  export_game_binary - Exports the current game session as a binary save
+ *
+ *  PARAMS: COMPRESS deflates the save, true by default
+ *  RETURN: Uint8Array with the same content as export_game
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn export_game_binary(compress: Option<bool>) -> Vec<u8>
+{
+    let mut result = Vec::new();
+    GLOBAL_SESSION.with(|session| {
+        result = binary::encode_game(&copy_session(&session.borrow()), compress.unwrap_or(true));
+    });
+    return result;
+}
+
+/******************************************************************************
+ *  load_game_binary - Loads a game saved by export_game_binary
+ *
+ *  RETURN: Names of the migrations that were applied, see load_game
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn load_game_binary(data: &[u8]) -> Result<JsValue, JsValue>
+{
+    let (mut my_game, applied) = binary::decode_game(data).map_err(|err| JsValue::from_str(&err.to_string()))?;
+    seed_dice(&mut my_game);
+    GLOBAL_SESSION.with(|session| {
+        *session.borrow_mut() = my_game;
+    });
+    return Ok(JsValue::from_serde(&applied).unwrap());
+}
+
+/******************************************************************************
+ *  reset_session - Resets current game session data to default values with
+ *                  freshly seeded dice, also installs the panic hook since the
+ *                  web client calls it first
  *---------------------------------------------------------------------------*/
 #[wasm_bindgen]
 pub fn reset_session()
 {
+    utils::set_panic_hook();
     GLOBAL_SESSION.with(|session| {
-        let my_game: GameSession = Default::default();
+        let mut my_game: GameSession = Default::default();
+        seed_dice(&mut my_game);
         *session.borrow_mut() = my_game;
     });
 }
@@ -189,11 +380,11 @@ pub fn reset_session()
  *  get_char - Returns the char at the input row and column of the game grid
  *---------------------------------------------------------------------------*/
 #[wasm_bindgen]
-pub fn get_char(row: i32, col: i32) -> JsValue
+pub fn get_char(row: i32, col: i32, map: Option<String>) -> JsValue
 {
     let mut result = ' ';
     GLOBAL_SESSION.with(|session| {
-        result = session.borrow().grid[row as usize][col as usize];
+        result = session.borrow().board(map_key(&map)).unwrap()[row as usize][col as usize];
     });
     return JsValue::from_serde(&result).unwrap();
 }
@@ -202,12 +393,13 @@ pub fn get_char(row: i32, col: i32) -> JsValue
  *  find_character - Returns character sheet of the char at input row/column
  *---------------------------------------------------------------------------*/
 #[wasm_bindgen]
-pub fn find_character(row: i32, col: i32) -> JsValue
+pub fn find_character(row: i32, col: i32, map: Option<String>) -> JsValue
 {
     let mut result = None;
     GLOBAL_SESSION.with(|session| {
         let game = &*session.borrow();
-        result = Some(JsValue::from_serde(game.characters.get(&game.grid[row as usize][col as usize]).unwrap()));
+        let grid = game.board(map_key(&map)).unwrap();
+        result = Some(JsValue::from_serde(game.characters.get(&grid[row as usize][col as usize]).unwrap()));
     });
     return result.unwrap().unwrap();
 }
@@ -230,11 +422,12 @@ pub fn get_character(key: char) -> JsValue
  *  get_dimensions - Returns the row/column dimensions of the current game grid
  *---------------------------------------------------------------------------*/
 #[wasm_bindgen]
-pub fn get_dimensions() -> JsValue {
+pub fn get_dimensions(map: Option<String>) -> JsValue {
     let mut res = [0,0];
     GLOBAL_SESSION.with(|session| {
-        if session.borrow().grid.len() > 0 
-            { res = [session.borrow().grid.len(), session.borrow().grid[0].len()] }
+        if let Some(grid) = session.borrow().board(map_key(&map)) {
+            if !grid.is_empty() { res = [grid.len(), grid[0].len()] }
+        }
     });
     return JsValue::from_serde(&res).unwrap();
 }
@@ -243,14 +436,14 @@ pub fn get_dimensions() -> JsValue {
  *  board_to_string - Returns the current game grid in flattened string format
  *---------------------------------------------------------------------------*/
 #[wasm_bindgen]
-pub fn board_to_string() -> JsValue
+pub fn board_to_string(map: Option<String>) -> JsValue
 {
-    let mut result = Vec::new();
+    let mut result: Vec<char> = Vec::new();
     GLOBAL_SESSION.with(|session| {
         let game = &*session.borrow();
-        for n in 0..game.grid.len() {
-            for m in 0..game.grid[0].len() {
-                result.push(game.grid[n][m]);
+        if let Some(grid) = game.board(map_key(&map)) {
+            for row in grid {
+                result.extend(row.iter());
             }
         }
     });
@@ -278,9 +471,7 @@ pub fn add_character(tk: String, nm: String, sp: i32, iv: i8, hp: i32, mp: i32,
     temp_char.stats.insert("Charisma".to_string(), ch);
 
     let str_to_chars: Vec<char> = tk.chars().collect();
-    GLOBAL_SESSION.with(|session| {
-        session.borrow_mut().sheets.insert(str_to_chars[0], temp_char);
-    });
+    apply_command(Command::AddCharacter { token: str_to_chars[0], sheet: temp_char }).unwrap();
 }
 
 /******************************************************************************
@@ -295,9 +486,7 @@ pub fn add_item(name: String, uses: i32, wgt: u16, slot: Option<String>, effx: O
     if slot.is_some() { temp_item.slots.push(slot.unwrap()); }
     if effx.is_some() { temp_item.effects.insert(effx.unwrap()); }
     if abil.is_some() { temp_item.abilities.insert(abil.unwrap()); } 
-    GLOBAL_SESSION.with(|session| {
-        session.borrow_mut().items.insert(name.to_string(), temp_item);
-    });
+    apply_command(Command::AddItem(temp_item)).unwrap();
 }
 
 /******************************************************************************
@@ -313,9 +502,7 @@ pub fn add_ability(nm: String, ran: i16, ap: i8, low: i32, high: i32, stat: Opti
     if tar.is_some() { temp_abi.target_effects.insert(tar.unwrap()); }
     if cas.is_some() { temp_abi.caster_effects.insert(cas.unwrap()); } 
     if stat.is_some() { temp_abi.stat_modifier = stat; } 
-    GLOBAL_SESSION.with(|session| {
-        session.borrow_mut().abilities.insert(nm.to_string(), temp_abi);
-    });
+    apply_command(Command::AddAbility(temp_abi)).unwrap();
 }
 
 /******************************************************************************
@@ -323,10 +510,8 @@ pub fn add_ability(nm: String, ran: i16, ap: i8, low: i32, high: i32, stat: Opti
  *---------------------------------------------------------------------------*/
 #[wasm_bindgen]
 pub fn add_effect(nm: String, dur: i32, target: String, low: i32, high: i32, temp: bool ) {
-    let temp_eff = Effect { name: nm.clone(), duration: dur, target_stat: target, modifier: [low, high], temporary: temp};
-    GLOBAL_SESSION.with(|session| {
-        session.borrow_mut().effects.insert(nm.clone(), temp_eff);
-    });
+    let temp_eff = Effect { name: nm, duration: dur, target_stat: target, modifier: [low, high], temporary: temp};
+    apply_command(Command::AddEffect(temp_eff)).unwrap();
 }
 
 /******************************************************************************
@@ -366,7 +551,7 @@ pub fn get_requests() -> JsValue {
 pub fn insert_request(token: char, request: JsValue) {
     GLOBAL_SESSION.with(|session| {
         let reqs: Vec<Request> = request.into_serde().unwrap();
-        session.borrow_mut().requests.push((token, reqs));
+        session.borrow_mut().log_request(token, reqs);
     });
 }
 
@@ -381,77 +566,86 @@ pub fn sort_requests() {
 }
 
 /******************************************************************************
- *  execute_request - Executes the request entered as the parameter
+ *  resolve_requests - Settles the conflicts between the logged requests, sort
+ *                     them first, the logged requests are left as they are
+ *
+ *  PARAMS: POLICY is "initiative-wins" (default), "bump" or "cancel"
+ *  RETURN: Outcome of each request, with the request to execute if it wasn't
+ *          cancelled, see resolve.rs
  *---------------------------------------------------------------------------*/
 #[wasm_bindgen]
-pub fn execute_request(request: JsValue) {
-    GLOBAL_SESSION.with(|session| {
-        let req: Request = request.into_serde().unwrap();
-        session.borrow_mut().execute_request(req.caster, &req);
-    });
+pub fn resolve_requests(policy: Option<String>) -> Result<JsValue, JsValue> {
+    let policy = match policy { Some(policy) => policy.parse().map_err(|err: String| JsValue::from_str(&err))?, None => Policy::default() };
+    GLOBAL_SESSION.with(|session| Ok(JsValue::from_serde(&session.borrow().resolve_requests(policy)).unwrap()))
 }
 
 /******************************************************************************
- *  resize_board - Changes the row/column dimensions of the current game grid
+ *  execute_request - Executes the request entered as the parameter, once
+ *                    check_request let it through
+ *
+ *  RETURN: What the request did (see combat.rs), throws the reason it was
+ *          refused
  *---------------------------------------------------------------------------*/
 #[wasm_bindgen]
-pub fn resize_board(rows: i32, cols: i32) 
-{
-    GLOBAL_SESSION.with(|session| { 
-        let mut my_game = copy_session(&*session.borrow());
-        if rows == 0 ||  cols == 0 {
-            my_game.grid = vec![Vec::new()];
-            *session.borrow_mut() = my_game;
-            return;
-        }
+pub fn execute_request(request: JsValue) -> Result<JsValue, JsValue> {
+    let req: Request = request.into_serde().map_err(|err| JsValue::from_str(&err.to_string()))?;
+    GLOBAL_SESSION.with(|session| session.borrow().check_request(&req)).map_err(|reason| JsValue::from_str(&reason))?;
+    return match apply_command(Command::Execute(req)).map_err(error_to_js)? {
+        Applied::Executed(record) => Ok(JsValue::from_serde(&record).unwrap()),
+        _ => Ok(JsValue::NULL)
+    };
+}
 
-        if rows as usize > my_game.grid.len() {
-            while my_game.grid.len() < rows as usize {
-                my_game.grid.push(vec!['0']);
-            }
-        }
-        else {
-            while my_game.grid.len() > rows as usize {
-                my_game.grid.pop();
-            }
-        }
+/******************************************************************************
+ *  get_combat_log - Records of every executed request, oldest first, for
+ *                   analysis, see combat.rs
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn get_combat_log() -> JsValue {
+    GLOBAL_SESSION.with(|session| JsValue::from_serde(&session.borrow().combat_log).unwrap())
+}
 
-        for i in 0..my_game.grid.len() {
-            while my_game.grid[i].len() < cols as usize{
-                my_game.grid[i].push('0');
-            }
-            while my_game.grid[i].len() > cols as usize{
-                my_game.grid[i].pop();
-            }
-        }
-        *session.borrow_mut() = my_game;
-        return;
-    });
+/******************************************************************************
+ *  get_combat_lines - The combat log as readable lines for the chat panel
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn get_combat_lines() -> JsValue {
+    GLOBAL_SESSION.with(|session| JsValue::from_serde(&session.borrow().combat_lines()).unwrap())
+}
+
+/******************************************************************************
+ *  resize_board - Changes the row/column dimensions of the current game grid
+ *
+ *  PARAMS: ANCHOR is the fixed edge ("top-left" default, "top", "center", ...),
+ *          POLICY handles tokens falling off ("reject", "nearest", "return")
+ *  RETURN: Resize report with the tokens moved or sent back to sheets
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn resize_board(rows: i32, cols: i32, map: Option<String>, anchor: Option<String>, policy: Option<String>) 
+    -> Result<JsValue, JsValue>
+{
+    let to_js = |err: resize::ResizeError| JsValue::from_str(&err.to_string());
+    let anchor = match anchor { Some(anchor) => anchor.parse().map_err(to_js)?, None => Anchor::default() };
+    let policy = match policy { Some(policy) => policy.parse().map_err(to_js)?, None => OffBoard::default() };
+    let command = Command::Resize { map: map_key(&map).to_string(), rows: rows.max(0) as usize, cols: cols.max(0) as usize, 
+        anchor, policy };
+    return match apply_command(command).map_err(error_to_js)? {
+        Applied::Resized(report) => Ok(JsValue::from_serde(&report).unwrap()),
+        _ => Ok(JsValue::NULL)
+    };
 }
 
 /******************************************************************************
  *  toggle_cell - Alternates the content of the cell at the input row/column
  *---------------------------------------------------------------------------*/
 #[wasm_bindgen]
-pub fn toggle_cell(row: i32, col: i32) -> JsValue
+pub fn toggle_cell(row: i32, col: i32, map: Option<String>) -> JsValue
 {
     let mut result: Option<char> = None;
-    GLOBAL_SESSION.with(|session| {
-        let mut my_game: GameSession = copy_session(&*session.borrow());
-        if my_game.grid[row as usize][col as usize] == '0' { 
-            my_game.grid[row as usize][col as usize] = '1';
-            result = Some('1');
-        }
-        else { 
-            if my_game.grid[row as usize][col as usize] != '1' {
-                let token = my_game.characters.remove(&my_game.grid[row as usize][col as usize]).unwrap();
-                my_game.sheets.insert(my_game.grid[row as usize][col as usize], token.sheet);
-            }
-            my_game.grid[row as usize][col as usize] = '0';
-            result = Some('0');
-        }
-        *session.borrow_mut() = my_game;
-    });
+    if row >= 0 && col >= 0 {
+        if let Ok(Applied::Cell(c)) = apply_command(Command::ToggleCell(Cell::new(map_key(&map), row as usize, col as usize))) 
+            { result = Some(c); }
+    }
     return JsValue::from_serde(&result).unwrap();
 }
 
@@ -459,15 +653,12 @@ pub fn toggle_cell(row: i32, col: i32) -> JsValue
  *  place_token - Places a char representation of a token at the input row/col
  *---------------------------------------------------------------------------*/
 #[wasm_bindgen]
-pub fn place_token(token: String, row: i32, col: i32)
+pub fn place_token(token: String, row: i32, col: i32, map: Option<String>) -> Result<(), JsValue>
 {
     let str_to_chars: Vec<char> = token.chars().collect();
-    GLOBAL_SESSION.with(|session| {
-        let sheet = session.borrow_mut().sheets.remove(&str_to_chars[0]).unwrap();
-        let token = Token { row: row as usize, column: col as usize, initiative: Some(sheet.initiative), sheet: sheet.clone() };
-        session.borrow_mut().grid[row as usize][col as usize] = str_to_chars[0];
-        session.borrow_mut().characters.insert(str_to_chars[0], token);
-    });
+    if str_to_chars.is_empty() || row < 0 || col < 0 { return Err(JsValue::from_str("invalid token or cell")); }
+    let cell = Cell::new(map_key(&map), row as usize, col as usize);
+    apply_command(Command::PlaceToken { token: str_to_chars[0], cell }).map(|_| ()).map_err(error_to_js)
 }
 
 /******************************************************************************
@@ -478,12 +669,13 @@ pub fn place_token(token: String, row: i32, col: i32)
  * 
  ******************************************************************************/
 #[wasm_bindgen]
-pub fn collect_cell_options(row: i32, column: i32, range: i32, target: bool) -> JsValue
+pub fn collect_cell_options(row: i32, column: i32, range: i32, target: bool, map: Option<String>) -> JsValue
 {
     let mut result = None;
     GLOBAL_SESSION.with(|session| {
         let game = &*session.borrow();
-        result =Some(JsValue::from_serde(&get_action_range(&game.grid, row as usize, column as usize, range, target)).unwrap());
+        let grid = game.board(map_key(&map)).unwrap();
+        result =Some(JsValue::from_serde(&get_action_range(grid, row as usize, column as usize, range, target)).unwrap());
     });
     return result.unwrap();
 }
@@ -502,23 +694,625 @@ pub fn get_cell_distance(src_row: i32, src_col: i32, end_row: i32, end_col: i32)
  *  give_item - Adds an item to a token's character sheet
  *---------------------------------------------------------------------------*/
 #[wasm_bindgen]
-pub fn give_item(token: String, item_key: String) {
+pub fn give_item(token: String, item_key: String) -> Result<(), JsValue> {
     let str_to_chars: Vec<char> = token.chars().collect();
+    if str_to_chars.is_empty() { return Err(JsValue::from_str("invalid token")); }
+    apply_command(Command::GiveItem { token: str_to_chars[0], item: item_key }).map(|_| ()).map_err(error_to_js)
+}
+
+/******************************************************************************
+ *  give_ability - Adds an ability to a token's character sheet
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn give_ability(token: String, abil_key: String) -> Result<(), JsValue> {
+    let str_to_chars: Vec<char> = token.chars().collect();
+    if str_to_chars.is_empty() { return Err(JsValue::from_str("invalid token")); }
+    apply_command(Command::GiveAbility { token: str_to_chars[0], ability: abil_key }).map(|_| ()).map_err(error_to_js)
+}
+
+/******************************************************************************
+ *  add_map - Adds an empty named map (floor/area), false if the id is taken or
+ *            the map is larger than generator::MAX_CELLS
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn add_map(id: String, rows: i32, cols: i32) -> bool
+{
+    return apply_command(Command::AddMap { id, rows: rows.max(0) as usize, cols: cols.max(0) as usize }).is_ok();
+}
+
+/******************************************************************************
+ *  remove_map - Removes a named map, tokens on it are returned to the sheets
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn remove_map(id: String) -> bool
+{
+    return apply_command(Command::RemoveMap(id)).is_ok();
+}
+
+/******************************************************************************
+ *  list_maps - Returns the ids of every map in the session, main map first
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn list_maps() -> JsValue
+{
+    let mut result = Vec::new();
     GLOBAL_SESSION.with(|session| {
-        let new_item = session.borrow().items.get(&item_key).unwrap().clone();
-        session.borrow_mut().sheets.get_mut(&str_to_chars[0]).unwrap().items.push(new_item);
+        result = session.borrow().map_ids();
     });
+    return JsValue::from_serde(&result).unwrap();
 }
 
 /******************************************************************************
- *  give_ability - Adds an ability to a token's character sheet
+ *  link_cells - Links two cells (stairs/portal), false if either is invalid
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn link_cells(name: String, from_map: String, from_row: i32, from_col: i32, 
+    to_map: String, to_row: i32, to_col: i32, two_way: bool) -> bool
+{
+    if from_row < 0 || from_col < 0 || to_row < 0 || to_col < 0 { return false; }
+    let link = MapLink {
+        name,
+        from: Cell::new(&from_map, from_row as usize, from_col as usize),
+        to: Cell::new(&to_map, to_row as usize, to_col as usize),
+        two_way
+    };
+    return apply_command(Command::LinkCells(link)).is_ok();
+}
+
+/******************************************************************************
+ *  unlink_cell - Removes any link starting or ending at the input cell
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn unlink_cell(map: String, row: i32, col: i32)
+{
+    if row < 0 || col < 0 { return; }
+    apply_command(Command::UnlinkCell(Cell::new(&map, row as usize, col as usize))).unwrap();
+}
+
+/******************************************************************************
+ *  generate_map - Fills a map with a seeded procedural dungeon
+ *
+ *  PARAMS: OPTIONS is a (partial) GeneratorOptions object, missing fields use
+ *          their defaults. MAP is the map to replace, created if missing
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn generate_map(options: JsValue, map: Option<String>) -> Result<(), JsValue>
+{
+    let options: GeneratorOptions = options.into_serde().map_err(|err| JsValue::from_str(&err.to_string()))?;
+    return apply_command(Command::GenerateMap { map: map_key(&map).to_string(), options }).map(|_| ()).map_err(error_to_js);
+}
+
+/******************************************************************************
+ *  get_objects - Returns the doors, traps and spawn points placed on a map
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn get_objects(map: Option<String>) -> JsValue
+{
+    let mut result = None;
+    GLOBAL_SESSION.with(|session| {
+        result = Some(JsValue::from_serde(&session.borrow().objects_on(map_key(&map))).unwrap());
+    });
+    return result.unwrap();
+}
+
+/******************************************************************************
+ *  import_ascii - Replaces a map's layout with a multi-line text map
+ *
+ *  PARAMS: LEGEND is an optional glyph -> meaning object, ie: {"#": "Wall"}
+ *  RETURN: Throws the list of problems found in the text if it is invalid
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn import_ascii(text: String, map: Option<String>, legend: JsValue) -> Result<(), JsValue>
+{
+    let legend = read_legend(legend)?;
+    return match apply_command(Command::ImportAscii { map: map_key(&map).to_string(), text, legend }) {
+        Err(CommandError::Ascii(errors)) => Err(JsValue::from_serde(&errors).unwrap()),
+        result => result.map(|_| ()).map_err(error_to_js)
+    };
+}
+
+/******************************************************************************
+ *  export_ascii - Returns a map as multi-line text, see import_ascii
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn export_ascii(map: Option<String>, legend: JsValue) -> Result<String, JsValue>
+{
+    let legend = read_legend(legend)?;
+    let mut result = Ok(String::new());
+    GLOBAL_SESSION.with(|session| {
+        result = session.borrow().export_ascii(map_key(&map), &legend);
+    });
+    return result.map_err(|err| JsValue::from_str(&err.to_string()));
+}
+
+/******************************************************************************
+ *  import_tiled - Replaces a map's layout with a map saved by the Tiled editor
+ *
+ *  PARAMS: JSON is the content of a Tiled .tmj/.json map, MAPPING is a (partial)
+ *          TiledMapping object telling what each tile id and object type means
+ *  RETURN: Import report listing every feature that couldn't be converted
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn import_tiled(json: String, map: Option<String>, mapping: JsValue) -> Result<JsValue, JsValue>
+{
+    let mapping: TiledMapping = if mapping.is_null() || mapping.is_undefined() { Default::default() } 
+        else { mapping.into_serde().map_err(|err| JsValue::from_str(&err.to_string()))? };
+    return match apply_command(Command::ImportTiled { map: map_key(&map).to_string(), json, mapping }).map_err(error_to_js)? {
+        Applied::Imported(report) => Ok(JsValue::from_serde(&report).unwrap()),
+        _ => Ok(JsValue::NULL)
+    };
+}
+
+/******************************************************************************
+ *  get_links - Returns every link between maps in the current session
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn get_links() -> JsValue
+{
+    let mut result = None;
+    GLOBAL_SESSION.with(|session| {
+        result = Some(JsValue::from_serde(&session.borrow().links).unwrap());
+    });
+    return result.unwrap();
+}
+
+/******************************************************************************
+ *  roll_dice - Rolls a number in [LOW, HIGH] and logs the roll with its seed
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn roll_dice(low: i32, high: i32) -> i32
+{
+    let mut result = low;
+    GLOBAL_SESSION.with(|session| {
+        result = session.borrow_mut().roll(low, high);
+    });
+    return result;
+}
+
+/******************************************************************************
+ *  chat - Parses a chat line and rolls its dice for the player of TOKEN
+ *
+ *  PARAMS: TOKEN whose stats expressions like 1d20+Dexterity use, none for
+ *          the DM
+ *  RETURN: Message to display (see chat.rs), throws if the line was refused
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn chat(input: String, token: Option<String>) -> Result<JsValue, JsValue>
+{
+    let token = token.and_then(|token| token.chars().next());
+    GLOBAL_SESSION.with(|session| {
+        let message = session.borrow_mut().chat(token, &input).map_err(|err| JsValue::from_str(&err.to_string()))?;
+        Ok(JsValue::from_serde(&message).unwrap())
+    })
+}
+
+/******************************************************************************
+ *  get_events - Returns the logged events, starting at seq FROM (default 0)
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn get_events(from: Option<u32>) -> JsValue
+{
+    let mut result = None;
+    GLOBAL_SESSION.with(|session| {
+        result = Some(JsValue::from_serde(session.borrow().events_since(from.unwrap_or(0) as u64)).unwrap());
+    });
+    return result.unwrap();
+}
+
+/******************************************************************************
+ *  replay_game - Rebuilds a game from a snapshot and the current event log
+ *
+ *  PARAMS: SNAPSHOT is a previously exported game, UPTO the last event to apply
+ *          (all of them if missing). The current session is left untouched
+ *  RETURN: The rebuilt game, can be handed to load_game
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn replay_game(snapshot: JsValue, upto: Option<u32>) -> Result<JsValue, JsValue>
+{
+    let (snapshot, _) = read_game(snapshot)?;
+    let mut result = Ok(JsValue::NULL);
+    GLOBAL_SESSION.with(|session| {
+        result = events::replay(&snapshot, &session.borrow().events, upto.map(|seq| seq as u64))
+            .map(|game| JsValue::from_serde(&game).unwrap())
+            .map_err(|err| JsValue::from_str(&err.to_string()));
+    });
+    return result;
+}
+
+/******************************************************************************
+ *  export_delta - Returns the changes since the last export_delta (or since an
+ *                 empty session the first time) and remembers the current state.
+ *                 A delta of nothing but rolls keeps the version
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn export_delta() -> JsValue
+{
+    let mut result = None;
+    GLOBAL_SESSION.with(|session| {
+        SYNCED_SESSION.with(|synced| {
+            let current = copy_session(&session.borrow());
+            let delta = match &*synced.borrow() {
+                Some(old) => sync::diff(old, &current),
+                None => sync::diff(&GameSession::default(), &current)
+            };
+            result = Some(JsValue::from_serde(&delta).unwrap());
+            *synced.borrow_mut() = Some(current);
+        });
+    });
+    return result.unwrap();
+}
+
+/******************************************************************************
+ *  apply_delta - Patches the current session with a delta from export_delta
+ *
+ *  RETURN: Throws if the delta doesn't start at the session's version, the
+ *          client should then reload the whole game with load_game
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn apply_delta(delta: JsValue) -> Result<(), JsValue>
+{
+    let delta: sync::SessionDelta = delta.into_serde().map_err(|err| JsValue::from_str(&err.to_string()))?;
+    let mut result = Ok(());
+    GLOBAL_SESSION.with(|session| {
+        result = session.borrow_mut().apply_delta(&delta);
+    });
+    return result.map_err(|err| JsValue::from_str(&err.to_string()));
+}
+
+/******************************************************************************
+ *  get_version - Returns the version of the current session
  *---------------------------------------------------------------------------*/
 #[wasm_bindgen]
-pub fn give_ability(token: String, abil_key: String) {
+pub fn get_version() -> f64
+{
+    let mut result = 0;
+    GLOBAL_SESSION.with(|session| {
+        result = session.borrow().version;
+    });
+    return result as f64;
+}
+
+/******************************************************************************
+ *  state_hash - Returns a stable hash of the current game state, clients that
+ *               ran the same requests with the same dice report the same hash
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn state_hash() -> String
+{
+    let mut result = String::new();
+    GLOBAL_SESSION.with(|session| {
+        result = session.borrow().state_hash();
+    });
+    return result;
+}
+
+/******************************************************************************
+ *  compare_games - Compares two exported games field by field
+ *
+ *  PARAMS: LIMIT is the max number of differences reported (default 10)
+ *  RETURN: Vector of {path, left, right} for the first fields that differ
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn compare_games(left: JsValue, right: JsValue, limit: Option<u32>) -> Result<JsValue, JsValue>
+{
+    let (left, _) = read_game(left)?;
+    let (right, _) = read_game(right)?;
+    let result = checksum::compare_states(&left, &right, limit.unwrap_or(10) as usize);
+    return Ok(JsValue::from_serde(&result).unwrap());
+}
+
+/******************************************************************************
+ *  validate_game - Lints the current game set
+ *
+ *  RETURN: Vector of {severity, path, kind} issues, errors first, empty if the
+ *          game is clean. See validate.rs for what is checked
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn validate_game() -> JsValue
+{
+    let mut result = None;
+    GLOBAL_SESSION.with(|session| {
+        result = Some(JsValue::from_serde(&validate::validate_game(&session.borrow())).unwrap());
+    });
+    return result.unwrap();
+}
+
+/******************************************************************************
+ *  encode_client_message - Wraps a message for the game server
+ *
+ *  PARAMS: MESSAGE is a {type, ...} client message, ID is echoed back by the
+ *          server's ack or transactionFailed (none: no ack). See protocol.rs
+ *  RETURN: The JSON text to send, throws if MESSAGE isn't a valid message
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn encode_client_message(message: JsValue, id: Option<f64>) -> Result<String, JsValue>
+{
+    let message: ClientMessage = message.into_serde().map_err(|err| JsValue::from_str(&err.to_string()))?;
+    return Ok(protocol::encode(message, id.map(|id| id as u64)));
+}
+
+/******************************************************************************
+ *  decode_server_message - Reads a message from the game server
+ *
+ *  RETURN: The {v, id, type, ...} envelope, throws on another protocol version
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn decode_server_message(text: String) -> Result<JsValue, JsValue>
+{
+    let envelope = protocol::decode::<ServerMessage>(&text).map_err(|err| JsValue::from_str(&err.to_string()))?;
+    return Ok(JsValue::from_serde(&envelope).unwrap());
+}
+
+/******************************************************************************
+ *  encode_server_message - Wraps a message for a client, for servers in JS
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn encode_server_message(message: JsValue, id: Option<f64>) -> Result<String, JsValue>
+{
+    let message: ServerMessage = message.into_serde().map_err(|err| JsValue::from_str(&err.to_string()))?;
+    return Ok(protocol::encode(message, id.map(|id| id as u64)));
+}
+
+/******************************************************************************
+ *  decode_client_message - Reads a message from a client, for servers in JS
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn decode_client_message(text: String) -> Result<JsValue, JsValue>
+{
+    let envelope = protocol::decode::<ClientMessage>(&text).map_err(|err| JsValue::from_str(&err.to_string()))?;
+    return Ok(JsValue::from_serde(&envelope).unwrap());
+}
+
+/******************************************************************************
+ *  undo - Reverts the last edit, returns its label or null if there is none
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn undo() -> JsValue
+{
+    let mut result = None;
+    GLOBAL_SESSION.with(|session| {
+        result = session.borrow_mut().undo();
+    });
+    return JsValue::from_serde(&result).unwrap();
+}
+
+/******************************************************************************
+ *  redo - Applies the last undone edit again, returns its label or null
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn redo() -> JsValue
+{
+    let mut result = None;
+    GLOBAL_SESSION.with(|session| {
+        result = session.borrow_mut().redo();
+    });
+    return JsValue::from_serde(&result).unwrap();
+}
+
+/******************************************************************************
+ *  history_list - Returns the labels of the edits that can be undone/redone
+ *
+ *  RETURN: Object {undo: [...], redo: [...]}, the last label of each is next
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn history_list() -> JsValue
+{
+    let mut result = None;
+    GLOBAL_SESSION.with(|session| {
+        result = Some(JsValue::from_serde(&session.borrow().history_list()).unwrap());
+    });
+    return result.unwrap();
+}
+
+/******************************************************************************
+ *  begin_group - Merges the following edits into one undo step until the
+ *                matching end_group (ie: a brush stroke, a multi-cell paste)
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn begin_group(label: String)
+{
+    GLOBAL_SESSION.with(|session| {
+        session.borrow_mut().begin_group(&label);
+    });
+}
+
+/******************************************************************************
+ *  end_group - Closes the group opened by the last begin_group
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn end_group()
+{
+    GLOBAL_SESSION.with(|session| {
+        session.borrow_mut().end_group();
+    });
+}
+
+/******************************************************************************
+ *  set_visibility - Changes who sees a token, see layers.rs
+ *
+ *  PARAMS: VISIBILITY is "visible", "hidden" or "invisible", DC is the
+ *          detection DC of an invisible token (10 by default)
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn set_visibility(token: String, visibility: String, dc: Option<i32>) -> Result<(), JsValue>
+{
+    let visibility = match visibility.as_str() {
+        "visible" => Visibility::Visible,
+        "hidden" => Visibility::Hidden,
+        "invisible" => Visibility::Invisible { dc: dc.unwrap_or(10) },
+        _ => return Err(JsValue::from_str(&format!("no visibility named '{}'", visibility)))
+    };
+    let token = token.chars().next().ok_or_else(|| JsValue::from_str("invalid token"))?;
+    apply_command(Command::SetVisibility { token, visibility }).map(|_| ()).map_err(error_to_js)
+}
+
+/******************************************************************************
+ *  set_behaviour - Sets how the AI plays a token, see ai.rs
+ *
+ *  PARAMS: BEHAVIOUR is "aggressive", "kiter", "defender", "coward" or
+ *          "support", none goes back to the default (aggressive)
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn set_behaviour(token: String, behaviour: Option<String>) -> Result<(), JsValue>
+{
+    let behaviour = match behaviour {
+        Some(behaviour) => Some(behaviour.parse::<Behaviour>().map_err(|err| JsValue::from_str(&err))?),
+        None => None
+    };
+    let token = token.chars().next().ok_or_else(|| JsValue::from_str("invalid token"))?;
+    apply_command(Command::SetBehaviour { token, behaviour }).map(|_| ()).map_err(error_to_js)
+}
+
+/******************************************************************************
+ *  auto_play_monsters - Requests the AI proposes for every token the party
+ *                       fights, to approve like any other request
+ *
+ *  PARAMS: PARTY is a string of the party's tokens, the tokens granted to a
+ *          player by default
+ *  RETURN: Proposed requests in the order to execute them, see ai.rs
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn auto_play_monsters(party: Option<String>) -> JsValue
+{
+    GLOBAL_SESSION.with(|session| {
+        let session = session.borrow();
+        let party = party.map_or_else(|| session.party(), |party| party.chars().collect());
+        JsValue::from_serde(&session.auto_play(&party)).unwrap()
+    })
+}
+
+/******************************************************************************
+ *  detect_token - Rolls a detection check of token BY against an invisible
+ *                 token, which becomes visible on a success
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn detect_token(token: String, by: String) -> Result<bool, JsValue>
+{
+    let token = token.chars().next().ok_or_else(|| JsValue::from_str("invalid token"))?;
+    let by = by.chars().next().ok_or_else(|| JsValue::from_str("invalid token"))?;
+    let mut result = Ok(false);
+    GLOBAL_SESSION.with(|session| {
+        result = session.borrow_mut().detect(token, by);
+    });
+    return result.map_err(|err| JsValue::from_str(&err));
+}
+
+/******************************************************************************
+ *  add_note - Pins a GM note on a cell, players never receive it
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn add_note(text: String, row: i32, col: i32, map: Option<String>) -> Result<(), JsValue>
+{
+    if row < 0 || col < 0 { return Err(JsValue::from_str("invalid cell")); }
+    let cell = Cell::new(map_key(&map), row as usize, col as usize);
+    apply_command(Command::AddNote(MapNote { cell, text })).map(|_| ()).map_err(error_to_js)
+}
+
+/******************************************************************************
+ *  remove_notes - Removes the GM notes pinned on a cell
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn remove_notes(row: i32, col: i32, map: Option<String>) -> Result<(), JsValue>
+{
+    if row < 0 || col < 0 { return Err(JsValue::from_str("invalid cell")); }
+    apply_command(Command::RemoveNotes(Cell::new(map_key(&map), row as usize, col as usize))).map(|_| ()).map_err(error_to_js)
+}
+
+/******************************************************************************
+ *  get_layer - Returns what is drawn on one layer of a map
+ *
+ *  PARAMS: LAYER is "background", "object", "token" or "gm-notes"
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn get_layer(layer: String, map: Option<String>) -> Result<JsValue, JsValue>
+{
+    let layer = layer.parse::<Layer>().map_err(|err| JsValue::from_str(&err))?;
+    let mut result = JsValue::default();
+    GLOBAL_SESSION.with(|session| {
+        result = JsValue::from_serde(&session.borrow().layer(map_key(&map), layer)).unwrap();
+    });
+    return Ok(result);
+}
+
+/******************************************************************************
+ *  get_permissions - Returns the users, their roles and the controllers of
+ *                    each token, see permissions.rs
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn get_permissions() -> JsValue
+{
+    let mut result = JsValue::default();
+    GLOBAL_SESSION.with(|session| {
+        result = JsValue::from_serde(&session.borrow().permissions).unwrap();
+    });
+    return result;
+}
+
+/******************************************************************************
+ *  set_role - Adds a user to the session or changes their role
+ *
+ *  PARAMS: ROLE is "host", "co-dm", "player" or "spectator", none removes USER
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn set_role(user: String, role: Option<String>) -> Result<(), JsValue>
+{
+    let role = match role {
+        Some(role) => Some(role.parse::<Role>().map_err(|err| JsValue::from_str(&err.to_string()))?),
+        None => None
+    };
+    apply_command(Command::SetRole { user, role }).map(|_| ()).map_err(error_to_js)
+}
+
+/******************************************************************************
+ *  grant_token - Lets a user act for a token, other controllers keep it
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn grant_token(user: String, token: String) -> Result<(), JsValue>
+{
     let str_to_chars: Vec<char> = token.chars().collect();
+    if str_to_chars.is_empty() { return Err(JsValue::from_str("invalid token")); }
+    apply_command(Command::Grant { user, token: str_to_chars[0] }).map(|_| ()).map_err(error_to_js)
+}
+
+/******************************************************************************
+ *  revoke_token - Takes a token away from a user
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn revoke_token(user: String, token: String) -> Result<(), JsValue>
+{
+    let str_to_chars: Vec<char> = token.chars().collect();
+    if str_to_chars.is_empty() { return Err(JsValue::from_str("invalid token")); }
+    apply_command(Command::Revoke { user, token: str_to_chars[0] }).map(|_| ()).map_err(error_to_js)
+}
+
+/******************************************************************************
+ *  set_action_allowed - Allows or withholds an action from a player
+ *
+ *  PARAMS: ACTION is "move", "item", "ability" or "unequip"
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn set_action_allowed(user: String, action: String, allowed: bool) -> Result<(), JsValue>
+{
+    let action = action.parse::<Action>().map_err(|err| JsValue::from_str(&err.to_string()))?;
+    apply_command(Command::SetAllowed { user, action, allowed }).map(|_| ()).map_err(error_to_js)
+}
+
+/******************************************************************************
+ *  can_act - Tells if a user may do an action with a token, to only show the
+ *            options they can use
+ *
+ *  RETURN: Empty string if allowed, why not otherwise
+ *---------------------------------------------------------------------------*/
+#[wasm_bindgen]
+pub fn can_act(user: String, token: String, action: String) -> Result<String, JsValue>
+{
+    let action = action.parse::<Action>().map_err(|err| JsValue::from_str(&err.to_string()))?;
+    let token = token.chars().next().ok_or_else(|| JsValue::from_str("invalid token"))?;
+    let mut result = String::new();
     GLOBAL_SESSION.with(|session| {
-        session.borrow_mut().sheets.get_mut(&str_to_chars[0]).unwrap().abilities.insert(abil_key);
+        if let Err(err) = session.borrow().permissions.allows(Some(&user), token, action) { result = err.to_string(); }
     });
+    return Ok(result);
 }
 
 
@@ -531,12 +1325,17 @@ impl GameSession
      *---------------------------------------------------------------------------*/
     pub fn move_token(&mut self, token: char, new_row: usize, new_col: usize)
     {
-        if self.grid[new_row][new_col] != '0' { return;}
-        let mut temp_token = self.characters.get_mut(&token).unwrap();
-        self.grid[new_row][new_col] = token;
-        self.grid[temp_token.row][temp_token.column] = '0';
+        let temp_token = self.characters.get_mut(&token).unwrap();
+        let grid = match self.maps.get_mut(&temp_token.map) {
+            Some(grid) => grid,
+            None => &mut self.grid
+        };
+        if grid[new_row][new_col] != '0' { return;}
+        grid[new_row][new_col] = token;
+        grid[temp_token.row][temp_token.column] = '0';
         temp_token.row = new_row;
         temp_token.column = new_col;
+        self.follow_link(token);
     }
 
     /******************************************************************************
@@ -617,15 +1416,17 @@ impl GameSession
             0 => { result.target_cell = Some((end_row as usize, end_col as usize)); },
             1 => {
                 result.action_type = 2;
+                let grid = self.board(&self.characters.get(&tok).unwrap().map).unwrap();
+                let target = grid[end_row as usize][end_col as usize];
                 if self.abilities.get(key).unwrap().range > 0 {
-                    if self.characters.contains_key(&self.grid[end_row as usize][end_col as usize])
-                        { result.target_tokens = Some(vec![self.grid[end_row as usize][end_col as usize]]) }
+                    if self.characters.contains_key(&target)
+                        { result.target_tokens = Some(vec![target]) }
                 }
             }
             2 => { result.action_type = 1; },
             _ => { return result; }
         }
-        self.execute_request(tok, &result);
+        let _ = self.apply(Command::Execute(result.clone()));
         return result;
     }
 
@@ -634,6 +1435,7 @@ impl GameSession
      *---------------------------------------------------------------------------*/
     pub fn sort_requests(&mut self) 
     {
+        self.record(events::EventKind::SortRequests);
         let mut req_w_initiat = Vec::new();
         while !self.requests.is_empty() {
             let temp_req = self.requests.pop().unwrap();
@@ -652,10 +1454,18 @@ impl GameSession
     
     /******************************************************************************
      *  execute_request - Executes the input request on the current game
+     *
+     *  RETURN: What the request did, also appended to the combat log (see
+     *          combat.rs), or why it can't be done: its caster isn't on a map or
+     *          its user may not make it (see permissions.rs)
      *---------------------------------------------------------------------------*/
-    pub fn execute_request(&mut self, token: char, req: &Request)
+    pub fn execute_request(&mut self, token: char, req: &Request) -> Result<ActionRecord, CommandError>
     {
-        if self.characters.get(&token).is_none() { return };
+        if !self.characters.contains_key(&token) { return Err(CommandError::Invalid(format!("'{}' is not on a map", token))); }
+        let watched = self.watch(token, req);
+        if let Some(action) = Action::of(req.action_type) {
+            self.permissions.allows(req.user.as_deref(), token, action).map_err(CommandError::Permission)?;
+        }
         match req.action_type {
             0 => { self.move_token(token, req.target_cell.unwrap().0, req.target_cell.unwrap().1); },
             1 => { self.use_item(token, req.subtype_key.clone().unwrap().parse::<i32>().unwrap() as usize); },
@@ -663,15 +1473,59 @@ impl GameSession
             3 => { self.remove_equipment(token, &req.subtype_key.clone().unwrap()); },
             _ => {}
         }
+        return Ok(self.log_action(token, req, watched));
     }
     
+    /******************************************************************************
+     *  check_request - Tells if a request can be executed right now, following
+     *                  the same rules as the options shown to players
+     *
+     *  RETURN: Why the request would be refused
+     *---------------------------------------------------------------------------*/
+    pub fn check_request(&self, req: &Request) -> Result<(), String>
+    {
+        self.permissions.check(req).map_err(|err| err.to_string())?;
+        let token = self.characters.get(&req.caster).ok_or_else(|| format!("'{}' is not on a map", req.caster))?;
+        let grid = self.board(&token.map).ok_or_else(|| format!("no map named '{}'", token.map))?;
+        let key = req.subtype_key.clone().unwrap_or_default();
+        match req.action_type {
+            0 => {
+                let (row, col) = req.target_cell.ok_or("a move needs a target cell")?;
+                let options = get_action_range(grid, token.row, token.column, token.sheet.speed, false);
+                if !options.contains(&(row as i32, col as i32)) { return Err(format!("{},{} is out of reach", row, col)); }
+            },
+            1 => {
+                let index = key.parse::<usize>().map_err(|_| format!("'{}' is not an item index", key))?;
+                if index >= token.sheet.items.len() { return Err(format!("'{}' has no item {}", req.caster, index)); }
+            },
+            2 => {
+                let ability = self.abilities.get(&key).ok_or_else(|| format!("no ability named '{}'", key))?;
+                if !token.sheet.abilities.contains(&key) { return Err(format!("'{}' can't use {}", req.caster, key)); }
+                let options = get_action_range(grid, token.row, token.column, ability.range as i32, true);
+                for target in req.target_tokens.iter().flatten() {
+                    let target_tok = self.characters.get(target).ok_or_else(|| format!("'{}' is not on a map", target))?;
+                    let in_range = *target == req.caster
+                        || (target_tok.map == token.map && options.contains(&(target_tok.row as i32, target_tok.column as i32)));
+                    if !in_range { return Err(format!("'{}' is out of range", target)); }
+                }
+            },
+            3 => { if !token.sheet.equipment.contains_key(&key) { return Err(format!("'{}' has nothing in {}", req.caster, key)); } },
+            n => { return Err(format!("unknown action type {}", n)); }
+        }
+        return Ok(());
+    }
+
     /******************************************************************************
      *  place_tokens - Syncs board by going through token list, places them on grid
      *---------------------------------------------------------------------------*/
     pub fn place_tokens(&mut self)
     {
         for entry in &self.characters { 
-            self.grid[entry.1.row][entry.1.column] = *entry.0; 
+            let grid = match self.maps.get_mut(&entry.1.map) {
+                Some(grid) => grid,
+                None => &mut self.grid
+            };
+            grid[entry.1.row][entry.1.column] = *entry.0; 
         }
     }
 
@@ -693,6 +1547,7 @@ impl GameSession
             row.push(c);
             column += 1;
         }
+        if !row.is_empty() { grid.push(row); }
         self.grid = grid;
     }
 }
@@ -705,9 +1560,9 @@ impl fmt::Display for GameSession
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         for n in self.grid.as_slice() {
             for c in n.iter() {
-                write!(f, "{}", c);
+                write!(f, "{}", c)?;
             }
-            write!(f, "\n");
+            write!(f, "\n")?;
         }
         Ok(())
     }
@@ -718,21 +1573,62 @@ impl fmt::Display for GameSession
  *---------------------------------------------------------------------------*/
 fn copy_session(arg: &GameSession) -> GameSession
 {
-    let mut result = GameSession {
-        sheets: arg.sheets.clone(),
-        characters: arg.characters.clone(),
-        abilities: HashMap::new(),
-        requests: Vec::new(),
-        effects: arg.effects.clone(),
-        items: arg.items.clone(),
-        grid: arg.grid.clone(),
-    };
-    for (key, value) in &arg.abilities {
-        result.abilities.insert(key.clone(), value.clone());
-    }
+    return GameSession { requests: Vec::new(), ..arg.clone() };
+}
+
+/******************************************************************************
+ *  apply_command - Applies an edit to the global session, see history.rs
+ *---------------------------------------------------------------------------*/
+fn apply_command(command: Command) -> Result<Applied, CommandError>
+{
+    let mut result = Ok(Applied::Done);
+    GLOBAL_SESSION.with(|session| {
+        result = session.borrow_mut().apply(command);
+    });
     return result;
 }
 
+/******************************************************************************
+ *  error_to_js - Converts a refused command into a JS error message
+ *---------------------------------------------------------------------------*/
+fn error_to_js(err: CommandError) -> JsValue
+{
+    return JsValue::from_str(&err.to_string());
+}
+
+/******************************************************************************
+ *  seed_dice - Seeds the dice of a game that was never seeded (state 0), so
+ *              every new session rolls its own sequence. Math.random and the
+ *              clock in the browser, the clock natively
+ *---------------------------------------------------------------------------*/
+pub(crate) fn seed_dice(game: &mut GameSession)
+{
+    if game.rng.state() != 0 { return; }
+    #[cfg(target_arch = "wasm32")]
+    let seed = (math_random() * (1u64 << 53) as f64) as u64 ^ (date_now() as u64).rotate_left(32);
+    #[cfg(not(target_arch = "wasm32"))]
+    let seed = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64);
+    game.rng = Rng::new(seed.max(1));
+}
+
+/******************************************************************************
+ *  read_game - Deserializes and upgrades a saved game passed in from JS
+ *---------------------------------------------------------------------------*/
+fn read_game(data: JsValue) -> Result<(GameSession, Vec<String>), JsValue>
+{
+    let value: serde_json::Value = data.into_serde().map_err(|err| JsValue::from_str(&err.to_string()))?;
+    return migrate::load_save(value).map_err(|err| JsValue::from_str(&err.to_string()));
+}
+
+/******************************************************************************
+ *  read_legend - Deserializes a legend from JS, null/undefined is the default
+ *---------------------------------------------------------------------------*/
+fn read_legend(legend: JsValue) -> Result<Legend, JsValue>
+{
+    if legend.is_null() || legend.is_undefined() { return Ok(Legend::default()); }
+    return legend.into_serde().map_err(|err| JsValue::from_str(&err.to_string()));
+}
+
 /******************************************************************************
  *  get_action_range - Recursive pathfinding, generates where a token can move
  *---------------------------------------------------------------------------*/
@@ -813,18 +1709,21 @@ fn get_line_of_sight(grid: &Vec<Vec<char>>, src_row: usize, src_col: usize, rang
 }
 
 /******************************************************************************
- *  apply_effect - Apply effect on the stat of a character 
+ *  apply_effect - Apply effect on the stat of a character, stats are matched
+ *                 in any case and a stat the character lacks is left alone
  *---------------------------------------------------------------------------*/
 fn apply_effect(target: &mut Character, key: &str)
 {
     // make to lowercase before checking in hash, or match
-    let mut effect = target.effects.get_mut(key).unwrap();
+    let effect = target.effects.get_mut(key).unwrap();
     let lower_key: &str = &effect.target_stat.to_lowercase();
     match lower_key {
         "health" => target.hitpoints += effect.modifier[0],
         "speed" => target.speed += effect.modifier[0],
         "initiative" => target.initiative += effect.modifier[0] as i8,
-        _ => { target.stats.insert(lower_key.to_string(), target.stats.get(lower_key).unwrap() + effect.modifier[0] as i16); }
+        _ => {
+            if let Some((_, stat)) = target.stats.iter_mut().find(|(stat, _)| stat.to_lowercase() == lower_key) { *stat += effect.modifier[0] as i16; }
+        }
     }
     effect.duration -= 1;
     if effect.duration == 0 { target.effects.remove(key); };
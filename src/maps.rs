@@ -0,0 +1,346 @@
+/*/////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+ *
+ *      Multi-level dungeons for ByteDungeon, named maps (floors, areas) and the links between them
+ *
+ *      Implementation and Assumptions
+ *          - The original board stays in GameSession.grid and is addressed by the MAIN_MAP id
+ *          - Every other map is stored by name in GameSession.maps using the same 2D char representation
+ *          - Tokens remember the id of the map they stand on, all coordinates are relative to that map
+ *          - Links (stairs, portals) are floor cells, stepping on one sends the token to the linked cell if it is free
+ *          - Doors, traps, terrain and spawn points are objects laid over floor cells, they never block movement
+ *          - New maps larger than the generator's MAX_CELLS are refused before anything is allocated
+ *
+ */////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+use serde::{Serialize, Deserialize};
+
+use crate::{GameSession, Token};
+use crate::generator::MAX_CELLS;
+
+pub const MAIN_MAP: &str = "main";
+
+/***********************************************
+ * Cell - Coordinates of a cell on a named map
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(ts_rs::TS))]
+pub struct Cell
+{
+    pub map: String,
+    pub row: usize,
+    pub column: usize
+}
+
+/***********************************************
+ * MapLink - Stairs/portals connecting two cells
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(ts_rs::TS))]
+pub struct MapLink
+{
+    pub name: String,                           // Display name of the link (ie: "stairs down", "portal")
+    pub from: Cell,
+    pub to: Cell,
+    pub two_way: bool                           // True: tokens standing on TO are also sent back to FROM
+}
+
+/***********************************************
+ * ObjectKind - What a map object represents
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(ts_rs::TS))]
+pub enum ObjectKind
+{
+    Door,
+    Trap,
+    Terrain(String),                            // Named terrain on a floor cell (ie: "water", "rubble")
+    Spawn(Option<char>)                         // Spawn point, optionally reserved for one token
+}
+
+/***********************************************
+ * MapObject - Object placed over a floor cell
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(ts_rs::TS))]
+pub struct MapObject
+{
+    pub cell: Cell,
+    pub kind: ObjectKind
+}
+
+/******************************************************************************
+ *  default_map - Serde default for tokens saved before maps existed
+ *---------------------------------------------------------------------------*/
+pub fn default_map() -> String { MAIN_MAP.to_string() }
+
+/******************************************************************************
+ *  map_key - Resolves an optional map id from the bindings, None is MAIN_MAP
+ *---------------------------------------------------------------------------*/
+pub fn map_key(map: &Option<String>) -> &str
+{
+    match map {
+        Some(id) if !id.is_empty() => id,
+        _ => MAIN_MAP
+    }
+}
+
+impl Cell
+{
+    pub fn new(map: &str, row: usize, column: usize) -> Cell {
+        Cell { map: map.to_string(), row, column }
+    }
+}
+
+impl GameSession
+{
+    /******************************************************************************
+     *  board - Returns the grid of the map with the input id
+     *---------------------------------------------------------------------------*/
+    pub fn board(&self, map: &str) -> Option<&Vec<Vec<char>>>
+    {
+        if map == MAIN_MAP { return Some(&self.grid); }
+        self.maps.get(map)
+    }
+
+    /******************************************************************************
+     *  board_mut - Returns a mutable grid of the map with the input id
+     *---------------------------------------------------------------------------*/
+    pub fn board_mut(&mut self, map: &str) -> Option<&mut Vec<Vec<char>>>
+    {
+        if map == MAIN_MAP { return Some(&mut self.grid); }
+        self.maps.get_mut(map)
+    }
+
+    /******************************************************************************
+     *  map_ids - Returns the id of every map, main map first, others sorted
+     *---------------------------------------------------------------------------*/
+    pub fn map_ids(&self) -> Vec<String>
+    {
+        let mut ids: Vec<String> = self.maps.keys().cloned().collect();
+        ids.sort();
+        ids.insert(0, MAIN_MAP.to_string());
+        return ids;
+    }
+
+    /******************************************************************************
+     *  add_map - Adds an empty map, returns false if the id is already taken or
+     *            the map would have more than MAX_CELLS cells
+     *---------------------------------------------------------------------------*/
+    pub fn add_map(&mut self, id: &str, rows: usize, cols: usize) -> bool
+    {
+        if id.is_empty() || self.board(id).is_some() || rows.saturating_mul(cols) > MAX_CELLS { return false; }
+        let grid = if rows == 0 || cols == 0 { Vec::new() } else { vec![vec!['0'; cols]; rows] };
+        self.maps.insert(id.to_string(), grid);
+        return true;
+    }
+
+    /******************************************************************************
+     *  remove_map - Removes a map with its links/objects, tokens go back to sheets
+     *---------------------------------------------------------------------------*/
+    pub fn remove_map(&mut self, id: &str) -> bool
+    {
+        if id == MAIN_MAP || self.maps.remove(id).is_none() { return false; }
+        self.clear_map(id);
+        self.notes.retain(|note| note.cell.map != id);
+        return true;
+    }
+
+    /******************************************************************************
+     *  toggle_cell - Alternates a cell between floor and wall, a token on the
+     *                cell goes back to sheets. Returns the new char
+     *---------------------------------------------------------------------------*/
+    pub fn toggle_cell(&mut self, cell: &Cell) -> Option<char>
+    {
+        let current = self.cell_at(cell)?;
+        let new = if current == '0' { '1' } else { '0' };
+        if let Some(token) = self.characters.remove(&current) { self.sheets.insert(current, token.sheet); }
+        self.board_mut(&cell.map).unwrap()[cell.row][cell.column] = new;
+        return Some(new);
+    }
+
+    /******************************************************************************
+     *  place_token - Places an unassigned sheet on a cell that holds no token
+     *---------------------------------------------------------------------------*/
+    pub fn place_token(&mut self, key: char, cell: &Cell) -> bool
+    {
+        match self.cell_at(cell) {
+            Some(current) if !self.characters.contains_key(&current) && self.sheets.contains_key(&key) => {},
+            _ => return false
+        }
+        let sheet = self.sheets.remove(&key).unwrap();
+        let token = Token { row: cell.row, column: cell.column, map: cell.map.clone(), initiative: Some(sheet.initiative), sheet,
+                            visibility: Default::default() };
+        self.board_mut(&cell.map).unwrap()[cell.row][cell.column] = key;
+        self.characters.insert(key, token);
+        return true;
+    }
+
+    /******************************************************************************
+     *  link_cells - Links two existing cells, replaces any link leaving FROM
+     *---------------------------------------------------------------------------*/
+    pub fn link_cells(&mut self, link: MapLink) -> bool
+    {
+        if !self.in_bounds(&link.from) || !self.in_bounds(&link.to) { return false; }
+        self.links.retain(|old| old.from != link.from);
+        self.links.push(link);
+        return true;
+    }
+
+    /******************************************************************************
+     *  unlink_cell - Removes every link that starts or ends at the input cell
+     *---------------------------------------------------------------------------*/
+    pub fn unlink_cell(&mut self, cell: &Cell)
+    {
+        self.links.retain(|link| &link.from != cell && &link.to != cell);
+    }
+
+    /******************************************************************************
+     *  link_destination - Returns where a token standing on CELL is sent to
+     *---------------------------------------------------------------------------*/
+    pub fn link_destination(&self, cell: &Cell) -> Option<Cell>
+    {
+        for link in &self.links {
+            if &link.from == cell { return Some(link.to.clone()); }
+            if link.two_way && &link.to == cell { return Some(link.from.clone()); }
+        }
+        return None;
+    }
+
+    /******************************************************************************
+     *  in_bounds - Returns true if the cell exists on its map
+     *---------------------------------------------------------------------------*/
+    pub fn in_bounds(&self, cell: &Cell) -> bool
+    {
+        match self.board(&cell.map) {
+            Some(grid) => cell.row < grid.len() && cell.column < grid[cell.row].len(),
+            None => false
+        }
+    }
+
+    /******************************************************************************
+     *  cell_at - Returns the char at the input cell, None if out of bounds
+     *---------------------------------------------------------------------------*/
+    pub fn cell_at(&self, cell: &Cell) -> Option<char>
+    {
+        if !self.in_bounds(cell) { return None; }
+        Some(self.board(&cell.map).unwrap()[cell.row][cell.column])
+    }
+
+    /******************************************************************************
+     *  objects_at - Returns every object placed on the input cell
+     *---------------------------------------------------------------------------*/
+    pub fn objects_at(&self, cell: &Cell) -> Vec<&MapObject>
+    {
+        self.objects.iter().filter(|obj| &obj.cell == cell).collect()
+    }
+
+    /******************************************************************************
+     *  objects_on - Returns every object placed on the map with the input id
+     *---------------------------------------------------------------------------*/
+    pub fn objects_on(&self, map: &str) -> Vec<&MapObject>
+    {
+        self.objects.iter().filter(|obj| obj.cell.map == map).collect()
+    }
+
+    /******************************************************************************
+     *  place_object - Places an object on an existing cell of any map
+     *---------------------------------------------------------------------------*/
+    pub fn place_object(&mut self, object: MapObject) -> bool
+    {
+        if !self.in_bounds(&object.cell) { return false; }
+        self.objects.push(object);
+        return true;
+    }
+
+    /******************************************************************************
+     *  clear_map - Empties a map's layout: tokens go back to sheets, objects and
+     *              links touching the map are removed
+     *---------------------------------------------------------------------------*/
+    pub fn clear_map(&mut self, id: &str)
+    {
+        let on_map: Vec<char> = self.characters.iter()
+            .filter(|(_, tok)| tok.map == id)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in on_map {
+            let token = self.characters.remove(&key).unwrap();
+            self.sheets.insert(key, token.sheet);
+        }
+        self.objects.retain(|obj| obj.cell.map != id);
+        self.links.retain(|link| link.from.map != id && link.to.map != id);
+    }
+
+    /******************************************************************************
+     *  replace_layout - Replaces the grid and objects of a map, creating it if
+     *                   missing, and places the input tokens on it
+     *
+     *  PARAMS: GRID is the new '0'/'1' layout, TOKENS are (row, col, token) spawns.
+     *          Tokens already in play are moved, sheets are placed, unknown chars
+     *          leave a spawn point reserved for them. Tokens on the map that are
+     *          not respawned go back to sheets, links left on walls are removed
+     *---------------------------------------------------------------------------*/
+    pub fn replace_layout(&mut self, map: &str, grid: Vec<Vec<char>>, objects: Vec<(usize, usize, ObjectKind)>, 
+        tokens: Vec<(usize, usize, char)>)
+    {
+        if map != MAIN_MAP && !self.maps.contains_key(map) { self.maps.insert(map.to_string(), Vec::new()); }
+        let stale: Vec<char> = self.characters.iter()
+            .filter(|(key, tok)| tok.map == map && !tokens.iter().any(|t| t.2 == **key))
+            .map(|(key, _)| *key)
+            .collect();
+        for key in stale {
+            let token = self.characters.remove(&key).unwrap();
+            self.sheets.insert(key, token.sheet);
+        }
+        let on_floor = |cell: &Cell| cell.map != map || grid.get(cell.row).and_then(|r| r.get(cell.column)) == Some(&'0');
+        self.links.retain(|link| on_floor(&link.from) && on_floor(&link.to));
+        self.objects.retain(|obj| obj.cell.map != map);
+        *self.board_mut(map).unwrap() = grid;
+
+        for (row, column, kind) in objects {
+            self.objects.push(MapObject { cell: Cell::new(map, row, column), kind });
+        }
+        for (row, column, key) in tokens {
+            if let Some(token) = self.characters.get(&key) {
+                let old = Cell::new(&token.map, token.row, token.column);
+                if old.map != map && self.cell_at(&old) == Some(key) { self.board_mut(&old.map).unwrap()[old.row][old.column] = '0'; }
+            }
+            else if let Some(sheet) = self.sheets.remove(&key) {
+                let initiative = Some(sheet.initiative);
+                self.characters.insert(key, Token { row, column, map: map.to_string(), initiative, sheet, visibility: Default::default() });
+            }
+            else {
+                self.objects.push(MapObject { cell: Cell::new(map, row, column), kind: ObjectKind::Spawn(Some(key)) });
+                continue;
+            }
+            let token = self.characters.get_mut(&key).unwrap();
+            token.map = map.to_string();
+            token.row = row;
+            token.column = column;
+            self.board_mut(map).unwrap()[row][column] = key;
+        }
+    }
+
+    /******************************************************************************
+     *  follow_link - Sends a token through the link it stands on, if any
+     *---------------------------------------------------------------------------*/
+    pub fn follow_link(&mut self, token: char) -> bool
+    {
+        let from = match self.characters.get(&token) {
+            Some(tok) => Cell::new(&tok.map, tok.row, tok.column),
+            None => return false
+        };
+        let dest = match self.link_destination(&from) {
+            Some(dest) => dest,
+            None => return false
+        };
+        if self.cell_at(&dest) != Some('0') { return false; }
+
+        self.board_mut(&from.map).unwrap()[from.row][from.column] = '0';
+        self.board_mut(&dest.map).unwrap()[dest.row][dest.column] = token;
+        let tok = self.characters.get_mut(&token).unwrap();
+        tok.map = dest.map;
+        tok.row = dest.row;
+        tok.column = dest.column;
+        return true;
+    }
+}
@@ -0,0 +1,180 @@
+/*/////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+ *
+ *      Versioned save format for ByteDungeon, older saved games are upgraded step by step when loaded
+ *
+ *      Implementation and Assumptions
+ *          - Saves are JSON documents with a top level "format_version", documents without one are version 0
+ *          - Each migration upgrades a document from version N to N + 1 on the raw JSON, before it is deserialized,
+ *            so migrations never depend on the current shape of the structs
+ *          - Migrations only add or rename data, they must leave a document already in the new shape untouched
+ *          - Fields that have a sensible empty value also use #[serde(default)], a missing list never fails a load
+ *          - Documents from a newer version than this build are refused instead of silently losing data
+ *          - Redacted views (see viewer.rs) look like saves but are refused, they are missing what the DM hides
+ *
+ */////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+
+use std::fmt;
+
+use crate::GameSession;
+use crate::maps::MAIN_MAP;
+use crate::viewer::PROJECTION;
+
+pub const FORMAT_VERSION: u64 = 6;
+
+/***********************************************
+ * Migration - One step of the upgrade chain
+ **********************************************/
+struct Migration
+{
+    name: &'static str,
+    upgrade: fn(&mut Value)                     // Upgrades a document from its index in MIGRATIONS to the next version
+}
+
+const MIGRATIONS: [Migration; FORMAT_VERSION as usize] = [
+    Migration { name: "1: tokens remember the map they are on", upgrade: add_maps },
+    Migration { name: "2: event log, dice generator and session version", upgrade: add_event_log },
+    Migration { name: "3: users, roles and token controllers", upgrade: add_permissions },
+    Migration { name: "4: token visibility and GM notes", upgrade: add_layers },
+    Migration { name: "5: combat log of executed requests", upgrade: add_combat_log },
+    Migration { name: "6: AI behaviours of the DM's tokens", upgrade: add_behaviours }
+];
+
+/***********************************************
+ * LoadError - Saves that couldn't be loaded
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum LoadError
+{
+    NotAnObject,
+    TooNew { found: u64, supported: u64 },
+    Projection,                                 // A view exported for a player or spectator, not a full save
+    Invalid(String)                             // Still doesn't match GameSession after the migrations
+}
+
+impl fmt::Display for LoadError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::NotAnObject => write!(f, "a saved game must be a JSON object"),
+            LoadError::TooNew { found, supported } =>
+                write!(f, "save format {} is newer than the supported format {}", found, supported),
+            LoadError::Projection => write!(f, "this is a view exported for a player or spectator, not a saved game"),
+            LoadError::Invalid(reason) => write!(f, "invalid saved game: {}", reason)
+        }
+    }
+}
+
+impl GameSession
+{
+    /******************************************************************************
+     *  to_save - Serializes the session as a document of the current format
+     *---------------------------------------------------------------------------*/
+    pub fn to_save(&self) -> Value
+    {
+        let mut value = serde_json::to_value(self).unwrap();
+        value["format_version"] = json!(FORMAT_VERSION);
+        return value;
+    }
+}
+
+/******************************************************************************
+ *  load_save - Upgrades a saved document to the current format and loads it
+ *
+ *  RETURN: The session and the names of the migrations that were applied
+ *---------------------------------------------------------------------------*/
+pub fn load_save(mut value: Value) -> Result<(GameSession, Vec<String>), LoadError>
+{
+    let applied = migrate(&mut value)?;
+    let game = serde_json::from_value(value).map_err(|err| LoadError::Invalid(err.to_string()))?;
+    return Ok((game, applied));
+}
+
+/******************************************************************************
+ *  migrate - Runs every migration the document needs, in order
+ *---------------------------------------------------------------------------*/
+pub fn migrate(value: &mut Value) -> Result<Vec<String>, LoadError>
+{
+    if !value.is_object() { return Err(LoadError::NotAnObject); }
+    let found = value.get("format_version").and_then(Value::as_u64).unwrap_or(0);
+    if found > FORMAT_VERSION { return Err(LoadError::TooNew { found, supported: FORMAT_VERSION }); }
+    if value.get(PROJECTION).is_some() { return Err(LoadError::Projection); }
+
+    let mut applied = Vec::new();
+    for migration in &MIGRATIONS[found as usize..] {
+        (migration.upgrade)(value);
+        applied.push(migration.name.to_string());
+    }
+    value.as_object_mut().unwrap().remove("format_version");
+    return Ok(applied);
+}
+
+/******************************************************************************
+ *  add_maps - 0 -> 1, adds the map collections and the map id of each token
+ *---------------------------------------------------------------------------*/
+fn add_maps(value: &mut Value)
+{
+    let fields = value.as_object_mut().unwrap();
+    fields.entry("maps").or_insert(json!({}));
+    fields.entry("links").or_insert(json!([]));
+    fields.entry("objects").or_insert(json!([]));
+    if let Some(Value::Object(characters)) = fields.get_mut("characters") {
+        for token in characters.values_mut() {
+            if let Value::Object(token) = token { token.entry("map").or_insert(json!(MAIN_MAP)); }
+        }
+    }
+}
+
+/******************************************************************************
+ *  add_event_log - 1 -> 2, adds an empty event log, a fresh generator and the
+ *                  session version
+ *---------------------------------------------------------------------------*/
+fn add_event_log(value: &mut Value)
+{
+    let fields = value.as_object_mut().unwrap();
+    fields.entry("events").or_insert(json!([]));
+    fields.entry("rng").or_insert(json!({ "state": 0 }));
+    fields.entry("version").or_insert(json!(0));
+}
+
+/******************************************************************************
+ *  add_permissions - 2 -> 3, adds an empty permission model (no users, every
+ *                    request is local)
+ *---------------------------------------------------------------------------*/
+fn add_permissions(value: &mut Value)
+{
+    let fields = value.as_object_mut().unwrap();
+    fields.entry("permissions").or_insert(json!({ "roles": {}, "controllers": {}, "withheld": {} }));
+}
+
+/******************************************************************************
+ *  add_layers - 3 -> 4, makes every token visible and adds the GM notes
+ *---------------------------------------------------------------------------*/
+fn add_layers(value: &mut Value)
+{
+    let fields = value.as_object_mut().unwrap();
+    fields.entry("notes").or_insert(json!([]));
+    if let Some(Value::Object(characters)) = fields.get_mut("characters") {
+        for token in characters.values_mut() {
+            if let Value::Object(token) = token { token.entry("visibility").or_insert(json!("Visible")); }
+        }
+    }
+}
+
+/******************************************************************************
+ *  add_combat_log - 4 -> 5, adds an empty combat log
+ *---------------------------------------------------------------------------*/
+fn add_combat_log(value: &mut Value)
+{
+    value.as_object_mut().unwrap().entry("combat_log").or_insert(json!([]));
+}
+
+/******************************************************************************
+ *  add_behaviours - 5 -> 6, no token has an AI behaviour set yet
+ *---------------------------------------------------------------------------*/
+fn add_behaviours(value: &mut Value)
+{
+    value.as_object_mut().unwrap().entry("behaviours").or_insert(json!({}));
+}
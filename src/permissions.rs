@@ -0,0 +1,319 @@
+/*/////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+ *
+ *      Permission model for ByteDungeon, who is in a session and what they may do with which token
+ *
+ *      Implementation and Assumptions
+ *          - Users are the ids clients join with (see protocol.rs), each user has one role:
+ *              host        runs the session and is the only one that changes permissions
+ *              co-dm       acts for every token like the host, but can't change permissions
+ *              player      acts only for the tokens they control, with the actions they weren't denied
+ *              spectator   watches, never acts
+ *          - A token can have several controllers (shared control) and a player can control several tokens
+ *          - Actions are the request types, players may do all of them unless the host withholds some
+ *          - Requests carry the user that made them, a request without a user comes from the local session (the
+ *            DM's own client, the terminal) and is always allowed
+ *          - Permissions are game state: saved, synced and changed through commands (see history.rs), so changes
+ *            are undoable and replayed like any other edit
+ *          - Collections are ordered so permissions serialize (and hash, see checksum.rs) the same on every client
+ *
+ */////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+use serde::{Serialize, Deserialize};
+
+use std::fmt;
+use std::str::FromStr;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::Request;
+
+/***********************************************
+ * Role - What a user is in the session
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(ts_rs::TS))]
+#[serde(rename_all = "kebab-case")]
+pub enum Role
+{
+    Host,
+    CoDm,
+    Player,
+    Spectator
+}
+
+/***********************************************
+ * Action - Kind of request a user may make
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(ts_rs::TS))]
+#[serde(rename_all = "lowercase")]
+pub enum Action
+{
+    Move,                                       // Request action types 0 to 3, in order
+    Item,
+    Ability,
+    Unequip
+}
+
+/***********************************************
+ * Permissions - Users, roles and token control
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(ts_rs::TS))]
+#[serde(default)]
+pub struct Permissions
+{
+    pub roles: BTreeMap<String, Role>,                  // Role of every user in the session
+    pub controllers: BTreeMap<char, BTreeSet<String>>,  // Players acting for each token
+    pub withheld: BTreeMap<String, BTreeSet<Action>>    // Actions a player isn't allowed to do
+}
+
+/***********************************************
+ * PermissionError - Refused actions and edits
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum PermissionError
+{
+    UnknownUser(String),
+    UnknownRole(String),
+    UnknownAction(String),
+    Spectator(String),                          // Spectators never act
+    NotController { user: String, token: char },
+    Withheld { user: String, action: Action },
+    LastHost                                    // The change would leave the session without a host
+}
+
+impl fmt::Display for PermissionError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PermissionError::UnknownUser(user) => write!(f, "'{}' is not in this session", user),
+            PermissionError::UnknownRole(role) => write!(f, "no role named '{}'", role),
+            PermissionError::UnknownAction(action) => write!(f, "no action named '{}'", action),
+            PermissionError::Spectator(user) => write!(f, "'{}' is only watching", user),
+            PermissionError::NotController { user, token } => write!(f, "'{}' doesn't control {}", user, token),
+            PermissionError::Withheld { user, action } => write!(f, "'{}' isn't allowed to {}", user, action),
+            PermissionError::LastHost => write!(f, "the session needs a host")
+        }
+    }
+}
+
+impl fmt::Display for Role
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Role::Host => write!(f, "host"),
+            Role::CoDm => write!(f, "co-dm"),
+            Role::Player => write!(f, "player"),
+            Role::Spectator => write!(f, "spectator")
+        }
+    }
+}
+
+impl FromStr for Role
+{
+    type Err = PermissionError;
+
+    fn from_str(s: &str) -> Result<Role, PermissionError> {
+        match s {
+            "host" => Ok(Role::Host),
+            "co-dm" => Ok(Role::CoDm),
+            "player" => Ok(Role::Player),
+            "spectator" => Ok(Role::Spectator),
+            _ => Err(PermissionError::UnknownRole(s.to_string()))
+        }
+    }
+}
+
+impl fmt::Display for Action
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Action::Move => write!(f, "move"),
+            Action::Item => write!(f, "use items"),
+            Action::Ability => write!(f, "use abilities"),
+            Action::Unequip => write!(f, "unequip")
+        }
+    }
+}
+
+impl FromStr for Action
+{
+    type Err = PermissionError;
+
+    fn from_str(s: &str) -> Result<Action, PermissionError> {
+        match s {
+            "move" => Ok(Action::Move),
+            "item" => Ok(Action::Item),
+            "ability" => Ok(Action::Ability),
+            "unequip" => Ok(Action::Unequip),
+            _ => Err(PermissionError::UnknownAction(s.to_string()))
+        }
+    }
+}
+
+impl Action
+{
+    /******************************************************************************
+     *  of - Action of a request type, None for unknown types
+     *---------------------------------------------------------------------------*/
+    pub fn of(action_type: i32) -> Option<Action>
+    {
+        match action_type {
+            0 => Some(Action::Move),
+            1 => Some(Action::Item),
+            2 => Some(Action::Ability),
+            3 => Some(Action::Unequip),
+            _ => None
+        }
+    }
+}
+
+impl Role
+{
+    /******************************************************************************
+     *  is_dm - True for the roles that act for every token (host and co-DM)
+     *---------------------------------------------------------------------------*/
+    pub fn is_dm(self) -> bool
+    {
+        self == Role::Host || self == Role::CoDm
+    }
+}
+
+impl Permissions
+{
+    /******************************************************************************
+     *  role - Role of USER, None if they aren't in the session
+     *---------------------------------------------------------------------------*/
+    pub fn role(&self, user: &str) -> Option<Role>
+    {
+        self.roles.get(user).cloned()
+    }
+
+    /******************************************************************************
+     *  is_dm - True if USER is the host or a co-DM
+     *---------------------------------------------------------------------------*/
+    pub fn is_dm(&self, user: &str) -> bool
+    {
+        self.role(user).is_some_and(Role::is_dm)
+    }
+
+    /******************************************************************************
+     *  tokens_of - Tokens USER controls, sorted
+     *---------------------------------------------------------------------------*/
+    pub fn tokens_of(&self, user: &str) -> Vec<char>
+    {
+        self.controllers.iter().filter(|(_, users)| users.contains(user)).map(|(token, _)| *token).collect()
+    }
+
+    /******************************************************************************
+     *  controllers_of - Players controlling TOKEN, sorted
+     *---------------------------------------------------------------------------*/
+    pub fn controllers_of(&self, token: char) -> Vec<String>
+    {
+        self.controllers.get(&token).map_or(Vec::new(), |users| users.iter().cloned().collect())
+    }
+
+    /******************************************************************************
+     *  allows - Tells if USER may do ACTION with TOKEN
+     *
+     *  PARAMS: USER is None for the local session, which may do anything
+     *---------------------------------------------------------------------------*/
+    pub fn allows(&self, user: Option<&str>, token: char, action: Action) -> Result<(), PermissionError>
+    {
+        let user = match user {
+            Some(user) => user,
+            None => return Ok(())
+        };
+        match self.role(user) {
+            None => Err(PermissionError::UnknownUser(user.to_string())),
+            Some(Role::Host) | Some(Role::CoDm) => Ok(()),
+            Some(Role::Spectator) => Err(PermissionError::Spectator(user.to_string())),
+            Some(Role::Player) => {
+                if !self.controllers.get(&token).is_some_and(|users| users.contains(user)) {
+                    return Err(PermissionError::NotController { user: user.to_string(), token });
+                }
+                if self.withheld.get(user).is_some_and(|actions| actions.contains(&action)) {
+                    return Err(PermissionError::Withheld { user: user.to_string(), action });
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /******************************************************************************
+     *  check - Tells if the user of a request may make it, requests of an unknown
+     *          type are left to the rules
+     *---------------------------------------------------------------------------*/
+    pub fn check(&self, req: &Request) -> Result<(), PermissionError>
+    {
+        match Action::of(req.action_type) {
+            Some(action) => self.allows(req.user.as_deref(), req.caster, action),
+            None => Ok(())
+        }
+    }
+
+    /******************************************************************************
+     *  set_role - Adds USER with ROLE, changes their role or removes them (None),
+     *             a user removed or made a spectator loses their tokens
+     *---------------------------------------------------------------------------*/
+    pub fn set_role(&mut self, user: &str, role: Option<Role>) -> Result<(), PermissionError>
+    {
+        let hosts = self.roles.values().filter(|role| **role == Role::Host).count();
+        if self.role(user) == Some(Role::Host) && role != Some(Role::Host) && hosts == 1 { return Err(PermissionError::LastHost); }
+        match role {
+            Some(role) => { self.roles.insert(user.to_string(), role); },
+            None => { self.roles.remove(user); }
+        }
+        if role.is_none() || role == Some(Role::Spectator) {
+            for users in self.controllers.values_mut() { users.remove(user); }
+            self.controllers.retain(|_, users| !users.is_empty());
+            self.withheld.remove(user);
+        }
+        return Ok(());
+    }
+
+    /******************************************************************************
+     *  grant - Lets USER act for TOKEN, along with its other controllers
+     *---------------------------------------------------------------------------*/
+    pub fn grant(&mut self, user: &str, token: char) -> Result<(), PermissionError>
+    {
+        self.can_act(user)?;
+        self.controllers.entry(token).or_default().insert(user.to_string());
+        return Ok(());
+    }
+
+    /******************************************************************************
+     *  revoke - Takes TOKEN away from USER
+     *---------------------------------------------------------------------------*/
+    pub fn revoke(&mut self, user: &str, token: char) -> Result<(), PermissionError>
+    {
+        let users = self.controllers.get_mut(&token)
+            .filter(|users| users.contains(user))
+            .ok_or_else(|| PermissionError::NotController { user: user.to_string(), token })?;
+        users.remove(user);
+        if users.is_empty() { self.controllers.remove(&token); }
+        return Ok(());
+    }
+
+    /******************************************************************************
+     *  set_allowed - Allows or withholds an action from USER, only players are
+     *                held to it
+     *---------------------------------------------------------------------------*/
+    pub fn set_allowed(&mut self, user: &str, action: Action, allowed: bool) -> Result<(), PermissionError>
+    {
+        self.can_act(user)?;
+        let actions = self.withheld.entry(user.to_string()).or_default();
+        if allowed { actions.remove(&action); } else { actions.insert(action); }
+        if actions.is_empty() { self.withheld.remove(user); }
+        return Ok(());
+    }
+
+    fn can_act(&self, user: &str) -> Result<(), PermissionError>
+    {
+        match self.role(user) {
+            Some(Role::Spectator) => Err(PermissionError::Spectator(user.to_string())),
+            Some(_) => Ok(()),
+            None => Err(PermissionError::UnknownUser(user.to_string()))
+        }
+    }
+}
@@ -0,0 +1,231 @@
+/*/////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+ *
+ *      Network protocol of ByteDungeon, the messages exchanged by the clients and the game server
+ *
+ *      Implementation and Assumptions
+ *          - Messages are JSON objects tagged by "type", wrapped in an envelope that adds the protocol version "v"
+ *            and an optional request "id" (ie: {"v":1,"id":7,"type":"approveRequest","index":0})
+ *          - A message with an id is answered with an ack or a transactionFailed carrying the same id, messages
+ *            pushed by the server have no id
+ *          - Envelopes of another protocol version are refused, PROTOCOL_VERSION is bumped on any breaking change
+ *          - The same types are used by the server (server.rs), the JS client (encode/decode bindings) and the
+ *            TypeScript typings generated from them (byte-dungeon typescript > webfiles/protocol.d.ts)
+ *
+ */////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+#[cfg(not(target_arch = "wasm32"))]
+use ts_rs::TS;
+
+use std::fmt;
+
+use crate::Request;
+use crate::sync::SessionDelta;
+use crate::permissions::{Role, Action};
+use crate::resolve::{Policy, Outcome};
+use crate::chat::ChatMessage;
+use crate::ai::Behaviour;
+use crate::maps::Cell;
+use crate::layers::{Visibility, MapNote};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{Token, Character, Item, Ability, Effect};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::maps::{MapLink, MapObject, ObjectKind};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::sync::TokenDelta;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::permissions::Permissions;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::resolve::{Conflict, Resolution};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::chat::{ChatKind, DiceRoll, RolledTerm, Term};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::rng::Rng;
+
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/***********************************************
+ * Envelope - Versioned message with an id
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Envelope<T>
+{
+    pub v: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    #[serde(flatten)]
+    pub message: T
+}
+
+/***********************************************
+ * ClientMessage - What clients send
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(TS))]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ClientMessage
+{
+    StartHosting {
+        room: String,
+        user: String,
+        name: String,
+        #[serde(default)]
+        set: String,                            // Id of the game set, only passed back to joining clients
+        #[serde(default)]
+        #[cfg_attr(not(target_arch = "wasm32"), ts(optional))]
+        game: Option<Value>                     // Saved game to start from, an empty session if missing
+    },
+    FindSession {
+        room: String,
+        user: String,
+        name: String,
+        #[serde(default)]
+        #[cfg_attr(not(target_arch = "wasm32"), ts(optional))]
+        secret: Option<String>                  // Secret handed out when USER first joined, see server.rs
+    },
+    RequestAccess { token: char },
+    GetAccessRequests,
+    GrantAccess { index: usize },               // Index in the access requests
+    RemoveAccess { index: usize },
+    StartTurn,
+    AddTurn { requests: Vec<Request> },
+    EndTurn,
+    ApproveRequest { index: usize },            // Index in the pending requests
+    RemoveRequest { index: usize },
+    EmitRollRequest { index: usize },
+    Roll20 { index: usize },
+    BroadcastLog { message: String },
+    Chat { text: String },                      // Chat line with commands and inline rolls, see chat.rs
+    SetRole { user: String, role: Option<Role> },   // Host only, a null role removes the user
+    GrantToken { user: String, token: char },
+    RevokeToken { user: String, token: char },
+    SetAllowed { user: String, action: Action, allowed: bool },
+    SetPolicy { policy: Policy },               // How conflicts between the requests of a turn are settled, see resolve.rs
+    SetBehaviour { token: char, behaviour: Option<Behaviour> },     // How the AI plays a token, null for the default
+    AutoPlay,                                   // Queue the requests the AI proposes for the monsters, see ai.rs
+    SetVisibility { token: char, visibility: Visibility },  // Who sees a token, see layers.rs
+    AddNote { note: MapNote },                  // GM note pinned on a cell, never sent to players
+    RemoveNotes { cell: Cell },                 // Every GM note pinned on the cell
+    Detect { token: char, by: char }            // Detection check of BY against the invisible TOKEN
+}
+
+/***********************************************
+ * ServerMessage - What the server sends
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(TS))]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ServerMessage
+{
+    StartSession { room: String, secret: String },  // SECRET is needed to rejoin as the same user
+    HostRejoin { room: String, set: String, secret: String, game: Value },
+    JoinGame { room: String, set: String, secret: String, tokens: Vec<char>, game: Value },
+    TransactionFailed { reason: String },       // The message with the same id was refused
+    SocketLog { message: String },
+    Chat { from: String, message: ChatMessage },    // Display name of the speaker, whispers only go to both ends
+    LoadAccessRequests { requests: Vec<AccessRequest> },
+    AddTokenAccess { tokens: Vec<char> },       // Tokens the player may queue requests for, empty between turns
+    GrantAllAccess,
+    LoadRequests { requests: Vec<Pending> },    // Requests waiting for the host, in initiative order
+    Resolved { outcomes: Vec<Outcome> },        // How the requests of the turn that ended were resolved
+    EnableRoll { index: usize, request: Request },
+    Ack,                                        // The message with the same id was handled
+    ExecuteRequest { request: Request },
+    Sync { delta: Box<SessionDelta> },
+    View { game: Value }                        // Redacted game sent to spectators instead of syncs, see viewer.rs
+}
+
+/***********************************************
+ * AccessRequest - Player asking for a token
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(TS))]
+pub struct AccessRequest
+{
+    pub user: String,
+    pub name: String,
+    pub token: char
+}
+
+/***********************************************
+ * Pending - Request waiting for the host
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(TS))]
+pub struct Pending
+{
+    pub user: String,                           // Player that controls the caster, rolls go to them
+    pub request: Request
+}
+
+/***********************************************
+ * ProtocolError - Messages that couldn't be read
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ProtocolError
+{
+    Malformed(String),
+    Version { found: u64, supported: u32 }
+}
+
+impl fmt::Display for ProtocolError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProtocolError::Malformed(reason) => write!(f, "invalid message: {}", reason),
+            ProtocolError::Version { found, supported } =>
+                write!(f, "protocol version {} is not supported, expected {}", found, supported)
+        }
+    }
+}
+
+impl<T> Envelope<T>
+{
+    pub fn new(message: T, id: Option<u64>) -> Envelope<T>
+    {
+        Envelope { v: PROTOCOL_VERSION, id, message }
+    }
+}
+
+/******************************************************************************
+ *  encode - Wraps a message in an envelope of the current version, as JSON
+ *---------------------------------------------------------------------------*/
+pub fn encode<T: Serialize>(message: T, id: Option<u64>) -> String
+{
+    return serde_json::to_string(&Envelope::new(message, id)).unwrap();
+}
+
+/******************************************************************************
+ *  decode - Reads an envelope, checking its version before the message
+ *---------------------------------------------------------------------------*/
+pub fn decode<T: DeserializeOwned>(text: &str) -> Result<Envelope<T>, ProtocolError>
+{
+    let value: Value = serde_json::from_str(text).map_err(|err| ProtocolError::Malformed(err.to_string()))?;
+    let found = value.get("v").and_then(Value::as_u64).ok_or_else(|| ProtocolError::Malformed("missing protocol version".to_string()))?;
+    if found != PROTOCOL_VERSION as u64 { return Err(ProtocolError::Version { found, supported: PROTOCOL_VERSION }); }
+    return serde_json::from_value(value).map_err(|err| ProtocolError::Malformed(err.to_string()));
+}
+
+/******************************************************************************
+ *  typescript - TypeScript declarations of every message and what they carry
+ *---------------------------------------------------------------------------*/
+#[cfg(not(target_arch = "wasm32"))]
+pub fn typescript() -> String
+{
+    let cfg = ts_rs::Config::new().with_large_int("number");
+    let decls = [
+        ClientMessage::decl(&cfg), ServerMessage::decl(&cfg), AccessRequest::decl(&cfg), Pending::decl(&cfg),
+        Request::decl(&cfg), SessionDelta::decl(&cfg), TokenDelta::decl(&cfg), Token::decl(&cfg), Character::decl(&cfg),
+        Item::decl(&cfg), Ability::decl(&cfg), Effect::decl(&cfg), Cell::decl(&cfg), MapLink::decl(&cfg),
+        MapObject::decl(&cfg), ObjectKind::decl(&cfg), Permissions::decl(&cfg), Role::decl(&cfg), Action::decl(&cfg),
+        Visibility::decl(&cfg), MapNote::decl(&cfg), Policy::decl(&cfg), Outcome::decl(&cfg), Conflict::decl(&cfg),
+        Resolution::decl(&cfg), ChatMessage::decl(&cfg), ChatKind::decl(&cfg), DiceRoll::decl(&cfg), RolledTerm::decl(&cfg),
+        Term::decl(&cfg), Behaviour::decl(&cfg), Rng::decl(&cfg), Value::decl(&cfg)
+    ];
+    let mut result = format!("// Generated by `byte-dungeon typescript`, do not edit\n\nexport const PROTOCOL_VERSION = {};\n\n", PROTOCOL_VERSION);
+    result += "export type Envelope<T> = { v: number, id?: number } & T;\n\n";
+    for decl in decls.iter() { result += &format!("export {}\n\n", decl); }
+    return result;
+}
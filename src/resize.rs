@@ -0,0 +1,269 @@
+/*/////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+ *
+ *      Anchored board resizing for ByteDungeon, keeps tokens, objects and links on the cells they were on
+ *
+ *      Implementation and Assumptions
+ *          - The anchor is the edge (or center) that stays in place, rows/columns are added or removed on the others
+ *          - Every token, object and link on the resized map is translated by the same offset as the cells
+ *          - Tokens that would fall off follow an OffBoard policy, objects and links that fall off are removed
+ *          - A size of zero rows or columns leaves an empty grid (no rows at all)
+ *          - Sizes larger than the generator's MAX_CELLS are refused before anything is allocated
+ *
+ */////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+use serde::{Serialize, Deserialize};
+
+use std::fmt;
+use std::str::FromStr;
+use std::collections::VecDeque;
+
+use crate::GameSession;
+use crate::maps::Cell;
+use crate::generator::MAX_CELLS;
+
+/***********************************************
+ * Anchor - Part of the board that stays fixed
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Anchor
+{
+    #[default]
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight
+}
+
+/***********************************************
+ * OffBoard - What to do with tokens cut off
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum OffBoard
+{
+    Reject,                                     // Fail the resize, nothing changes
+    Nearest,                                    // Move the token to the nearest free floor cell
+    #[default]
+    ReturnToSheets                              // Take the token off the board, its sheet stays in the game
+}
+
+/***********************************************
+ * ResizeReport - What happened to the board
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ResizeReport
+{
+    pub moved: Vec<(char, usize, usize)>,       // Tokens bumped to the nearest free cell
+    pub returned: Vec<char>,                    // Tokens sent back to sheets
+    pub removed_objects: usize,
+    pub removed_links: usize
+}
+
+/***********************************************
+ * ResizeError - Resizes that were refused
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ResizeError
+{
+    UnknownMap(String),
+    UnknownAnchor(String),
+    UnknownPolicy(String),
+    TokensOffBoard(Vec<char>),                  // Reject policy, these tokens would fall off
+    NoFreeCell(char),                           // Nearest policy, the new board has no room left for this token
+    TooLarge { rows: usize, cols: usize }       // More than MAX_CELLS cells
+}
+
+impl FromStr for Anchor
+{
+    type Err = ResizeError;
+
+    fn from_str(s: &str) -> Result<Anchor, ResizeError> {
+        match s.to_lowercase().replace(['_', ' '], "-").as_str() {
+            "top-left" => Ok(Anchor::TopLeft),
+            "top" => Ok(Anchor::Top),
+            "top-right" => Ok(Anchor::TopRight),
+            "left" => Ok(Anchor::Left),
+            "center" | "centre" => Ok(Anchor::Center),
+            "right" => Ok(Anchor::Right),
+            "bottom-left" => Ok(Anchor::BottomLeft),
+            "bottom" => Ok(Anchor::Bottom),
+            "bottom-right" => Ok(Anchor::BottomRight),
+            _ => Err(ResizeError::UnknownAnchor(s.to_string()))
+        }
+    }
+}
+
+impl FromStr for OffBoard
+{
+    type Err = ResizeError;
+
+    fn from_str(s: &str) -> Result<OffBoard, ResizeError> {
+        match s.to_lowercase().replace(['_', ' '], "-").as_str() {
+            "reject" => Ok(OffBoard::Reject),
+            "nearest" => Ok(OffBoard::Nearest),
+            "return" | "return-to-sheets" | "sheets" => Ok(OffBoard::ReturnToSheets),
+            _ => Err(ResizeError::UnknownPolicy(s.to_string()))
+        }
+    }
+}
+
+impl fmt::Display for ResizeError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ResizeError::UnknownMap(map) => write!(f, "no map named '{}'", map),
+            ResizeError::UnknownAnchor(anchor) => write!(f, "unknown anchor '{}'", anchor),
+            ResizeError::UnknownPolicy(policy) => write!(f, "unknown off board policy '{}'", policy),
+            ResizeError::TokensOffBoard(tokens) =>
+                write!(f, "tokens {} would fall off the board", tokens.iter().collect::<String>()),
+            ResizeError::NoFreeCell(token) => write!(f, "no free cell left for token '{}'", token),
+            ResizeError::TooLarge { rows, cols } =>
+                write!(f, "map of {}x{} is too large, at most {} cells are allowed", rows, cols, MAX_CELLS)
+        }
+    }
+}
+
+impl Anchor
+{
+    /******************************************************************************
+     *  offset - Row/column shift of the old cells when growing by the deltas
+     *---------------------------------------------------------------------------*/
+    fn offset(self, row_delta: i64, col_delta: i64) -> (i64, i64)
+    {
+        let row = match self {
+            Anchor::TopLeft | Anchor::Top | Anchor::TopRight => 0,
+            Anchor::Left | Anchor::Center | Anchor::Right => row_delta / 2,
+            Anchor::BottomLeft | Anchor::Bottom | Anchor::BottomRight => row_delta
+        };
+        let col = match self {
+            Anchor::TopLeft | Anchor::Left | Anchor::BottomLeft => 0,
+            Anchor::Top | Anchor::Center | Anchor::Bottom => col_delta / 2,
+            Anchor::TopRight | Anchor::Right | Anchor::BottomRight => col_delta
+        };
+        return (row, col);
+    }
+}
+
+impl GameSession
+{
+    /******************************************************************************
+     *  resize_map - Resizes a map around the anchor and translates everything
+     *               on it, tokens falling off follow POLICY
+     *---------------------------------------------------------------------------*/
+    pub fn resize_map(&mut self, map: &str, rows: usize, cols: usize, anchor: Anchor, policy: OffBoard)
+        -> Result<ResizeReport, ResizeError>
+    {
+        let old = self.board(map).ok_or_else(|| ResizeError::UnknownMap(map.to_string()))?;
+        if rows.saturating_mul(cols) > MAX_CELLS { return Err(ResizeError::TooLarge { rows, cols }); }
+        let old_rows = if old.iter().all(|row| row.is_empty()) { 0 } else { old.len() };
+        let old_cols = if old_rows == 0 { 0 } else { old[0].len() };
+        let (rows, cols) = if rows == 0 || cols == 0 { (0, 0) } else { (rows, cols) };
+        let (dr, dc) = anchor.offset(rows as i64 - old_rows as i64, cols as i64 - old_cols as i64);
+        let shift = |row: usize, col: usize| -> Option<(usize, usize)> {
+            let (r, c) = (row as i64 + dr, col as i64 + dc);
+            if r < 0 || c < 0 || r >= rows as i64 || c >= cols as i64 { return None; }
+            Some((r as usize, c as usize))
+        };
+
+        let mut grid = vec![vec!['0'; cols]; rows];
+        for (row, cells) in old.iter().enumerate() {
+            for (col, c) in cells.iter().enumerate() {
+                if let Some((r, c2)) = shift(row, col) { grid[r][c2] = *c; }
+            }
+        }
+        let mut off_board: Vec<char> = self.characters.iter()
+            .filter(|(_, tok)| tok.map == map && shift(tok.row, tok.column).is_none())
+            .map(|(key, _)| *key)
+            .collect();
+        off_board.sort();
+        if policy == OffBoard::Reject && !off_board.is_empty() { return Err(ResizeError::TokensOffBoard(off_board)); }
+
+        let mut report = ResizeReport::default();
+        let mut bumped = Vec::new();
+        for key in &off_board {
+            let token = &self.characters[key];
+            if policy == OffBoard::ReturnToSheets { report.returned.push(*key); continue; }
+            if rows == 0 { return Err(ResizeError::NoFreeCell(*key)); }
+            let row = (token.row as i64 + dr).clamp(0, rows as i64 - 1) as usize;
+            let col = (token.column as i64 + dc).clamp(0, cols as i64 - 1) as usize;
+            match nearest_free(&grid, row, col) {
+                Some((row, col)) => {
+                    grid[row][col] = *key;
+                    bumped.push((*key, row, col));
+                },
+                None => return Err(ResizeError::NoFreeCell(*key))
+            }
+        }
+
+        for key in &report.returned {
+            let token = self.characters.remove(key).unwrap();
+            self.sheets.insert(*key, token.sheet);
+        }
+        for token in self.characters.values_mut().filter(|tok| tok.map == map) {
+            if let Some((row, col)) = shift(token.row, token.column) {
+                token.row = row;
+                token.column = col;
+            }
+        }
+        for (key, row, col) in bumped {
+            let token = self.characters.get_mut(&key).unwrap();
+            token.row = row;
+            token.column = col;
+            report.moved.push((key, row, col));
+        }
+
+        let objects = self.objects.len();
+        self.objects.retain_mut(|obj| obj.cell.map != map || move_cell(&mut obj.cell, &shift));
+        report.removed_objects = objects - self.objects.len();
+        let links = self.links.len();
+        self.links.retain_mut(|link| {
+            let from = link.from.map != map || move_cell(&mut link.from, &shift);
+            let to = link.to.map != map || move_cell(&mut link.to, &shift);
+            from && to
+        });
+        report.removed_links = links - self.links.len();
+
+        *self.board_mut(map).unwrap() = grid;
+        return Ok(report);
+    }
+}
+
+/******************************************************************************
+ *  move_cell - Shifts a cell in place, false if it fell off the board
+ *---------------------------------------------------------------------------*/
+fn move_cell(cell: &mut Cell, shift: &dyn Fn(usize, usize) -> Option<(usize, usize)>) -> bool
+{
+    match shift(cell.row, cell.column) {
+        Some((row, col)) => {
+            cell.row = row;
+            cell.column = col;
+            true
+        },
+        None => false
+    }
+}
+
+/******************************************************************************
+ *  nearest_free - Breadth first search for the closest '0' cell
+ *---------------------------------------------------------------------------*/
+fn nearest_free(grid: &[Vec<char>], row: usize, col: usize) -> Option<(usize, usize)>
+{
+    let mut seen = vec![vec![false; grid[0].len()]; grid.len()];
+    let mut queue = VecDeque::from(vec![(row, col)]);
+    seen[row][col] = true;
+    while let Some((r, c)) = queue.pop_front() {
+        if grid[r][c] == '0' { return Some((r, c)); }
+        let neighbors = [(r.wrapping_sub(1), c), (r + 1, c), (r, c.wrapping_sub(1)), (r, c + 1)];
+        for (nr, nc) in neighbors {
+            if nr < grid.len() && nc < grid[0].len() && !seen[nr][nc] {
+                seen[nr][nc] = true;
+                queue.push_back((nr, nc));
+            }
+        }
+    }
+    return None;
+}
@@ -0,0 +1,274 @@
+/*/////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+ *
+ *      Conflict resolution for the requests of a simultaneous turn, before the DM replays them
+ *
+ *      Implementation and Assumptions
+ *          - Requests are resolved in the order they are queued, sort_requests first so the order is initiative
+ *            (ties keep the order the requests were queued in)
+ *          - Resolving plays the requests on a copy of the session, each request meets the board the earlier ones
+ *            left behind, the session itself is never changed (and nothing is logged or rolled)
+ *          - Conflicts:
+ *              same destination    another request moves a token to the same cell
+ *              blocked             the destination can no longer be reached because a token moved in the way
+ *              out of range        a target of an ability moved away
+ *              target dead         a target has no hitpoints left or is no longer on a map
+ *              caster down         the caster itself has no hitpoints left or is no longer on a map
+ *          - Policies, for moves:
+ *              initiative-wins     the earlier request keeps the cell, later ones are cancelled
+ *              bump                later requests move to the reachable cell nearest their destination instead
+ *              cancel              every request involved in a conflict is cancelled, earlier ones included
+ *          - Abilities drop the targets that are dead or out of range and go on with the others (cancelled if none
+ *            remain, or with the cancel policy), requests of a downed caster are always cancelled
+ *          - Requests the rules refuse for another reason (ie: the item was used up) are kept as they are, they
+ *            are refused when the DM approves them (see check_request) and are skipped while resolving
+ *
+ */////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+use serde::{Serialize, Deserialize};
+
+use std::fmt;
+use std::str::FromStr;
+use std::collections::HashMap;
+
+use crate::{GameSession, Request, copy_session, get_action_range};
+
+type Destinations = HashMap<(String, usize, usize), Vec<char>>;   // Casters by the map cell they move to
+
+/***********************************************
+ * Policy - How conflicting requests are settled
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(ts_rs::TS))]
+#[serde(rename_all = "kebab-case")]
+pub enum Policy
+{
+    #[default]
+    InitiativeWins,
+    Bump,
+    Cancel
+}
+
+/***********************************************
+ * Conflict - Why a request can't go as queued
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(ts_rs::TS))]
+pub enum Conflict
+{
+    SameDestination { with: char },             // Token of the other request
+    Blocked,
+    OutOfRange { target: char },
+    TargetDead { target: char },
+    CasterDown
+}
+
+/***********************************************
+ * Resolution - What becomes of a request
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(ts_rs::TS))]
+pub enum Resolution
+{
+    Kept,
+    Bumped { cell: (usize, usize) },            // Moves there instead
+    Retargeted { dropped: Vec<char> },          // Goes on without these targets
+    Cancelled
+}
+
+/***********************************************
+ * Outcome - Resolution of one queued request
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(ts_rs::TS))]
+pub struct Outcome
+{
+    pub request: Request,                       // As it should be executed (bumped cell, remaining targets)
+    pub conflicts: Vec<Conflict>,
+    pub resolution: Resolution
+}
+
+impl fmt::Display for Policy
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Policy::InitiativeWins => write!(f, "initiative-wins"),
+            Policy::Bump => write!(f, "bump"),
+            Policy::Cancel => write!(f, "cancel")
+        }
+    }
+}
+
+impl FromStr for Policy
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Policy, String> {
+        match s {
+            "initiative-wins" => Ok(Policy::InitiativeWins),
+            "bump" => Ok(Policy::Bump),
+            "cancel" => Ok(Policy::Cancel),
+            _ => Err(format!("no conflict policy named '{}'", s))
+        }
+    }
+}
+
+impl fmt::Display for Conflict
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Conflict::SameDestination { with } => write!(f, "same destination as {}", with),
+            Conflict::Blocked => write!(f, "the path is blocked"),
+            Conflict::OutOfRange { target } => write!(f, "{} is out of range", target),
+            Conflict::TargetDead { target } => write!(f, "{} is dead", target),
+            Conflict::CasterDown => write!(f, "the caster is down")
+        }
+    }
+}
+
+impl fmt::Display for Resolution
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Resolution::Kept => write!(f, "kept"),
+            Resolution::Bumped { cell } => write!(f, "bumped to {},{}", cell.0, cell.1),
+            Resolution::Retargeted { dropped } => write!(f, "kept without {}", dropped.iter().collect::<String>()),
+            Resolution::Cancelled => write!(f, "cancelled")
+        }
+    }
+}
+
+impl fmt::Display for Outcome
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "The request of {} was {}", self.request.caster, self.resolution)?;
+        if self.conflicts.is_empty() { return Ok(()); }
+        let reasons: Vec<String> = self.conflicts.iter().map(|conflict| conflict.to_string()).collect();
+        write!(f, " ({})", reasons.join(", "))
+    }
+}
+
+impl GameSession
+{
+    /******************************************************************************
+     *  resolve_requests - Settles the conflicts between the queued requests with
+     *                     POLICY, see header
+     *
+     *  RETURN: The outcome of every queued request, in queue order
+     *---------------------------------------------------------------------------*/
+    pub fn resolve_requests(&self, policy: Policy) -> Vec<Outcome>
+    {
+        return self.resolve_queue(&self.requests, policy);
+    }
+
+    /******************************************************************************
+     *  resolve_queue - Same as resolve_requests for the requests of QUEUE, that
+     *                  were taken out of the session (ie: by the server)
+     *---------------------------------------------------------------------------*/
+    pub fn resolve_queue(&self, queue: &[(char, Vec<Request>)], policy: Policy) -> Vec<Outcome>
+    {
+        let queued: Vec<Request> = queue.iter().flat_map(|(_, requests)| requests.iter().cloned()).collect();
+        let mut destinations = Destinations::new();
+        for req in queued.iter().filter(|req| req.action_type == 0) {
+            if let (Some(token), Some((row, col))) = (self.characters.get(&req.caster), req.target_cell) {
+                destinations.entry((token.map.clone(), row, col)).or_default().push(req.caster);
+            }
+        }
+
+        let mut scratch = copy_session(self);
+        let mut claimed = HashMap::new();
+        let mut outcomes = Vec::new();
+        for req in queued {
+            let outcome = scratch.resolve_one(req, policy, &destinations, &claimed);
+            if outcome.resolution == Resolution::Cancelled { outcomes.push(outcome); continue; }
+            let req = &outcome.request;
+            if scratch.check_request(req).is_ok() {
+                if let (0, Some((row, col))) = (req.action_type, req.target_cell) {
+                    claimed.insert((scratch.characters[&req.caster].map.clone(), row, col), req.caster);
+                }
+                let _ = scratch.execute_request(req.caster, req);
+            }
+            outcomes.push(outcome);
+        }
+        return outcomes;
+    }
+
+    /******************************************************************************
+     *  resolve_one - Resolves a request against the board the earlier ones left
+     *
+     *  PARAMS: DESTINATIONS are the cells every queued move goes to, CLAIMED the
+     *          cells moved into by the requests resolved so far
+     *---------------------------------------------------------------------------*/
+    fn resolve_one(&self, mut req: Request, policy: Policy, destinations: &Destinations,
+                   claimed: &HashMap<(String, usize, usize), char>) -> Outcome
+    {
+        let cancel = |request: Request, conflicts: Vec<Conflict>| Outcome { request, conflicts, resolution: Resolution::Cancelled };
+        let caster = match self.characters.get(&req.caster) {
+            Some(caster) if caster.sheet.hitpoints > 0 => caster,
+            _ => return cancel(req, vec![Conflict::CasterDown])
+        };
+
+        let mut conflicts = Vec::new();
+        let mut resolution = Resolution::Kept;
+        if let (0, Some((row, col))) = (req.action_type, req.target_cell) {
+            let cell = (caster.map.clone(), row, col);
+            let rival = destinations.get(&cell).and_then(|casters| casters.iter().find(|token| **token != req.caster));
+            if let Some(with) = claimed.get(&cell) { conflicts.push(Conflict::SameDestination { with: *with }); }
+            else if let Some(with) = rival.filter(|_| policy == Policy::Cancel) { conflicts.push(Conflict::SameDestination { with: *with }); }
+            else if self.check_request(&req).is_err() && self.reachable_unblocked(&req, claimed) { conflicts.push(Conflict::Blocked); }
+
+            if !conflicts.is_empty() {
+                if policy != Policy::Bump { return cancel(req, conflicts); }
+                let grid = self.board(&caster.map).unwrap();
+                let nearest = get_action_range(grid, caster.row, caster.column, caster.sheet.speed, false).into_iter()
+                    .min_by_key(|(r, c)| ((r - row as i32).abs() + (c - col as i32).abs(), *r, *c));
+                match nearest {
+                    Some((r, c)) => {
+                        req.target_cell = Some((r as usize, c as usize));
+                        resolution = Resolution::Bumped { cell: (r as usize, c as usize) };
+                    },
+                    None => return cancel(req, conflicts)
+                }
+            }
+        }
+        else if let (2, Some(ability)) = (req.action_type, req.subtype_key.as_ref().and_then(|key| self.abilities.get(key))) {
+            let grid = self.board(&caster.map).unwrap();
+            let options = get_action_range(grid, caster.row, caster.column, ability.range as i32, true);
+            let mut dropped = Vec::new();
+            for target in req.target_tokens.iter().flatten() {
+                let conflict = match self.characters.get(target) {
+                    None => Some(Conflict::TargetDead { target: *target }),
+                    Some(tok) if tok.sheet.hitpoints <= 0 => Some(Conflict::TargetDead { target: *target }),
+                    Some(tok) if *target != req.caster && (tok.map != caster.map || !options.contains(&(tok.row as i32, tok.column as i32)))
+                        => Some(Conflict::OutOfRange { target: *target }),
+                    Some(_) => None
+                };
+                if let Some(conflict) = conflict { conflicts.push(conflict); dropped.push(*target); }
+            }
+            if !dropped.is_empty() {
+                if let Some(targets) = req.target_tokens.as_mut() { targets.retain(|target| !dropped.contains(target)); }
+                if policy == Policy::Cancel || req.target_tokens.as_ref().is_some_and(|targets| targets.is_empty()) {
+                    return cancel(req, conflicts);
+                }
+                resolution = Resolution::Retargeted { dropped };
+            }
+        }
+        return Outcome { request: req, conflicts, resolution };
+    }
+
+    /******************************************************************************
+     *  reachable_unblocked - True if the destination of a move could be reached
+     *                        without the tokens other requests moved this turn
+     *---------------------------------------------------------------------------*/
+    fn reachable_unblocked(&self, req: &Request, claimed: &HashMap<(String, usize, usize), char>) -> bool
+    {
+        let (caster, (row, col)) = match (self.characters.get(&req.caster), req.target_cell) {
+            (Some(caster), Some(cell)) => (caster, cell),
+            _ => return false
+        };
+        let mut grid = self.board(&caster.map).unwrap().clone();
+        for ((map, r, c), token) in claimed {
+            if *map == caster.map && *token != req.caster && grid[*r][*c] == *token { grid[*r][*c] = '0'; }
+        }
+        return get_action_range(&grid, caster.row, caster.column, caster.sheet.speed, false).contains(&(row as i32, col as i32));
+    }
+}
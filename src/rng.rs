@@ -0,0 +1,80 @@
+/*/////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+ *
+ *      Seeded random number generator shared by map generation and dice rolls
+ *
+ *      Implementation and Assumptions
+ *          - SplitMix64, small and fast, and produces the same sequence on every platform (native and wasm)
+ *          - Only integer math is used so a seed always reproduces the same maps and rolls
+ *
+ */////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+use serde::{Serialize, Deserialize};
+
+/***********************************************
+ * Rng - Deterministic SplitMix64 generator
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(ts_rs::TS))]
+pub struct Rng
+{
+    state: u64
+}
+
+impl Rng
+{
+    pub fn new(seed: u64) -> Rng { Rng { state: seed } }
+
+    /******************************************************************************
+     *  state - Current state, Rng::new(state) continues the same sequence
+     *---------------------------------------------------------------------------*/
+    pub fn state(&self) -> u64 { self.state }
+
+    /******************************************************************************
+     *  next_u64 - Advances the generator and returns the next 64 random bits
+     *---------------------------------------------------------------------------*/
+    pub fn next_u64(&mut self) -> u64
+    {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        return z ^ (z >> 31);
+    }
+
+    /******************************************************************************
+     *  below - Returns a number in [0, N), N must be greater than 0
+     *---------------------------------------------------------------------------*/
+    pub fn below(&mut self, n: usize) -> usize
+    {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /******************************************************************************
+     *  range - Returns a number in the inclusive range [LOW, HIGH]
+     *---------------------------------------------------------------------------*/
+    pub fn range(&mut self, low: i32, high: i32) -> i32
+    {
+        if high <= low { return low; }
+        let span = (high as i64 - low as i64 + 1) as u64;
+        return (low as i64 + (self.next_u64() % span) as i64) as i32;
+    }
+
+    /******************************************************************************
+     *  chance - Returns true with a probability of PERCENT / 100
+     *---------------------------------------------------------------------------*/
+    pub fn chance(&mut self, percent: u32) -> bool
+    {
+        (self.next_u64() % 100) < percent as u64
+    }
+
+    /******************************************************************************
+     *  shuffle - Fisher-Yates shuffle of the input slice
+     *---------------------------------------------------------------------------*/
+    pub fn shuffle<T>(&mut self, items: &mut [T])
+    {
+        for n in (1..items.len()).rev() {
+            let m = self.below(n + 1);
+            items.swap(n, m);
+        }
+    }
+}
@@ -0,0 +1,872 @@
+/*/////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+ *
+ *      Authoritative game server for ByteDungeon, rooms hold the session and run the rules instead of the clients
+ *
+ *      Implementation and Assumptions
+ *          - Native only, WebSocket (tungstenite) with one thread per connection, a room is shared behind one lock
+ *          - Messages are the versioned envelopes of protocol.rs, named after the events of the old Node relay
+ *          - A connection becomes a member of a room with startHosting or findSession, every later message acts on
+ *            that room as that user, ids in the messages are never trusted
+ *          - The first connection to claim a user id in a room (the host with startHosting) is handed a secret, later
+ *            findSession for that user must carry it, so nobody can rejoin as the host or as another player
+ *          - The user that hosted a room is its host (the DM), the host and co-DMs run turns, grant access and
+ *            approve requests, players only queue requests for the tokens they were granted and spectators watch,
+ *            roles and token control are kept in the game (see permissions.rs), only the host changes them
+ *          - Users joining a room for the first time become players
+ *          - Only the host and co-DMs receive the whole game, players receive what layers.rs lets them see (no
+ *            hidden tokens or GM notes) with deltas between those copies, spectators receive the redacted view of
+ *            viewer.rs again whenever the game changed, instead of deltas and executed requests
+ *          - Requests are checked again when approved (GameSession::check_request) and executed on the server,
+ *            clients are kept in sync with deltas (see sync.rs) after every message that changed the game
+ *          - Server holds the rooms and is independent of the network so it can be driven directly
+ *          - Saved games are validated (see validate.rs) before a room is opened with them, a message that still
+ *            panics is answered with transactionFailed, its room is put back as it was before the message and the
+ *            other connections are never taken down with the lock
+ *          - Rooms are persisted after every message when the server has a store, see storage.rs
+ *          - The DMs can have the AI propose requests for the monsters, they are approved like any other, see ai.rs
+ *          - The DMs hide tokens, pin GM notes and roll detection checks with commands like any other edit, players
+ *            learn of them through their synced copy only (see layers.rs)
+ *          - Chat lines are rolled on the server with the speaker's first token, whispers go to the recipient and
+ *            the speaker only, see chat.rs
+ *
+ */////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+use serde_json::Value;
+use tungstenite::{Message, Error as WsError};
+
+use std::io;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::net::{TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::sync::mpsc::{self, Sender};
+use std::hash::{BuildHasher, Hasher};
+use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::collections::hash_map::RandomState;
+
+use crate::{GameSession, Request, seed_dice, ROLL_TO_SUCCEED};
+use crate::history::{Command, Applied};
+use crate::migrate::load_save;
+use crate::validate::{validate_game, Severity};
+use crate::sync;
+use crate::chat::{self, ChatCommand, ChatKind};
+use crate::storage::{Store, RoomRecord, COMPACT_EVERY};
+use crate::protocol::{self, ClientMessage, ServerMessage, AccessRequest, Pending, Envelope};
+use crate::permissions::Role;
+use crate::resolve::{Policy, Resolution};
+
+pub const DEFAULT_PORT: u16 = 3000;
+pub const HISTORY_LIMIT: usize = 1;            // Rooms have no undo, one entry keeps executes from piling up copies
+const POLL: Duration = Duration::from_millis(20);
+
+pub type ClientId = u64;
+
+/***********************************************
+ * Room - One hosted game
+ **********************************************/
+pub struct Room
+{
+    pub host: String,
+    pub set: String,
+    pub game: GameSession,
+    pub access: Vec<AccessRequest>,
+    pub pending: Vec<Pending>,
+    pub turn_open: bool,
+    pub policy: Policy,                         // How conflicts between the requests of a turn are settled
+    clients: HashMap<String, ClientId>,         // Connection of each user in the room
+    secrets: BTreeMap<String, String>,          // Secret each user rejoins with, see header
+    synced: GameSession,                        // Last state the DMs were sent
+    synced_view: GameSession,                   // Last player copy the players were sent
+    saved_seq: u64,                             // First event not stored yet
+    logged: usize,                              // Events stored since the last compaction
+    saved_meta: String                          // Last RoomRecord stored, empty if the room was never stored
+}
+
+/***********************************************
+ * Snapshot - Room before a message
+ **********************************************/
+struct Snapshot
+{
+    game: GameSession,
+    access: Vec<AccessRequest>,
+    pending: Vec<Pending>,
+    turn_open: bool,
+    policy: Policy
+}
+
+/***********************************************
+ * Member - Who a connection is
+ **********************************************/
+#[derive(Clone)]
+struct Member
+{
+    room: String,
+    user: String,
+    name: String
+}
+
+/***********************************************
+ * Server - Every room and its members
+ **********************************************/
+#[derive(Default)]
+pub struct Server
+{
+    pub rooms: HashMap<String, Room>,
+    members: HashMap<ClientId, Member>,
+    store: Option<Store>                        // Where rooms are persisted, see storage.rs
+}
+
+type Outbox = Vec<(ClientId, ServerMessage)>;
+pub type Sent = Vec<(ClientId, Envelope<ServerMessage>)>;
+
+impl Server
+{
+    /******************************************************************************
+     *  with_store - Server that persists its rooms in STORE, starting with the
+     *               rooms already stored there
+     *
+     *  RETURN: The server and the warnings of the rooms that were restored
+     *---------------------------------------------------------------------------*/
+    pub fn with_store(store: Store) -> io::Result<(Server, Vec<String>)>
+    {
+        let (records, warnings) = store.load()?;
+        let mut server = Server { store: Some(store), ..Default::default() };
+        for (record, game) in records {
+            let mut room = Room::new(record.host, record.set, game);
+            room.access = record.access;
+            room.pending = record.pending;
+            room.turn_open = record.turn_open;
+            room.policy = record.policy;
+            room.secrets = record.secrets;
+            server.rooms.insert(record.id.clone(), room);
+            server.persist(&record.id);
+        }
+        return Ok((server, warnings));
+    }
+
+    /******************************************************************************
+     *  handle - Runs a message of CLIENT, a refused message only answers
+     *           transactionFailed to that client, a handled one with an id is
+     *           acked after the room was synced
+     *
+     *  RETURN: The messages to send and who to send them to
+     *---------------------------------------------------------------------------*/
+    pub fn handle(&mut self, client: ClientId, envelope: Envelope<ClientMessage>) -> Sent
+    {
+        let mut out = Vec::new();
+        let reply = match self.dispatch(client, envelope.message, &mut out) {
+            Ok(_) => envelope.id.map(|_| ServerMessage::Ack),
+            Err(reason) => Some(ServerMessage::TransactionFailed { reason })
+        };
+        if let Some(member) = self.members.get(&client).cloned() {
+            if let Some(room) = self.rooms.get_mut(&member.room) { room.sync(&mut out); }
+            self.persist(&member.room);
+        }
+        let mut sent = wrap(out);
+        if let Some(reply) = reply { sent.push((client, Envelope::new(reply, envelope.id))); }
+        return sent;
+    }
+
+    /******************************************************************************
+     *  receive - Handles a message like handle, a message that panics is
+     *            answered with transactionFailed and the room of CLIENT is put
+     *            back as it was before the message
+     *---------------------------------------------------------------------------*/
+    pub fn receive(&mut self, client: ClientId, envelope: Envelope<ClientMessage>) -> Sent
+    {
+        let id = envelope.id;
+        let room = self.members.get(&client).map(|member| member.room.clone());
+        let before = room.as_ref().and_then(|room| self.rooms.get(room)).map(Room::snapshot);
+        return panic::catch_unwind(AssertUnwindSafe(|| self.handle(client, envelope))).unwrap_or_else(|_| {
+            if let (Some(room), Some(before)) = (room, before) {
+                if let Some(room) = self.rooms.get_mut(&room) { room.restore(before); }
+            }
+            let reason = "the server couldn't handle that message".to_string();
+            vec![(client, Envelope::new(ServerMessage::TransactionFailed { reason }, id))]
+        });
+    }
+
+    /******************************************************************************
+     *  disconnect - Forgets a connection, its room stays open for a rejoin
+     *---------------------------------------------------------------------------*/
+    pub fn disconnect(&mut self, client: ClientId) -> Sent
+    {
+        let mut out = Vec::new();
+        self.leave(client, &mut out);
+        return wrap(out);
+    }
+
+    fn leave(&mut self, client: ClientId, out: &mut Outbox)
+    {
+        if let Some(member) = self.members.remove(&client) {
+            if let Some(room) = self.rooms.get_mut(&member.room) {
+                if room.clients.get(&member.user) == Some(&client) { room.clients.remove(&member.user); }
+                room.log(format!("{} has disconnected", member.name), out);
+            }
+        }
+    }
+
+    fn dispatch(&mut self, client: ClientId, message: ClientMessage, out: &mut Outbox) -> Result<(), String>
+    {
+        let message = match message {
+            ClientMessage::StartHosting { room, user, name, set, game } => return self.host(client, room, user, name, set, game, out),
+            ClientMessage::FindSession { room, user, name, secret } => return self.join(client, room, user, name, secret, out),
+            message => message
+        };
+        let member = self.members.get(&client).cloned().ok_or("join a session first")?;
+        let room = self.rooms.get_mut(&member.room).unwrap();
+        let player_message = matches!(message, ClientMessage::RequestAccess { .. } | ClientMessage::AddTurn { .. }
+            | ClientMessage::Roll20 { .. } | ClientMessage::BroadcastLog { .. } | ClientMessage::Chat { .. });
+        let host_message = matches!(message, ClientMessage::SetRole { .. } | ClientMessage::GrantToken { .. }
+            | ClientMessage::RevokeToken { .. } | ClientMessage::SetAllowed { .. });
+        let role = room.game.permissions.role(&member.user);
+        if (host_message && role != Some(Role::Host)) || (!player_message && !role.is_some_and(Role::is_dm)) {
+            return Err("only the host can do that".to_string());
+        }
+
+        match message {
+            ClientMessage::RequestAccess { token } => room.request_access(&member, token, out)?,
+            ClientMessage::GetAccessRequests => room.send_access(out),
+            ClientMessage::GrantAccess { index } => room.answer_access(index, true, out)?,
+            ClientMessage::RemoveAccess { index } => room.answer_access(index, false, out)?,
+            ClientMessage::StartTurn => room.start_turn(out),
+            ClientMessage::AddTurn { requests } => room.add_turn(&member, requests, out)?,
+            ClientMessage::EndTurn => room.end_turn(out),
+            ClientMessage::ApproveRequest { index } => room.execute(room.pending_at(index)?, None, out),
+            ClientMessage::RemoveRequest { index } => {
+                let pending = room.pending.remove(room.pending_at(index)?);
+                room.log(format!("Skipped the request of {}", pending.request.caster), out);
+                room.send_pending(out);
+            },
+            ClientMessage::EmitRollRequest { index } => {
+                let pending = room.pending[room.pending_at(index)?].clone();
+                room.send_to(&pending.user, ServerMessage::EnableRoll { index, request: pending.request.clone() }, out);
+                room.log(format!("Waiting for {} to roll", pending.request.caster), out);
+                room.log(format!("To use {}, rolling {}+ is needed", pending.request.subtype_key.unwrap_or_default(), ROLL_TO_SUCCEED), out);
+            },
+            ClientMessage::Roll20 { index } => room.roll(&member, index, out)?,
+            ClientMessage::BroadcastLog { message } => room.log(format!("{}: {}", member.name, message), out),
+            ClientMessage::Chat { text } => {
+                let command = chat::parse(&text).map_err(|err| err.to_string())?;
+                let to = match &command.kind {
+                    ChatKind::Whisper { to } => Some(self.members.values()
+                        .find(|other| other.room == member.room && (other.user == *to || other.name == *to))
+                        .map(|other| other.user.clone()).ok_or_else(|| format!("{} isn't in this room", to))?),
+                    _ => None
+                };
+                room.chat(&member, command, to, out)?;
+            },
+            ClientMessage::SetRole { user, role } => room.permit(&user, Command::SetRole { user: user.clone(), role }, out)?,
+            ClientMessage::GrantToken { user, token } => room.permit(&user, Command::Grant { user: user.clone(), token }, out)?,
+            ClientMessage::RevokeToken { user, token } => room.permit(&user, Command::Revoke { user: user.clone(), token }, out)?,
+            ClientMessage::SetAllowed { user, action, allowed } =>
+                room.permit(&user, Command::SetAllowed { user: user.clone(), action, allowed }, out)?,
+            ClientMessage::SetPolicy { policy } => {
+                room.policy = policy;
+                room.send_to_dms(ServerMessage::SocketLog { message: format!("Conflicts are now settled by: {}", policy) }, out);
+            },
+            ClientMessage::SetBehaviour { token, behaviour } => room.edit(Command::SetBehaviour { token, behaviour }, out)?,
+            ClientMessage::AutoPlay => room.auto_play(&member, out),
+            ClientMessage::SetVisibility { token, visibility } => room.edit(Command::SetVisibility { token, visibility }, out)?,
+            ClientMessage::AddNote { note } => room.edit(Command::AddNote(note), out)?,
+            ClientMessage::RemoveNotes { cell } => room.edit(Command::RemoveNotes(cell), out)?,
+            ClientMessage::Detect { token, by } => {
+                let found = room.game.detect(token, by)?;
+                let message = format!("{} {} {}", by, if found { "found" } else { "didn't find" }, token);
+                room.send_to_dms(ServerMessage::SocketLog { message }, out);
+            },
+            ClientMessage::StartHosting { .. } | ClientMessage::FindSession { .. } => unreachable!()
+        }
+        return Ok(());
+    }
+
+    /******************************************************************************
+     *  host - Opens a room with CLIENT as its host
+     *---------------------------------------------------------------------------*/
+    fn host(&mut self, client: ClientId, id: String, user: String, name: String, set: String, game: Option<Value>,
+            out: &mut Outbox) -> Result<(), String>
+    {
+        if self.rooms.contains_key(&id) { return Err(format!("Name: '{}' already taken!", id)); }
+        let mut game = match game {
+            Some(save) => load_save(save).map_err(|err| err.to_string())?.0,
+            None => GameSession::default()
+        };
+        if let Some(issue) = validate_game(&game).into_iter().find(|issue| issue.severity == Severity::Error) {
+            return Err(format!("The saved game isn't valid: {}", issue));
+        }
+        seed_dice(&mut game);
+        let mut room = Room::new(user.clone(), set, game);
+        let secret = room.secret_of(&user);
+        self.rooms.insert(id.clone(), room);
+        self.enter(client, &id, user, name);
+        out.push((client, ServerMessage::StartSession { room: id, secret }));
+        return Ok(());
+    }
+
+    /******************************************************************************
+     *  join - Adds CLIENT to a room, as its host again if USER hosted it, a user
+     *         that joined before must give the secret it was handed then
+     *---------------------------------------------------------------------------*/
+    fn join(&mut self, client: ClientId, id: String, user: String, name: String, secret: Option<String>,
+            out: &mut Outbox) -> Result<(), String>
+    {
+        let room = self.rooms.get(&id).ok_or_else(|| format!("Couldn't find game: '{}'", id))?;
+        if room.secrets.get(&user).is_some_and(|expected| secret.as_ref() != Some(expected)) {
+            return Err(format!("'{}' already joined this room, rejoin with its secret", user));
+        }
+        self.leave(client, out);
+        let room = self.rooms.get_mut(&id).unwrap();
+        let secret = room.secret_of(&user);
+        if room.game.permissions.role(&user).is_none() {
+            room.game.apply(Command::SetRole { user: user.clone(), role: Some(Role::Player) }).map_err(|err| err.to_string())?;
+        }
+        room.log(format!("{} has connected", name), out);
+        let (set, game) = (room.set.clone(), room.game_for(&user));
+        let message = if user == room.host { ServerMessage::HostRejoin { room: id.clone(), set, secret, game } }
+        else { ServerMessage::JoinGame { room: id.clone(), set, secret, tokens: room.tokens_of(&user), game } };
+        out.push((client, message));
+        self.enter(client, &id, user, name);
+        return Ok(());
+    }
+
+    /******************************************************************************
+     *  persist - Stores what changed in a room, compacting its log when needed
+     *---------------------------------------------------------------------------*/
+    fn persist(&mut self, id: &str)
+    {
+        let (store, room) = match (&self.store, self.rooms.get_mut(id)) {
+            (Some(store), Some(room)) => (store, room),
+            _ => return
+        };
+        let events = room.game.events_since(room.saved_seq);
+        let result = if room.saved_meta.is_empty() || room.logged + events.len() >= COMPACT_EVERY {
+            room.logged = 0;
+            store.compact(id, &room.game)
+        }
+        else {
+            room.logged += events.len();
+            store.append_events(id, events)
+        };
+        room.saved_seq = room.game.next_seq();
+
+        let meta = serde_json::to_string(&room.record(id)).unwrap();
+        let result = result.and_then(|_| if meta != room.saved_meta { store.save_meta(&room.record(id)) } else { Ok(()) });
+        match result {
+            Ok(_) => room.saved_meta = meta,
+            Err(err) => eprintln!("byte-dungeon-server: couldn't store room '{}': {}", id, err)
+        }
+    }
+
+    fn enter(&mut self, client: ClientId, id: &str, user: String, name: String)
+    {
+        self.rooms.get_mut(id).unwrap().clients.insert(user.clone(), client);
+        self.members.insert(client, Member { room: id.to_string(), user, name });
+    }
+}
+
+impl Room
+{
+    fn new(host: String, set: String, mut game: GameSession) -> Room
+    {
+        if game.permissions.role(&host) != Some(Role::Host) {
+            let _ = game.apply(Command::SetRole { user: host.clone(), role: Some(Role::Host) });
+        }
+        game.set_history_limit(HISTORY_LIMIT);
+        Room {
+            host, set,
+            access: Vec::new(),
+            pending: Vec::new(),
+            turn_open: false,
+            policy: Policy::default(),
+            clients: HashMap::new(),
+            secrets: BTreeMap::new(),
+            synced: game.clone(),
+            synced_view: game.player_copy(),
+            saved_seq: game.next_seq(),
+            logged: 0,
+            saved_meta: String::new(),
+            game
+        }
+    }
+
+    /******************************************************************************
+     *  record - What is stored of the room besides its game
+     *---------------------------------------------------------------------------*/
+    pub fn record(&self, id: &str) -> RoomRecord
+    {
+        RoomRecord {
+            id: id.to_string(),
+            host: self.host.clone(),
+            set: self.set.clone(),
+            access: self.access.clone(),
+            pending: self.pending.clone(),
+            turn_open: self.turn_open,
+            policy: self.policy,
+            secrets: self.secrets.clone()
+        }
+    }
+
+    /******************************************************************************
+     *  snapshot - What a message can change in the room, see Server::receive
+     *---------------------------------------------------------------------------*/
+    fn snapshot(&self) -> Snapshot
+    {
+        Snapshot {
+            game: self.game.clone(),
+            access: self.access.clone(),
+            pending: self.pending.clone(),
+            turn_open: self.turn_open,
+            policy: self.policy
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot)
+    {
+        self.game = snapshot.game;
+        self.access = snapshot.access;
+        self.pending = snapshot.pending;
+        self.turn_open = snapshot.turn_open;
+        self.policy = snapshot.policy;
+    }
+
+    /******************************************************************************
+     *  secret_of - Secret USER rejoins with, handed out the first time
+     *---------------------------------------------------------------------------*/
+    fn secret_of(&mut self, user: &str) -> String
+    {
+        self.secrets.entry(user.to_string()).or_insert_with(new_secret).clone()
+    }
+
+    fn send_to(&self, user: &str, message: ServerMessage, out: &mut Outbox)
+    {
+        if let Some(client) = self.clients.get(user) { out.push((*client, message)); }
+    }
+
+    fn broadcast(&self, message: ServerMessage, out: &mut Outbox)
+    {
+        let mut clients: Vec<&ClientId> = self.clients.values().collect();
+        clients.sort();
+        for client in clients { out.push((*client, message.clone())); }
+    }
+
+    /******************************************************************************
+     *  audience - Connected clients with the role of their user, by connection
+     *---------------------------------------------------------------------------*/
+    fn audience(&self) -> Vec<(ClientId, Option<Role>)>
+    {
+        let mut clients: Vec<(ClientId, Option<Role>)> = self.clients.iter()
+            .map(|(user, client)| (*client, self.game.permissions.role(user))).collect();
+        clients.sort_by_key(|(client, _)| *client);
+        return clients;
+    }
+
+    /******************************************************************************
+     *  game_for - Save of the game as USER may receive it
+     *---------------------------------------------------------------------------*/
+    fn game_for(&self, user: &str) -> Value
+    {
+        match self.game.permissions.role(user) {
+            Some(role) if role.is_dm() => self.game.to_save(),
+            Some(Role::Spectator) => self.game.export_for_viewer(Role::Spectator),
+            _ => self.game.player_copy().to_save()
+        }
+    }
+
+    fn send_to_dms(&self, message: ServerMessage, out: &mut Outbox)
+    {
+        let mut users: Vec<&String> = self.clients.keys().filter(|user| self.game.permissions.is_dm(user)).collect();
+        users.sort();
+        for user in users { self.send_to(user, message.clone(), out); }
+    }
+
+    fn log(&self, message: String, out: &mut Outbox)
+    {
+        self.broadcast(ServerMessage::SocketLog { message }, out);
+    }
+
+    /******************************************************************************
+     *  sync - Sends the changes made since the last sync to every client, as
+     *         each of them may see the game (see game_for)
+     *---------------------------------------------------------------------------*/
+    fn sync(&mut self, out: &mut Outbox)
+    {
+        if self.game.version == self.synced.version { return; }
+        let player_copy = self.game.player_copy();
+        let delta = ServerMessage::Sync { delta: Box::new(sync::diff(&self.synced, &self.game)) };
+        let player_delta = ServerMessage::Sync { delta: Box::new(sync::diff(&self.synced_view, &player_copy)) };
+        let mut view = None;
+        for (client, role) in self.audience() {
+            let message = match role {
+                Some(role) if role.is_dm() => delta.clone(),
+                Some(Role::Spectator) => view.get_or_insert_with(|| ServerMessage::View { game: self.game.export_for_viewer(Role::Spectator) }).clone(),
+                _ => player_delta.clone()
+            };
+            out.push((client, message));
+        }
+        self.synced = self.game.clone();
+        self.synced_view = player_copy;
+    }
+
+    /******************************************************************************
+     *  tokens_of - Tokens granted to USER, sorted
+     *---------------------------------------------------------------------------*/
+    pub fn tokens_of(&self, user: &str) -> Vec<char>
+    {
+        self.game.permissions.tokens_of(user)
+    }
+
+    /******************************************************************************
+     *  owner - First player controlling TOKEN, the host if no player was granted it
+     *---------------------------------------------------------------------------*/
+    pub fn owner(&self, token: char) -> String
+    {
+        self.game.permissions.controllers_of(token).into_iter().next().unwrap_or_else(|| self.host.clone())
+    }
+
+    fn pending_at(&self, index: usize) -> Result<usize, String>
+    {
+        if index < self.pending.len() { Ok(index) } else { Err(format!("no pending request {}", index)) }
+    }
+
+    fn send_access(&self, out: &mut Outbox)
+    {
+        self.send_to_dms(ServerMessage::LoadAccessRequests { requests: self.access.clone() }, out);
+    }
+
+    fn send_pending(&self, out: &mut Outbox)
+    {
+        self.send_to_dms(ServerMessage::LoadRequests { requests: self.pending.clone() }, out);
+    }
+
+    fn request_access(&mut self, member: &Member, token: char, out: &mut Outbox) -> Result<(), String>
+    {
+        if !self.game.characters.contains_key(&token) { return Err(format!("'{}' is not on a map", token)); }
+        if self.game.permissions.role(&member.user) == Some(Role::Spectator) { return Err("spectators can't play tokens".to_string()); }
+        self.access.push(AccessRequest { user: member.user.clone(), name: member.name.clone(), token });
+        self.send_to_dms(ServerMessage::SocketLog { message: format!("'{}' is requesting access to {}", member.name, token) }, out);
+        self.send_access(out);
+        return Ok(());
+    }
+
+    /******************************************************************************
+     *  answer_access - Grants or declines an access request
+     *---------------------------------------------------------------------------*/
+    fn answer_access(&mut self, index: usize, grant: bool, out: &mut Outbox) -> Result<(), String>
+    {
+        if index >= self.access.len() { return Err(format!("no access request {}", index)); }
+        let request = self.access.remove(index);
+        if grant {
+            self.permit(&request.user, Command::Grant { user: request.user.clone(), token: request.token }, out)?;
+            self.log(format!("'{}' now plays {}", request.name, request.token), out);
+        }
+        else { self.send_to(&request.user, ServerMessage::SocketLog { message: format!("'{}' access declined", request.token) }, out); }
+        self.send_access(out);
+        return Ok(());
+    }
+
+    /******************************************************************************
+     *  permit - Applies a permission change about USER and tells them which
+     *           tokens they now play
+     *---------------------------------------------------------------------------*/
+    fn permit(&mut self, user: &str, command: Command, out: &mut Outbox) -> Result<(), String>
+    {
+        let label = command.label();
+        self.game.apply(command).map_err(|err| err.to_string())?;
+        if !self.game.permissions.is_dm(user) {
+            let tokens = if self.turn_open { self.tokens_of(user) } else { Vec::new() };
+            self.send_to(user, ServerMessage::AddTokenAccess { tokens }, out);
+        }
+        self.send_to_dms(ServerMessage::SocketLog { message: label }, out);
+        return Ok(());
+    }
+
+    /******************************************************************************
+     *  edit - Applies an edit of the DMs (AI behaviours, hidden tokens, GM
+     *         notes), only the DMs are told about it
+     *---------------------------------------------------------------------------*/
+    fn edit(&mut self, command: Command, out: &mut Outbox) -> Result<(), String>
+    {
+        let label = command.label();
+        self.game.apply(command).map_err(|err| err.to_string())?;
+        self.send_to_dms(ServerMessage::SocketLog { message: label }, out);
+        return Ok(());
+    }
+
+    /******************************************************************************
+     *  start_turn - Lets players queue requests for their tokens
+     *---------------------------------------------------------------------------*/
+    fn start_turn(&mut self, out: &mut Outbox)
+    {
+        self.turn_open = true;
+        self.pending.clear();
+        for user in self.clients.keys() {
+            if self.game.permissions.is_dm(user) { continue; }
+            self.send_to(user, ServerMessage::AddTokenAccess { tokens: self.tokens_of(user) }, out);
+        }
+        self.send_to_dms(ServerMessage::GrantAllAccess, out);
+        self.log("Turn started".to_string(), out);
+    }
+
+    /******************************************************************************
+     *  add_turn - Queues the requests of a player, all of them or none
+     *---------------------------------------------------------------------------*/
+    fn add_turn(&mut self, member: &Member, requests: Vec<Request>, out: &mut Outbox) -> Result<(), String>
+    {
+        if !self.turn_open { return Err("the turn hasn't started".to_string()); }
+        let requests: Vec<Request> = requests.into_iter().map(|request| Request { user: Some(member.user.clone()), rolled_at: None, ..request }).collect();
+        for request in &requests {
+            if !self.game.characters.contains_key(&request.caster) { return Err(format!("'{}' is not on a map", request.caster)); }
+            self.game.permissions.check(request).map_err(|err| err.to_string())?;
+        }
+        for request in requests { self.game.log_request(request.caster, vec![request]); }
+        self.log(format!("'{}' has ended turn", member.name), out);
+        return Ok(());
+    }
+
+    /******************************************************************************
+     *  end_turn - Closes the turn and hands the queued requests to the host in
+     *             initiative order, once their conflicts were settled with the
+     *             policy of the room (see resolve.rs)
+     *---------------------------------------------------------------------------*/
+    fn end_turn(&mut self, out: &mut Outbox)
+    {
+        self.turn_open = false;
+        for user in self.clients.keys() {
+            if !self.game.permissions.is_dm(user) { self.send_to(user, ServerMessage::AddTokenAccess { tokens: Vec::new() }, out); }
+        }
+        self.game.sort_requests();
+        let queued = std::mem::take(&mut self.game.requests);
+        let outcomes = self.game.resolve_queue(&queued, self.policy);
+        for outcome in &outcomes {
+            if !outcome.conflicts.is_empty() { self.send_to_dms(ServerMessage::SocketLog { message: outcome.to_string() }, out); }
+            if outcome.resolution == Resolution::Cancelled { continue; }
+            self.pending.push(Pending { user: self.owner(outcome.request.caster), request: outcome.request.clone() });
+        }
+        self.send_to_dms(ServerMessage::Resolved { outcomes }, out);
+        self.send_pending(out);
+    }
+
+    /******************************************************************************
+     *  auto_play - Queues the requests the AI proposes for the tokens no player
+     *              controls, as MEMBER's, for the DMs to approve (see ai.rs)
+     *---------------------------------------------------------------------------*/
+    fn auto_play(&mut self, member: &Member, out: &mut Outbox)
+    {
+        let requests = self.game.auto_play(&self.game.party());
+        self.send_to_dms(ServerMessage::SocketLog { message: format!("The AI proposed {} requests for the monsters", requests.len()) }, out);
+        for request in requests {
+            let request = Request { user: Some(member.user.clone()), rolled_at: None, ..request };
+            self.pending.push(Pending { user: self.owner(request.caster), request });
+        }
+        self.send_pending(out);
+    }
+
+    /******************************************************************************
+     *  execute - Checks and executes a pending request, a request that can no
+     *            longer be done is dropped, clients that see the caster get the
+     *            request and its combat record (see combat.rs) as a log line,
+     *            players without what they don't see (see layers.rs) and only
+     *            the DMs learn why a request was refused, ROLLED_AT is the seq
+     *            of the d20 rolled for it
+     *---------------------------------------------------------------------------*/
+    fn execute(&mut self, index: usize, rolled_at: Option<u64>, out: &mut Outbox)
+    {
+        let request = Request { rolled_at, ..self.pending.remove(index).request };
+        let result = self.game.check_request(&request)
+            .and_then(|_| self.game.apply(Command::Execute(request.clone())).map_err(|err| err.to_string()));
+        match result {
+            Ok(applied) => {
+                let record = match applied { Applied::Executed(record) => Some(record), _ => None };
+                let for_players = record.as_ref().and_then(|record| self.game.record_for_players(record));
+                let request_for_players = self.game.request_for_players(&request);
+                for (client, role) in self.audience() {
+                    let sent = match role {
+                        Some(role) if role.is_dm() => record.as_ref().map(|record| (&request, record)),
+                        Some(Role::Spectator) => None,
+                        _ => request_for_players.as_ref().zip(for_players.as_ref())
+                    };
+                    if let Some((request, record)) = sent {
+                        out.push((client, ServerMessage::ExecuteRequest { request: request.clone() }));
+                        out.push((client, ServerMessage::SocketLog { message: record.to_string() }));
+                    }
+                }
+            },
+            Err(reason) => {
+                let refused = format!("Refused the request of {}", request.caster);
+                let seen = self.game.seen_by_players(request.caster);
+                for (client, role) in self.audience() {
+                    let message = match role {
+                        Some(role) if role.is_dm() => format!("{}: {}", refused, reason),
+                        _ if seen => refused.clone(),
+                        _ => continue
+                    };
+                    out.push((client, ServerMessage::SocketLog { message }));
+                }
+            }
+        }
+        self.send_pending(out);
+    }
+
+    /******************************************************************************
+     *  chat - Rolls a chat line of MEMBER with the stats of their first token and
+     *         sends it to the room, or only to TO and MEMBER for a whisper
+     *---------------------------------------------------------------------------*/
+    fn chat(&mut self, member: &Member, command: ChatCommand, to: Option<String>, out: &mut Outbox) -> Result<(), String>
+    {
+        let token = self.tokens_of(&member.user).first().cloned();
+        let message = self.game.roll_chat(token, command).map_err(|err| err.to_string())?;
+        let chat = ServerMessage::Chat { from: member.name.clone(), message };
+        match to {
+            Some(to) => {
+                if to != member.user { self.send_to(&member.user, chat.clone(), out); }
+                self.send_to(&to, chat, out);
+            },
+            None => self.broadcast(chat, out)
+        }
+        return Ok(());
+    }
+
+    /******************************************************************************
+     *  roll - Rolls a d20 for a pending request, it is executed on a success
+     *---------------------------------------------------------------------------*/
+    fn roll(&mut self, member: &Member, index: usize, out: &mut Outbox) -> Result<(), String>
+    {
+        self.pending_at(index)?;
+        if !self.game.permissions.is_dm(&member.user) && member.user != self.pending[index].user {
+            return Err("that roll isn't yours".to_string());
+        }
+        let rolled_at = self.game.next_seq();
+        let roll = self.game.roll(1, 20);
+        self.log(format!("{} rolled a {}", member.name, roll), out);
+        if roll >= ROLL_TO_SUCCEED {
+            self.execute(index, Some(rolled_at), out);
+        }
+        else {
+            self.log("Attempt failed!".to_string(), out);
+            self.pending.remove(index);
+            self.send_pending(out);
+        }
+        return Ok(());
+    }
+}
+
+/******************************************************************************
+ *  new_secret - 128 unguessable bits as hex, from the hasher keys std seeds
+ *               with the OS generator
+ *---------------------------------------------------------------------------*/
+fn new_secret() -> String
+{
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos());
+    let half = |n: u8| {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(nanos);
+        hasher.write_u8(n);
+        hasher.finish()
+    };
+    return format!("{:016x}{:016x}", half(0), half(1));
+}
+
+/******************************************************************************
+ *  wrap - Envelopes for messages pushed by the server
+ *---------------------------------------------------------------------------*/
+fn wrap(out: Outbox) -> Sent
+{
+    out.into_iter().map(|(client, message)| (client, Envelope::new(message, None))).collect()
+}
+
+/***********************************************
+ * Hub - Server shared by the connections
+ **********************************************/
+#[derive(Default)]
+struct Hub
+{
+    server: Server,
+    senders: HashMap<ClientId, Sender<String>>,
+    next_id: ClientId
+}
+
+impl Hub
+{
+    fn connect(&mut self, sender: Sender<String>) -> ClientId
+    {
+        self.next_id += 1;
+        self.senders.insert(self.next_id, sender);
+        return self.next_id;
+    }
+
+    fn receive(&mut self, client: ClientId, text: &str)
+    {
+        let out = match protocol::decode(text) {
+            Ok(envelope) => self.server.receive(client, envelope),
+            Err(err) => vec![(client, Envelope::new(ServerMessage::TransactionFailed { reason: err.to_string() }, None))]
+        };
+        self.deliver(out);
+    }
+
+    fn disconnect(&mut self, client: ClientId)
+    {
+        self.senders.remove(&client);
+        let out = self.server.disconnect(client);
+        self.deliver(out);
+    }
+
+    fn deliver(&self, out: Sent)
+    {
+        for (client, message) in out {
+            if let Some(sender) = self.senders.get(&client) { let _ = sender.send(serde_json::to_string(&message).unwrap()); }
+        }
+    }
+}
+
+/******************************************************************************
+ *  lock - Locks HUB, even after a connection panicked while holding it
+ *---------------------------------------------------------------------------*/
+fn lock(hub: &Mutex<Hub>) -> MutexGuard<'_, Hub>
+{
+    hub.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/******************************************************************************
+ *  serve - Accepts WebSocket clients of SERVER on LISTENER until it fails
+ *---------------------------------------------------------------------------*/
+pub fn serve(listener: TcpListener, server: Server) -> io::Result<()>
+{
+    let hub = Arc::new(Mutex::new(Hub { server, ..Default::default() }));
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let hub = Arc::clone(&hub);
+        thread::spawn(move || connection(stream, hub));
+    }
+    return Ok(());
+}
+
+/******************************************************************************
+ *  connection - Reads the messages of one client and writes what the server
+ *               sends it, polling both
+ *---------------------------------------------------------------------------*/
+fn connection(stream: TcpStream, hub: Arc<Mutex<Hub>>)
+{
+    let mut socket = match tungstenite::accept(stream) {
+        Ok(socket) => socket,
+        Err(_) => return
+    };
+    if socket.get_ref().set_read_timeout(Some(POLL)).is_err() { return; }
+    let (sender, receiver) = mpsc::channel();
+    let client = lock(&hub).connect(sender);
+
+    'connected: loop {
+        match socket.read() {
+            Ok(Message::Text(text)) => lock(&hub).receive(client, &text),
+            Ok(Message::Close(_)) => break,
+            Ok(_) => {},
+            Err(WsError::Io(err)) if matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {},
+            Err(_) => break
+        }
+        while let Ok(text) = receiver.try_recv() {
+            if socket.send(Message::Text(text)).is_err() { break 'connected; }
+        }
+    }
+    lock(&hub).disconnect(client);
+}
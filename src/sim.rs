@@ -0,0 +1,243 @@
+/*/////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+ *
+ *      Monte Carlo combat simulator for ByteDungeon, tells how deadly an encounter is before it is played
+ *
+ *      Implementation and Assumptions
+ *          - Native only, run from the CLI with `byte-dungeon simulate`
+ *          - The party is the tokens given in the options, or else the tokens granted to a player (see permissions.rs),
+ *            every other token with hitpoints left is a monster
+ *          - Every combat plays a copy of the session with its own generator, drawn from the seed, so the same seed
+ *            always gives the same report
+ *          - Rounds go in initiative order (highest first, ties by token), a token without hitpoints left is down,
+ *            a combat ends when a side is down, or as a draw after max_rounds
+ *          - Both sides play the requests the monster AI proposes (see ai.rs), with the behaviour the DM set for
+ *            each token, aggressive when none is set, so the DM tells how a hero plays by giving it a behaviour
+ *            (ie: support for a healer), it is only used by the simulator since party tokens are never auto-played
+ *          - Requests are checked (check_request) and executed (execute_request) like approved ones, abilities need a
+ *            d20 roll of ROLL_TO_SUCCEED like on the server
+ *          - Usefulness of an ability (uses, hits, damage, healing, kills) is read from the combat records, by the
+ *            side of each target: hitpoints taken from and kills of the other side, hitpoints given to the caster's
+ *            own side
+ *
+ */////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+use serde::{Serialize, Deserialize};
+
+use std::fmt;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+use crate::{GameSession, Request, copy_session, ROLL_TO_SUCCEED};
+use crate::rng::Rng;
+
+type Usefulness = BTreeMap<char, BTreeMap<String, AbilityStats>>;    // Stats of every ability used, by token
+
+/***********************************************
+ * SimOptions - How many combats and who fights
+ **********************************************/
+#[derive(Clone, Debug)]
+pub struct SimOptions
+{
+    pub runs: u32,
+    pub seed: u64,
+    pub max_rounds: u32,                        // Combats still going after this many rounds are draws
+    pub party: Vec<char>                        // Empty for the tokens granted to a player
+}
+
+impl Default for SimOptions
+{
+    fn default() -> SimOptions {
+        SimOptions { runs: 100, seed: 1, max_rounds: 50, party: Vec::new() }
+    }
+}
+
+/***********************************************
+ * AbilityStats - How useful an ability was
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct AbilityStats
+{
+    pub uses: u32,                              // Times it was tried, rolls missed included
+    pub hits: u32,
+    pub damage: i64,                            // Hitpoints taken from enemies
+    pub healing: i64,                           // Hitpoints given to allies
+    pub kills: u32
+}
+
+/***********************************************
+ * SimReport - Totals of every combat played
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct SimReport
+{
+    pub runs: u32,
+    pub party_wins: u32,
+    pub monster_wins: u32,
+    pub draws: u32,
+    pub average_rounds: f64,
+    pub hitpoints: BTreeMap<char, f64>,         // Average hitpoints left at the end, 0 for a token that was down
+    pub abilities: Usefulness                   // Totals over every combat
+}
+
+/***********************************************
+ * SimError - Encounters that can't be played
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum SimError
+{
+    NoRuns,
+    NoParty,
+    NoMonsters,
+    UnknownToken(char)                          // Party token that isn't on a map
+}
+
+impl fmt::Display for SimError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SimError::NoRuns => write!(f, "at least one combat must be played"),
+            SimError::NoParty => write!(f, "no party, name its tokens or grant them to players"),
+            SimError::NoMonsters => write!(f, "no monster with hitpoints left"),
+            SimError::UnknownToken(token) => write!(f, "'{}' is not on a map", token)
+        }
+    }
+}
+
+impl AbilityStats
+{
+    /******************************************************************************
+     *  per_use - Hitpoints taken or given per use, misses included
+     *---------------------------------------------------------------------------*/
+    pub fn per_use(&self) -> f64
+    {
+        if self.uses == 0 { return 0.0; }
+        return (self.damage + self.healing) as f64 / self.uses as f64;
+    }
+}
+
+impl SimReport
+{
+    pub fn party_win_rate(&self) -> f64 { self.party_wins as f64 / self.runs.max(1) as f64 }
+    pub fn monster_win_rate(&self) -> f64 { self.monster_wins as f64 / self.runs.max(1) as f64 }
+}
+
+impl fmt::Display for SimReport
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let percent = |count: u32| 100.0 * count as f64 / self.runs.max(1) as f64;
+        writeln!(f, "{} combats: party wins {:.1}%, monsters win {:.1}%, draws {:.1}%",
+                 self.runs, percent(self.party_wins), percent(self.monster_wins), percent(self.draws))?;
+        writeln!(f, "Average rounds: {:.1}", self.average_rounds)?;
+        writeln!(f, "Hitpoints left (average):")?;
+        for (token, hitpoints) in &self.hitpoints { writeln!(f, "    {} {:.1}", token, hitpoints)?; }
+        writeln!(f, "Abilities:")?;
+        for (token, abilities) in &self.abilities {
+            for (name, stats) in abilities {
+                writeln!(f, "    {} {}: {} uses, {} hits, {} damage, {} healing, {} kills, {:.1} per use",
+                         token, name, stats.uses, stats.hits, stats.damage, stats.healing, stats.kills, stats.per_use())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl GameSession
+{
+    /******************************************************************************
+     *  simulate - Plays the encounter OPTIONS.RUNS times, see header
+     *
+     *  RETURN: Win rates, rounds, hitpoints left and ability usefulness
+     *---------------------------------------------------------------------------*/
+    pub fn simulate(&self, options: &SimOptions) -> Result<SimReport, SimError>
+    {
+        if options.runs == 0 { return Err(SimError::NoRuns); }
+        let party: Vec<char> = match options.party.is_empty() {
+            true => self.party(),
+            false => options.party.clone()
+        };
+        if let Some(token) = party.iter().find(|token| !self.characters.contains_key(token)) { return Err(SimError::UnknownToken(*token)); }
+        let mut order: Vec<char> = self.characters.keys().filter(|token| party.contains(token) || self.is_up(**token)).cloned().collect();
+        if party.is_empty() { return Err(SimError::NoParty); }
+        if order.len() == party.len() { return Err(SimError::NoMonsters); }
+        order.sort_by_key(|token| (Reverse(self.characters[token].initiative.unwrap_or(i8::MIN)), *token));
+
+        let mut seeds = Rng::new(options.seed);
+        let mut report = SimReport { runs: options.runs, ..Default::default() };
+        let mut rounds = 0;
+        let mut hitpoints: BTreeMap<char, i64> = BTreeMap::new();
+        for _ in 0..options.runs {
+            let mut game = copy_session(self);
+            game.rng = Rng::new(seeds.next_u64());
+            game.events.clear();
+            game.combat_log.clear();
+            let (winner, played) = game.fight(&party, &order, options.max_rounds, &mut report.abilities);
+            match winner {
+                Some(true) => report.party_wins += 1,
+                Some(false) => report.monster_wins += 1,
+                None => report.draws += 1
+            }
+            rounds += played;
+            for token in &order { *hitpoints.entry(*token).or_default() += game.characters[token].sheet.hitpoints.max(0) as i64; }
+        }
+        report.average_rounds = rounds as f64 / options.runs as f64;
+        report.hitpoints = hitpoints.into_iter().map(|(token, total)| (token, total as f64 / options.runs as f64)).collect();
+        return Ok(report);
+    }
+
+    /******************************************************************************
+     *  fight - Plays one combat between PARTY and the other tokens of ORDER
+     *
+     *  RETURN: True if the party won, false if the monsters did, None for a draw,
+     *          and the rounds played
+     *---------------------------------------------------------------------------*/
+    fn fight(&mut self, party: &[char], order: &[char], max_rounds: u32, abilities: &mut Usefulness) -> (Option<bool>, u32)
+    {
+        let standing = |game: &GameSession, side: bool| order.iter().any(|token| party.contains(token) == side && game.is_up(*token));
+        for round in 1..=max_rounds {
+            for token in order {
+                if !self.is_up(*token) { continue; }
+                let side = party.contains(token);
+                if !standing(self, !side) { return (Some(side), round); }
+                for req in self.propose_turn(*token, party) {
+                    if req.action_type == 2 { self.attempt(&req, party, abilities); }
+                    else { let _ = self.execute_request(*token, &req); }
+                }
+            }
+            match (standing(self, true), standing(self, false)) {
+                (true, false) => return (Some(true), round),
+                (false, true) => return (Some(false), round),
+                (false, false) => return (None, round),
+                _ => {}
+            }
+        }
+        return (None, max_rounds);
+    }
+
+    /******************************************************************************
+     *  attempt - Rolls a d20 for an ability request and executes it on a
+     *            success, its usefulness is read from the combat record, PARTY
+     *            tells the sides apart
+     *---------------------------------------------------------------------------*/
+    fn attempt(&mut self, req: &Request, party: &[char], abilities: &mut Usefulness)
+    {
+        if self.check_request(req).is_err() { return; }
+        let key = req.subtype_key.clone().unwrap_or_default();
+        let stats = abilities.entry(req.caster).or_default().entry(key).or_default();
+        stats.uses += 1;
+        let rolled_at = self.next_seq();
+        if self.roll(1, 20) < ROLL_TO_SUCCEED { return; }
+        let req = &Request { rolled_at: Some(rolled_at), ..req.clone() };
+        let record = match self.execute_request(req.caster, req) { Ok(record) => record, Err(_) => return };
+        stats.hits += 1;
+        let side = party.contains(&req.caster);
+        for change in record.changes.iter().filter(|change| record.targets.contains(&change.token)) {
+            if party.contains(&change.token) == side {
+                stats.healing += change.hitpoints.max(0) as i64;
+                continue;
+            }
+            let after = self.characters[&change.token].sheet.hitpoints;
+            stats.damage += (-change.hitpoints).max(0) as i64;
+            if after <= 0 && after - change.hitpoints > 0 { stats.kills += 1; }
+        }
+    }
+}
@@ -0,0 +1,197 @@
+/*/////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+ *
+ *      File storage for the rooms of the game server, so a restart or a crash doesn't lose the games
+ *
+ *      Implementation and Assumptions
+ *          - Each room is a directory (its id hex encoded) with three files:
+ *              snapshot.json   saved game (see migrate.rs) at the last compaction, without the event and combat logs
+ *                              it covers, its base_seq is where the event log goes on
+ *              events.jsonl    events logged since the snapshot, one JSON event per line, only ever appended
+ *              meta.json       host, access requests, pending requests, turn state and conflict policy (roles are
+ *                              in the game)
+ *          - snapshot.json and meta.json are replaced atomically (written aside then renamed), a crash leaves either
+ *            the old or the new file
+ *          - Restoring replays the event log over the snapshot (see events.rs), a line cut short by a crash ends
+ *            the log, a log that no longer replays falls back to the snapshot
+ *          - Compaction writes the current game as the new snapshot and empties the log, the server compacts a room
+ *            every COMPACT_EVERY events and after restoring it, so a restored game only keeps the events and combat
+ *            records since the last compaction
+ *
+ */////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+use serde::{Serialize, Deserialize};
+use serde_json::json;
+
+use std::fs;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::GameSession;
+use crate::events::{self, Event};
+use crate::migrate::load_save;
+use crate::protocol::{AccessRequest, Pending};
+use crate::resolve::Policy;
+
+pub const COMPACT_EVERY: usize = 256;
+const SNAPSHOT: &str = "snapshot.json";
+const EVENTS: &str = "events.jsonl";
+const META: &str = "meta.json";
+
+/***********************************************
+ * RoomRecord - Room state outside of the game
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RoomRecord
+{
+    pub id: String,
+    pub host: String,
+    pub set: String,
+    pub access: Vec<AccessRequest>,
+    pub pending: Vec<Pending>,
+    pub turn_open: bool,
+    #[serde(default)]
+    pub policy: Policy,
+    #[serde(default)]
+    pub secrets: BTreeMap<String, String>       // Secret of every user that joined, see server.rs
+}
+
+pub type StoredRoom = (RoomRecord, GameSession);
+
+/***********************************************
+ * Store - Directory holding the rooms
+ **********************************************/
+#[derive(Clone, Debug)]
+pub struct Store
+{
+    dir: PathBuf
+}
+
+impl Store
+{
+    /******************************************************************************
+     *  open - Uses DIR as the store, creating it if needed
+     *---------------------------------------------------------------------------*/
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<Store>
+    {
+        fs::create_dir_all(&dir)?;
+        return Ok(Store { dir: dir.as_ref().to_path_buf() });
+    }
+
+    /******************************************************************************
+     *  load - Restores every stored room
+     *
+     *  RETURN: The rooms with their game, and a warning for each room (or part of
+     *          a room) that couldn't be restored
+     *---------------------------------------------------------------------------*/
+    pub fn load(&self) -> io::Result<(Vec<StoredRoom>, Vec<String>)>
+    {
+        let mut rooms = Vec::new();
+        let mut warnings = Vec::new();
+        let mut dirs: Vec<PathBuf> = fs::read_dir(&self.dir)?.filter_map(|entry| entry.ok()).map(|entry| entry.path())
+            .filter(|path| path.is_dir()).collect();
+        dirs.sort();
+        for dir in dirs {
+            match load_room(&dir, &mut warnings) {
+                Ok(room) => rooms.push(room),
+                Err(reason) => warnings.push(format!("{}: {}", dir.display(), reason))
+            }
+        }
+        return Ok((rooms, warnings));
+    }
+
+    /******************************************************************************
+     *  save_meta - Replaces the room state kept outside of the game
+     *---------------------------------------------------------------------------*/
+    pub fn save_meta(&self, record: &RoomRecord) -> io::Result<()>
+    {
+        let dir = self.room_dir(&record.id);
+        fs::create_dir_all(&dir)?;
+        return write_atomic(&dir.join(META), serde_json::to_string(record).unwrap().as_bytes());
+    }
+
+    /******************************************************************************
+     *  append_events - Adds events to the log of a room
+     *---------------------------------------------------------------------------*/
+    pub fn append_events(&self, id: &str, events: &[Event]) -> io::Result<()>
+    {
+        if events.is_empty() { return Ok(()); }
+        let mut lines = String::new();
+        for event in events { lines += &format!("{}\n", serde_json::to_string(event).unwrap()); }
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(self.room_dir(id).join(EVENTS))?;
+        file.write_all(lines.as_bytes())?;
+        return file.sync_data();
+    }
+
+    /******************************************************************************
+     *  compact - Saves GAME as the snapshot of a room, without the events it
+     *            covers, and empties its log
+     *---------------------------------------------------------------------------*/
+    pub fn compact(&self, id: &str, game: &GameSession) -> io::Result<()>
+    {
+        let dir = self.room_dir(id);
+        fs::create_dir_all(&dir)?;
+        let mut snapshot = game.to_save();
+        snapshot["events"] = json!([]);
+        snapshot["combat_log"] = json!([]);
+        snapshot["base_seq"] = json!(game.next_seq());
+        write_atomic(&dir.join(SNAPSHOT), serde_json::to_string(&snapshot).unwrap().as_bytes())?;
+        return fs::write(dir.join(EVENTS), b"");
+    }
+
+    /******************************************************************************
+     *  remove - Deletes a stored room
+     *---------------------------------------------------------------------------*/
+    pub fn remove(&self, id: &str) -> io::Result<()>
+    {
+        fs::remove_dir_all(self.room_dir(id))
+    }
+
+    fn room_dir(&self, id: &str) -> PathBuf
+    {
+        self.dir.join(id.bytes().map(|byte| format!("{:02x}", byte)).collect::<String>())
+    }
+}
+
+/******************************************************************************
+ *  load_room - Reads the files of one room and replays its log
+ *---------------------------------------------------------------------------*/
+fn load_room(dir: &Path, warnings: &mut Vec<String>) -> Result<StoredRoom, String>
+{
+    let read = |name: &str| fs::read_to_string(dir.join(name)).map_err(|err| format!("{}: {}", name, err));
+    let record: RoomRecord = serde_json::from_str(&read(META)?).map_err(|err| format!("{}: {}", META, err))?;
+    let snapshot = serde_json::from_str(&read(SNAPSHOT)?).map_err(|err| format!("{}: {}", SNAPSHOT, err))?;
+    let (snapshot, _) = load_save(snapshot).map_err(|err| format!("{}: {}", SNAPSHOT, err))?;
+
+    let mut logged = Vec::new();
+    for (n, line) in read(EVENTS).unwrap_or_default().lines().enumerate() {
+        match serde_json::from_str(line) {
+            Ok(event) => logged.push(event),
+            Err(_) => {
+                warnings.push(format!("room '{}': event log cut short at line {}", record.id, n + 1));
+                break;
+            }
+        }
+    }
+    let mut game = match events::replay(&snapshot, &logged, None) {
+        Ok(game) => game,
+        Err(err) => {
+            warnings.push(format!("room '{}': {}, restored from the last snapshot", record.id, err));
+            snapshot
+        }
+    };
+    if !record.turn_open { game.requests.clear(); }    // Requests of a closed turn were handed over as pending
+    return Ok((record, game));
+}
+
+/******************************************************************************
+ *  write_atomic - Writes a file aside and renames it over PATH
+ *---------------------------------------------------------------------------*/
+fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()>
+{
+    let temp = path.with_extension("tmp");
+    let mut file = fs::File::create(&temp)?;
+    file.write_all(bytes)?;
+    file.sync_data()?;
+    return fs::rename(temp, path);
+}
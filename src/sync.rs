@@ -0,0 +1,302 @@
+/*/////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+ *
+ *      Incremental sync for ByteDungeon, compact deltas between two states of a session
+ *
+ *      Implementation and Assumptions
+ *          - Commands and undo/redo bump GameSession.version (see events.rs), a delta goes from one version to a later
+ *            one, requests and rolls a client logs alone leave its version matching the host's
+ *          - A delta only applies on a session at its FROM version, out of order, repeated or backward patches are
+ *            rejected. A delta from a version to itself changes at most the dice and applies as is
+ *          - The dice generator is sent when it moved, rolls don't bump the version but are part of the hashed state
+ *          - Grids that kept their size send only the cells that changed, resized/new maps are sent whole
+ *          - Tokens that only moved, lost/gained hitpoints or changed effects send just that, anything else sends
+ *            the whole token
+ *          - Requests, the event and combat logs and the undo history stay with the host and are never part of a delta
+ *          - Applying a delta is all or nothing, a delta that doesn't fit the session leaves it untouched
+ *
+ */////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+use serde::{Serialize, Deserialize};
+
+use std::fmt;
+use std::hash::Hash;
+use std::collections::HashMap;
+use std::collections::BTreeMap;
+
+use crate::{GameSession, Token, Character, Item, Ability, Effect};
+use crate::maps::{Cell, MapLink, MapObject, MAIN_MAP};
+use crate::permissions::Permissions;
+use crate::layers::MapNote;
+use crate::ai::Behaviour;
+use crate::rng::Rng;
+
+/***********************************************
+ * TokenDelta - How a token changed
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(ts_rs::TS), ts(optional_fields))]
+pub enum TokenDelta
+{
+    Removed,
+    Placed(Box<Token>),                         // New token, or too many changes to send them one by one
+    Changed {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cell: Option<Cell>,                     // New position
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        hitpoints: Option<i32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        effects: Option<HashMap<String, Effect>>
+    }
+}
+
+/***********************************************
+ * SessionDelta - Changes between two versions
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(ts_rs::TS), ts(optional_fields))]
+pub struct SessionDelta
+{
+    pub from: u64,
+    pub to: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[cfg_attr(not(target_arch = "wasm32"), ts(as = "Option<_>"))]
+    pub grids: Vec<(String, Option<Vec<Vec<char>>>)>,   // Maps added, resized or removed (None)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[cfg_attr(not(target_arch = "wasm32"), ts(as = "Option<_>"))]
+    pub cells: Vec<(Cell, char)>,                       // Changed cells of maps that kept their size
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[cfg_attr(not(target_arch = "wasm32"), ts(as = "Option<_>"))]
+    pub tokens: Vec<(char, TokenDelta)>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[cfg_attr(not(target_arch = "wasm32"), ts(as = "Option<_>"))]
+    pub sheets: Vec<(char, Option<Character>)>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[cfg_attr(not(target_arch = "wasm32"), ts(as = "Option<_>"))]
+    pub items: Vec<(String, Option<Item>)>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[cfg_attr(not(target_arch = "wasm32"), ts(as = "Option<_>"))]
+    pub abilities: Vec<(String, Option<Ability>)>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[cfg_attr(not(target_arch = "wasm32"), ts(as = "Option<_>"))]
+    pub effects: Vec<(String, Option<Effect>)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub links: Option<Vec<MapLink>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub objects: Option<Vec<MapObject>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permissions: Option<Permissions>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<Vec<MapNote>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub behaviours: Option<BTreeMap<char, Behaviour>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rng: Option<Rng>                                // Dice generator, if it rolled since FROM
+}
+
+/***********************************************
+ * DeltaError - Patches that were rejected
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum DeltaError
+{
+    Version { version: u64, from: u64 },        // Session is at VERSION, the patch starts at FROM
+    Backward { from: u64, to: u64 },            // Patch doesn't lead to a later version
+    UnknownMap(String),
+    OutOfBounds(Cell),
+    UnknownToken(char)
+}
+
+impl fmt::Display for DeltaError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeltaError::Version { version, from } =>
+                write!(f, "patch starts at version {} but the session is at version {}", from, version),
+            DeltaError::Backward { from, to } => write!(f, "patch goes from version {} back to version {}", from, to),
+            DeltaError::UnknownMap(map) => write!(f, "no map named '{}'", map),
+            DeltaError::OutOfBounds(cell) => write!(f, "cell {},{} is outside map '{}'", cell.row, cell.column, cell.map),
+            DeltaError::UnknownToken(token) => write!(f, "no token '{}' in play", token)
+        }
+    }
+}
+
+impl SessionDelta
+{
+    /******************************************************************************
+     *  is_empty - True if the delta changes nothing but the version and the dice
+     *---------------------------------------------------------------------------*/
+    pub fn is_empty(&self) -> bool
+    {
+        self.grids.is_empty() && self.cells.is_empty() && self.tokens.is_empty() && self.sheets.is_empty()
+            && self.items.is_empty() && self.abilities.is_empty() && self.effects.is_empty()
+            && self.links.is_none() && self.objects.is_none() && self.permissions.is_none() && self.notes.is_none()
+            && self.behaviours.is_none()
+    }
+}
+
+/******************************************************************************
+ *  diff - Computes the delta that turns OLD into NEW
+ *---------------------------------------------------------------------------*/
+pub fn diff(old: &GameSession, new: &GameSession) -> SessionDelta
+{
+    let mut delta = SessionDelta { from: old.version, to: new.version, ..Default::default() };
+    for id in new.map_ids() {
+        let grid = new.board(&id).unwrap();
+        match old.board(&id) {
+            Some(before) if same_size(before, grid) => {
+                for (row, cells) in grid.iter().enumerate() {
+                    for (col, c) in cells.iter().enumerate() {
+                        if before[row][col] != *c { delta.cells.push((Cell::new(&id, row, col), *c)); }
+                    }
+                }
+            },
+            _ => delta.grids.push((id.clone(), Some(grid.clone())))
+        }
+    }
+    for id in old.map_ids() {
+        if new.board(&id).is_none() { delta.grids.push((id, None)); }
+    }
+
+    let mut keys: Vec<&char> = old.characters.keys().chain(new.characters.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    for key in keys {
+        match (old.characters.get(key), new.characters.get(key)) {
+            (Some(_), None) => delta.tokens.push((*key, TokenDelta::Removed)),
+            (None, Some(token)) => delta.tokens.push((*key, TokenDelta::Placed(Box::new(token.clone())))),
+            (Some(before), Some(token)) if before != token => delta.tokens.push((*key, token_delta(before, token))),
+            _ => {}
+        }
+    }
+
+    delta.sheets = keyed_changes(&old.sheets, &new.sheets);
+    delta.items = keyed_changes(&old.items, &new.items);
+    delta.abilities = keyed_changes(&old.abilities, &new.abilities);
+    delta.effects = keyed_changes(&old.effects, &new.effects);
+    if old.links != new.links { delta.links = Some(new.links.clone()); }
+    if old.objects != new.objects { delta.objects = Some(new.objects.clone()); }
+    if old.permissions != new.permissions { delta.permissions = Some(new.permissions.clone()); }
+    if old.notes != new.notes { delta.notes = Some(new.notes.clone()); }
+    if old.behaviours != new.behaviours { delta.behaviours = Some(new.behaviours.clone()); }
+    if old.rng != new.rng { delta.rng = Some(new.rng.clone()); }
+    return delta;
+}
+
+impl GameSession
+{
+    /******************************************************************************
+     *  apply_delta - Patches the session with a delta made from its version, a
+     *                delta of nothing but the dice may keep the version
+     *---------------------------------------------------------------------------*/
+    pub fn apply_delta(&mut self, delta: &SessionDelta) -> Result<(), DeltaError>
+    {
+        if delta.to < delta.from || (delta.to == delta.from && !delta.is_empty()) {
+            return Err(DeltaError::Backward { from: delta.from, to: delta.to });
+        }
+        if delta.from != self.version { return Err(DeltaError::Version { version: self.version, from: delta.from }); }
+        let mut next = self.clone();
+        for (id, grid) in &delta.grids {
+            match grid {
+                Some(grid) if id == MAIN_MAP => next.grid = grid.clone(),
+                Some(grid) => { next.maps.insert(id.clone(), grid.clone()); },
+                None => { next.maps.remove(id); }
+            }
+        }
+        for (cell, c) in &delta.cells {
+            if next.board(&cell.map).is_none() { return Err(DeltaError::UnknownMap(cell.map.clone())); }
+            if !next.in_bounds(cell) { return Err(DeltaError::OutOfBounds(cell.clone())); }
+            next.board_mut(&cell.map).unwrap()[cell.row][cell.column] = *c;
+        }
+        for (key, change) in &delta.tokens {
+            match change {
+                TokenDelta::Removed => { next.characters.remove(key); },
+                TokenDelta::Placed(token) => { next.characters.insert(*key, (**token).clone()); },
+                TokenDelta::Changed { cell, hitpoints, effects } => {
+                    let token = next.characters.get_mut(key).ok_or(DeltaError::UnknownToken(*key))?;
+                    if let Some(cell) = cell {
+                        token.map = cell.map.clone();
+                        token.row = cell.row;
+                        token.column = cell.column;
+                    }
+                    if let Some(hitpoints) = hitpoints { token.sheet.hitpoints = *hitpoints; }
+                    if let Some(effects) = effects { token.sheet.effects = effects.clone(); }
+                }
+            }
+        }
+        apply_keyed(&mut next.sheets, &delta.sheets);
+        apply_keyed(&mut next.items, &delta.items);
+        apply_keyed(&mut next.abilities, &delta.abilities);
+        apply_keyed(&mut next.effects, &delta.effects);
+        if let Some(links) = &delta.links { next.links = links.clone(); }
+        if let Some(objects) = &delta.objects { next.objects = objects.clone(); }
+        if let Some(permissions) = &delta.permissions { next.permissions = permissions.clone(); }
+        if let Some(notes) = &delta.notes { next.notes = notes.clone(); }
+        if let Some(behaviours) = &delta.behaviours { next.behaviours = behaviours.clone(); }
+        if let Some(rng) = &delta.rng { next.rng = rng.clone(); }
+
+        next.version = delta.to;
+        next.history = std::mem::take(&mut self.history);
+        next.events = std::mem::take(&mut self.events);
+        next.combat_log = std::mem::take(&mut self.combat_log);
+        *self = next;
+        return Ok(());
+    }
+}
+
+/******************************************************************************
+ *  token_delta - Smallest description of how a token changed
+ *---------------------------------------------------------------------------*/
+fn token_delta(old: &Token, new: &Token) -> TokenDelta
+{
+    let mut rest = new.clone();
+    rest.map = old.map.clone();
+    rest.row = old.row;
+    rest.column = old.column;
+    rest.sheet.hitpoints = old.sheet.hitpoints;
+    rest.sheet.effects = old.sheet.effects.clone();
+    if &rest != old { return TokenDelta::Placed(Box::new(new.clone())); }
+
+    let moved = old.map != new.map || old.row != new.row || old.column != new.column;
+    TokenDelta::Changed {
+        cell: if moved { Some(Cell::new(&new.map, new.row, new.column)) } else { None },
+        hitpoints: if old.sheet.hitpoints != new.sheet.hitpoints { Some(new.sheet.hitpoints) } else { None },
+        effects: if old.sheet.effects != new.sheet.effects { Some(new.sheet.effects.clone()) } else { None }
+    }
+}
+
+/******************************************************************************
+ *  keyed_changes - Added/changed (Some) and removed (None) entries of a map
+ *---------------------------------------------------------------------------*/
+fn keyed_changes<K, V>(old: &HashMap<K, V>, new: &HashMap<K, V>) -> Vec<(K, Option<V>)>
+    where K: Clone + Eq + Hash + Ord, V: Clone + PartialEq
+{
+    let mut result: Vec<(K, Option<V>)> = new.iter()
+        .filter(|(key, value)| old.get(*key) != Some(*value))
+        .map(|(key, value)| (key.clone(), Some(value.clone())))
+        .collect();
+    result.extend(old.keys().filter(|key| !new.contains_key(*key)).map(|key| (key.clone(), None)));
+    result.sort_by(|a, b| a.0.cmp(&b.0));
+    return result;
+}
+
+/******************************************************************************
+ *  apply_keyed - Applies changes made by keyed_changes
+ *---------------------------------------------------------------------------*/
+fn apply_keyed<K, V>(map: &mut HashMap<K, V>, changes: &[(K, Option<V>)])
+    where K: Clone + Eq + Hash, V: Clone
+{
+    for (key, value) in changes {
+        match value {
+            Some(value) => { map.insert(key.clone(), value.clone()); },
+            None => { map.remove(key); }
+        }
+    }
+}
+
+/******************************************************************************
+ *  same_size - True if both grids have the same rows and columns
+ *---------------------------------------------------------------------------*/
+fn same_size(a: &[Vec<char>], b: &[Vec<char>]) -> bool
+{
+    a.len() == b.len() && a.iter().zip(b).all(|(x, y)| x.len() == y.len())
+}
@@ -0,0 +1,400 @@
+/*/////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+ *
+ *      Tiled map editor importer for ByteDungeon, reads Tiled JSON maps (.tmj/.json) into a session map
+ *
+ *      Implementation and Assumptions
+ *          - Only orthogonal, finite maps with CSV/array tile data can be converted cell by cell
+ *          - A TiledMapping tells what each global tile id and each object type means (wall, door, terrain, spawn...)
+ *          - Tile layers are read bottom to top, a wall tile on any layer makes the cell a wall
+ *          - Cells no layer covers take the mapping's EMPTY meaning, walls by default (void around the dungeon)
+ *          - Object layers place objects on every cell their rectangle covers, points/tiles on a single cell
+ *          - Anything that can't be converted is listed in the report instead of being silently dropped, objects
+ *            and spawns that end up on a wall or on a cell another token already spawns on included
+ *          - Maps larger than the generator's MAX_CELLS are refused before anything is allocated
+ *
+ */////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+
+use std::fmt;
+use std::convert::TryFrom;
+use std::collections::BTreeMap;
+
+use crate::GameSession;
+use crate::maps::ObjectKind;
+use crate::generator::MAX_CELLS;
+
+const FLIP_FLAGS: u32 = 0xF000_0000;            // Tiled stores flips/rotations in the top bits of a gid
+
+/***********************************************
+ * TileMeaning - What a tile or object becomes
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum TileMeaning
+{
+    Wall,
+    Floor,
+    Door,
+    Trap,
+    Terrain(String),
+    Spawn(Option<char>),                        // None: token taken from the object's name/"token" property
+    Ignore                                      // Decoration, the tile doesn't change the cell
+}
+
+/***********************************************
+ * TiledMapping - Tile id / object type lookup
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct TiledMapping
+{
+    pub tiles: BTreeMap<u32, TileMeaning>,      // Global tile id (gid, as shown by Tiled + firstgid) to meaning
+    pub objects: BTreeMap<String, TileMeaning>, // Object class/type (or name when it has none) to meaning
+    pub empty: TileMeaning,                     // Meaning of cells that no tile layer covers
+    pub unmapped: TileMeaning                   // Meaning of tiles missing from TILES, reported once per gid
+}
+
+/***********************************************
+ * TiledReport - Summary of an import
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct TiledReport
+{
+    pub rows: usize,
+    pub cols: usize,
+    pub objects: usize,
+    pub tokens: usize,                          // Token spawns, placed if their sheet exists
+    pub unsupported: Vec<String>                // Every feature that was skipped or approximated
+}
+
+/***********************************************
+ * TiledError - Maps that can't be imported
+ **********************************************/
+#[derive(Clone, Debug, PartialEq)]
+pub enum TiledError
+{
+    Json(String),
+    Infinite,                                   // Chunked maps have no fixed size to convert
+    Empty,
+    TooLarge { rows: usize, cols: usize },
+    LayerSize { layer: String, expected: usize, found: usize }
+}
+
+#[derive(Deserialize)]
+struct TiledMap
+{
+    width: usize,
+    height: usize,
+    tilewidth: f64,
+    tileheight: f64,
+    #[serde(default)]
+    infinite: bool,
+    #[serde(default)]
+    orientation: String,
+    #[serde(default)]
+    layers: Vec<TiledLayer>
+}
+
+#[derive(Deserialize)]
+struct TiledLayer
+{
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    data: Option<Value>,
+    #[serde(default)]
+    encoding: Option<String>,
+    #[serde(default)]
+    compression: Option<String>,
+    #[serde(default)]
+    offsetx: f64,
+    #[serde(default)]
+    offsety: f64,
+    #[serde(default)]
+    objects: Vec<TiledObject>,
+    #[serde(default)]
+    layers: Vec<TiledLayer>
+}
+
+#[derive(Deserialize)]
+struct TiledObject
+{
+    #[serde(default)]
+    id: u32,
+    #[serde(default)]
+    name: String,
+    #[serde(default, rename = "type")]
+    kind: String,
+    #[serde(default)]
+    class: String,
+    x: f64,
+    y: f64,
+    #[serde(default)]
+    width: f64,
+    #[serde(default)]
+    height: f64,
+    #[serde(default)]
+    gid: Option<u32>,
+    #[serde(default)]
+    point: bool,
+    #[serde(default)]
+    ellipse: bool,
+    #[serde(default)]
+    polygon: Option<Value>,
+    #[serde(default)]
+    polyline: Option<Value>,
+    #[serde(default)]
+    text: Option<Value>,
+    #[serde(default)]
+    properties: Vec<TiledProperty>
+}
+
+#[derive(Deserialize)]
+struct TiledProperty
+{
+    name: String,
+    #[serde(default)]
+    value: Value
+}
+
+/***********************************************
+ * Converted - Layout being built from layers
+ **********************************************/
+struct Converted
+{
+    walls: Vec<Vec<bool>>,
+    covered: Vec<Vec<bool>>,
+    objects: Vec<(usize, usize, ObjectKind)>,
+    tokens: Vec<(usize, usize, char)>,
+    report: TiledReport
+}
+
+impl Default for TiledMapping
+{
+    fn default() -> TiledMapping {
+        TiledMapping { tiles: BTreeMap::new(), objects: BTreeMap::new(), empty: TileMeaning::Wall, unmapped: TileMeaning::Floor }
+    }
+}
+
+impl fmt::Display for TiledError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TiledError::Json(err) => write!(f, "not a Tiled JSON map: {}", err),
+            TiledError::Infinite => write!(f, "infinite maps are not supported, resize the map to a fixed size in Tiled"),
+            TiledError::Empty => write!(f, "map has no cells"),
+            TiledError::TooLarge { rows, cols } =>
+                write!(f, "map of {}x{} is too large, at most {} cells are allowed", rows, cols, MAX_CELLS),
+            TiledError::LayerSize { layer, expected, found } =>
+                write!(f, "layer '{}' has {} tiles, expected {}", layer, found, expected)
+        }
+    }
+}
+
+impl GameSession
+{
+    /******************************************************************************
+     *  import_tiled - Replaces a map's layout with a Tiled JSON map
+     *
+     *  RETURNS: Report listing every unsupported feature, the session is only
+     *           changed on success, see GameSession::replace_layout
+     *---------------------------------------------------------------------------*/
+    pub fn import_tiled(&mut self, map: &str, json: &str, mapping: &TiledMapping) -> Result<TiledReport, TiledError>
+    {
+        let tiled: TiledMap = serde_json::from_str(json).map_err(|err| TiledError::Json(err.to_string()))?;
+        if tiled.infinite { return Err(TiledError::Infinite); }
+        if tiled.width == 0 || tiled.height == 0 { return Err(TiledError::Empty); }
+        if tiled.width.saturating_mul(tiled.height) > MAX_CELLS { return Err(TiledError::TooLarge { rows: tiled.height, cols: tiled.width }); }
+
+        let mut converted = Converted {
+            walls: vec![vec![false; tiled.width]; tiled.height],
+            covered: vec![vec![false; tiled.width]; tiled.height],
+            objects: Vec::new(),
+            tokens: Vec::new(),
+            report: TiledReport { rows: tiled.height, cols: tiled.width, ..Default::default() }
+        };
+        if !tiled.orientation.is_empty() && tiled.orientation != "orthogonal" {
+            converted.report.unsupported.push(format!("{} orientation, cells were read as an orthogonal grid", tiled.orientation));
+        }
+        converted.read_layers(&tiled, &tiled.layers, mapping)?;
+
+        let mut grid = vec![vec!['0'; tiled.width]; tiled.height];
+        for (row, cells) in grid.iter_mut().enumerate() {
+            for (col, cell) in cells.iter_mut().enumerate() {
+                let wall = converted.walls[row][col] || (!converted.covered[row][col] && mapping.empty == TileMeaning::Wall);
+                if wall { *cell = '1'; }
+                else if !converted.covered[row][col] { converted.apply(&mapping.empty, row, col, None); }
+            }
+        }
+        let report = &mut converted.report;
+        converted.objects.retain(|obj| {
+            let kept = grid[obj.0][obj.1] == '0';
+            if !kept { report.unsupported.push(format!("{:?} at {},{} is on a wall, it was dropped", obj.2, obj.0, obj.1)); }
+            kept
+        });
+        converted.tokens.retain(|tok| {
+            let kept = grid[tok.0][tok.1] == '0';
+            if !kept { report.unsupported.push(format!("spawn of '{}' at {},{} is on a wall, it was dropped", tok.2, tok.0, tok.1)); }
+            kept
+        });
+        converted.report.objects = converted.objects.len();
+        converted.report.tokens = converted.tokens.len();
+
+        self.replace_layout(map, grid, converted.objects, converted.tokens);
+        return Ok(converted.report);
+    }
+}
+
+impl Converted
+{
+    /******************************************************************************
+     *  read_layers - Converts tile and object layers, recursing into groups
+     *---------------------------------------------------------------------------*/
+    fn read_layers(&mut self, tiled: &TiledMap, layers: &[TiledLayer], mapping: &TiledMapping) -> Result<(), TiledError>
+    {
+        for layer in layers {
+            if layer.offsetx != 0.0 || layer.offsety != 0.0 {
+                self.report.unsupported.push(format!("layer '{}' has a pixel offset, it was ignored", layer.name));
+            }
+            match layer.kind.as_str() {
+                "tilelayer" => self.read_tiles(tiled, layer, mapping)?,
+                "objectgroup" => {
+                    for object in &layer.objects { self.read_object(tiled, &layer.name, object, mapping); }
+                },
+                "group" => self.read_layers(tiled, &layer.layers, mapping)?,
+                other => self.report.unsupported.push(format!("{} '{}' was skipped", other, layer.name))
+            }
+        }
+        return Ok(());
+    }
+
+    /******************************************************************************
+     *  read_tiles - Applies the meaning of every tile of a tile layer
+     *---------------------------------------------------------------------------*/
+    fn read_tiles(&mut self, tiled: &TiledMap, layer: &TiledLayer, mapping: &TiledMapping) -> Result<(), TiledError>
+    {
+        let data = match (&layer.data, &layer.encoding, &layer.compression) {
+            (Some(Value::Array(data)), None, None) => data,
+            (Some(Value::Array(data)), Some(encoding), None) if encoding == "csv" => data,
+            _ => {
+                self.report.unsupported.push(format!("tile layer '{}' uses base64/compressed data, save the map with \
+                    CSV tile layer format", layer.name));
+                return Ok(());
+            }
+        };
+        let expected = tiled.width * tiled.height;
+        if data.len() != expected {
+            return Err(TiledError::LayerSize { layer: layer.name.clone(), expected, found: data.len() });
+        }
+
+        let mut flipped = false;
+        let mut invalid = 0;
+        let mut missing: Vec<u32> = Vec::new();
+        for (n, value) in data.iter().enumerate() {
+            let raw = match value.as_u64().and_then(|raw| u32::try_from(raw).ok()) {
+                Some(raw) => raw,
+                None => { invalid += 1; continue; }
+            };
+            let gid = raw & !FLIP_FLAGS;
+            if gid == 0 { continue; }
+            flipped |= raw & FLIP_FLAGS != 0;
+            let meaning = match mapping.tiles.get(&gid) {
+                Some(meaning) => meaning,
+                None => {
+                    if !missing.contains(&gid) { missing.push(gid); }
+                    &mapping.unmapped
+                }
+            };
+            self.apply(meaning, n / tiled.width, n % tiled.width, None);
+        }
+        if flipped { self.report.unsupported.push(format!("layer '{}' has flipped/rotated tiles, flips were ignored", layer.name)); }
+        if invalid > 0 {
+            self.report.unsupported.push(format!("layer '{}' has {} values that aren't tile ids, they were read as empty", layer.name, invalid));
+        }
+        for gid in missing {
+            self.report.unsupported.push(format!("tile id {} in layer '{}' has no mapping, read as {:?}", gid, layer.name, mapping.unmapped));
+        }
+        return Ok(());
+    }
+
+    /******************************************************************************
+     *  read_object - Applies an object's meaning on the cells it covers
+     *---------------------------------------------------------------------------*/
+    fn read_object(&mut self, tiled: &TiledMap, layer: &str, object: &TiledObject, mapping: &TiledMapping)
+    {
+        let label = format!("object {} '{}' in layer '{}'", object.id, object.name, layer);
+        if object.polygon.is_some() || object.polyline.is_some() || object.text.is_some() {
+            self.report.unsupported.push(format!("{} is a polygon/polyline/text, it was skipped", label));
+            return;
+        }
+        let key = if !object.class.is_empty() { &object.class } else if !object.kind.is_empty() { &object.kind } else { &object.name };
+        let meaning = match mapping.objects.get(key) {
+            Some(meaning) => meaning,
+            None => {
+                self.report.unsupported.push(format!("{} has no mapping for '{}', it was skipped", label, key));
+                return;
+            }
+        };
+        if object.ellipse { self.report.unsupported.push(format!("{} is an ellipse, its bounding box was used", label)); }
+
+        // Tile objects are anchored on their bottom left corner, everything else on the top left
+        let top = if object.gid.is_some() { object.y - object.height } else { object.y };
+        let single = object.point || object.width <= 0.0 || object.height <= 0.0 || matches!(meaning, TileMeaning::Spawn(_));
+        if top < 0.0 || object.x < 0.0 {
+            if single || top + object.height <= 0.0 || object.x + object.width <= 0.0 {
+                self.report.unsupported.push(format!("{} is outside the map, it was skipped", label));
+                return;
+            }
+            self.report.unsupported.push(format!("{} starts outside the map, it was cut to the cells inside", label));
+        }
+        let first_row = (top / tiled.tileheight).floor().max(0.0) as usize;
+        let first_col = (object.x / tiled.tilewidth).floor().max(0.0) as usize;
+        let last_row = if single { first_row } else { (((top + object.height) / tiled.tileheight).ceil() as usize).max(first_row + 1) - 1 };
+        let last_col = if single { first_col } else { (((object.x + object.width) / tiled.tilewidth).ceil() as usize).max(first_col + 1) - 1 };
+        if first_row >= tiled.height || first_col >= tiled.width {
+            self.report.unsupported.push(format!("{} is outside the map, it was skipped", label));
+            return;
+        }
+
+        let token = object.properties.iter()
+            .find(|prop| prop.name == "token")
+            .and_then(|prop| prop.value.as_str())
+            .unwrap_or(&object.name)
+            .chars()
+            .next();
+        if matches!(meaning, TileMeaning::Spawn(None)) && token.is_none() {
+            self.report.unsupported.push(format!("{} is a spawn without a token name, left as an open spawn point", label));
+        }
+        for row in first_row..=last_row.min(tiled.height - 1) {
+            for col in first_col..=last_col.min(tiled.width - 1) { self.apply(meaning, row, col, token); }
+        }
+    }
+
+    /******************************************************************************
+     *  apply - Applies a meaning on a cell, TOKEN names unreserved spawns. A
+     *          second token spawned on the same cell is dropped and reported
+     *---------------------------------------------------------------------------*/
+    fn apply(&mut self, meaning: &TileMeaning, row: usize, col: usize, token: Option<char>)
+    {
+        if *meaning == TileMeaning::Ignore { return; }
+        self.covered[row][col] = true;
+        match meaning {
+            TileMeaning::Wall => self.walls[row][col] = true,
+            TileMeaning::Door => self.objects.push((row, col, ObjectKind::Door)),
+            TileMeaning::Trap => self.objects.push((row, col, ObjectKind::Trap)),
+            TileMeaning::Terrain(name) => self.objects.push((row, col, ObjectKind::Terrain(name.clone()))),
+            TileMeaning::Spawn(reserved) => match reserved.or(token) {
+                Some(key) if !self.tokens.iter().any(|tok| tok.2 == key) => match self.tokens.iter().find(|tok| (tok.0, tok.1) == (row, col)) {
+                    Some(taken) => self.report.unsupported.push(format!("spawn of '{}' at {},{} is taken by '{}', it was dropped",
+                                                                        key, row, col, taken.2)),
+                    None => self.tokens.push((row, col, key))
+                },
+                _ => self.objects.push((row, col, ObjectKind::Spawn(None)))
+            },
+            TileMeaning::Floor | TileMeaning::Ignore => {}
+        }
+    }
+}
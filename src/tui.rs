@@ -0,0 +1,514 @@
+/*/////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+ *
+ *      Terminal client for ByteDungeon, plays a session locally without the web app (byte-dungeon play <save>)
+ *
+ *      Implementation and Assumptions
+ *          - Native only (crossterm), the wasm build never compiles this module
+ *          - App holds the whole client state and reacts to keys, drawing is a separate pass over that state so
+ *            the client can be driven (and tested) without a terminal
+ *          - The player queues requests for any token, executing the queue logs them, sorts them by initiative and
+ *            runs each one as a command, so undo and the event log work the same as in the web client. Requests
+ *            check_request refuses are logged and skipped, like the command line and the server do
+ *          - Movement and targeting options come from the same pathfinding as collect_cell_options
+ *          - Non ASCII tokens (emoji) are assumed to be two columns wide, every cell is drawn two columns wide
+ *
+ */////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+use crossterm::{cursor, queue, terminal};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::style::{Attribute, Color, Print, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor};
+
+use std::fs;
+use std::panic;
+use std::io::{self, Write};
+use std::collections::HashSet;
+
+use crate::{GameSession, Request, get_action_range};
+use crate::binary;
+use crate::history::{Command, Applied};
+use crate::maps::{Cell, MAIN_MAP};
+
+const LOG_LINES: usize = 8;
+const HELP: &str = "arrows move  enter select  tab next token  m move  a ability  i item  x execute  c clear  u undo  \
+                    [ ] map  w save  q quit";
+
+/***********************************************
+ * Mode - What the keys currently act on
+ **********************************************/
+#[derive(Clone, Debug, PartialEq)]
+pub enum Mode
+{
+    Browse,
+    Move,                                       // Picking a destination among the options
+    Abilities,                                  // Picking an ability of the selected token by number
+    Items,                                      // Picking an item of the selected token by number
+    Target(String)                              // Picking the target of an ability among the options
+}
+
+/***********************************************
+ * App - State of the terminal client
+ **********************************************/
+pub struct App
+{
+    pub game: GameSession,
+    pub map: String,                            // Map being shown
+    pub cursor: (usize, usize),
+    pub selected: Option<char>,
+    pub mode: Mode,
+    pub options: HashSet<(i32, i32)>,           // Highlighted cells of Move and Target
+    pub queue: Vec<Request>,                    // Requests waiting to be executed
+    pub log: Vec<String>,
+    pub quit: bool,
+    path: Option<String>                        // Where w saves the game
+}
+
+/******************************************************************************
+ *  run - Plays GAME in the terminal until the player quits, PATH is where the
+ *        game is saved (JSON for .json files, binary otherwise). A panic gives
+ *        the terminal back before its message is printed
+ *---------------------------------------------------------------------------*/
+pub fn run(game: GameSession, path: Option<String>) -> io::Result<()>
+{
+    let mut app = App::new(game, path);
+    let mut out = io::stdout();
+    terminal::enable_raw_mode()?;
+    queue!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+    let print_panic = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = leave_terminal(&mut io::stdout());
+        print_panic(info);
+    }));
+
+    let mut result = Ok(());
+    while !app.quit {
+        if let Err(err) = app.render(&mut out).and_then(|_| out.flush()) { result = Err(err); break; }
+        match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => app.handle(key.code),
+            Ok(_) => {},
+            Err(err) => { result = Err(err); break; }
+        }
+    }
+    let _ = panic::take_hook();
+    leave_terminal(&mut out)?;
+    return result;
+}
+
+/******************************************************************************
+ *  leave_terminal - Shows the cursor, leaves the alternate screen and raw mode
+ *---------------------------------------------------------------------------*/
+fn leave_terminal(out: &mut impl Write) -> io::Result<()>
+{
+    queue!(out, cursor::Show, terminal::LeaveAlternateScreen)?;
+    out.flush()?;
+    return terminal::disable_raw_mode();
+}
+
+impl App
+{
+    pub fn new(game: GameSession, path: Option<String>) -> App
+    {
+        let mut app = App {
+            game, path,
+            map: MAIN_MAP.to_string(),
+            cursor: (0, 0),
+            selected: None,
+            mode: Mode::Browse,
+            options: HashSet::new(),
+            queue: Vec::new(),
+            log: Vec::new(),
+            quit: false
+        };
+        if let Some(token) = app.tokens().first() { app.select(*token); }
+        app.log.push("Welcome, press tab to pick a token".to_string());
+        return app;
+    }
+
+    /******************************************************************************
+     *  handle - Reacts to a key press
+     *---------------------------------------------------------------------------*/
+    pub fn handle(&mut self, key: KeyCode)
+    {
+        match key {
+            KeyCode::Up | KeyCode::Char('k') => { return self.move_cursor(-1, 0); },
+            KeyCode::Down | KeyCode::Char('j') => { return self.move_cursor(1, 0); },
+            KeyCode::Left | KeyCode::Char('h') => { return self.move_cursor(0, -1); },
+            KeyCode::Right | KeyCode::Char('l') => { return self.move_cursor(0, 1); },
+            KeyCode::Esc if self.mode != Mode::Browse => { return self.browse(); },
+            _ => {}
+        }
+        match (self.mode.clone(), key) {
+            (Mode::Abilities, KeyCode::Char(c)) if c.is_ascii_digit() => self.choose_ability(c),
+            (Mode::Items, KeyCode::Char(c)) if c.is_ascii_digit() => self.choose_item(c),
+            (Mode::Move, KeyCode::Enter) => self.queue_move(),
+            (Mode::Target(ability), KeyCode::Enter) => self.queue_target(&ability),
+            (_, KeyCode::Enter) | (_, KeyCode::Char(' ')) => {
+                match self.cell() {
+                    Some(c) if self.game.characters.contains_key(&c) => self.select(c),
+                    _ => self.browse()
+                }
+            },
+            (_, KeyCode::Tab) => self.next_token(),
+            (_, KeyCode::Char('m')) => self.enter_move(),
+            (_, KeyCode::Char('a')) => self.enter_menu(Mode::Abilities),
+            (_, KeyCode::Char('i')) => self.enter_menu(Mode::Items),
+            (_, KeyCode::Char('x')) => self.execute_queue(),
+            (_, KeyCode::Char('c')) => {
+                self.log.push(format!("Cleared {} request(s)", self.queue.len()));
+                self.queue.clear();
+            },
+            (_, KeyCode::Char('u')) => {
+                let line = match self.game.undo() {
+                    Some(label) => format!("Undid: {}", label),
+                    None => "Nothing to undo".to_string()
+                };
+                self.log.push(line);
+                self.browse();
+            },
+            (_, KeyCode::Char('[')) => self.cycle_map(-1),
+            (_, KeyCode::Char(']')) => self.cycle_map(1),
+            (_, KeyCode::Char('w')) => self.save(),
+            (_, KeyCode::Char('q')) | (_, KeyCode::Esc) => { self.quit = true; },
+            _ => {}
+        }
+    }
+
+    /******************************************************************************
+     *  tokens - Tokens on the shown map, sorted
+     *---------------------------------------------------------------------------*/
+    pub fn tokens(&self) -> Vec<char>
+    {
+        let mut result: Vec<char> = self.game.characters.iter()
+            .filter(|(_, token)| token.map == self.map).map(|(key, _)| *key).collect();
+        result.sort();
+        return result;
+    }
+
+    /******************************************************************************
+     *  select - Selects a token and moves the cursor (and the map) to it
+     *---------------------------------------------------------------------------*/
+    pub fn select(&mut self, key: char)
+    {
+        let token = match self.game.characters.get(&key) {
+            Some(token) => token,
+            None => return
+        };
+        self.map = token.map.clone();
+        self.cursor = (token.row, token.column);
+        self.selected = Some(key);
+        self.browse();
+    }
+
+    fn browse(&mut self)
+    {
+        self.mode = Mode::Browse;
+        self.options.clear();
+    }
+
+    fn grid(&self) -> &Vec<Vec<char>>
+    {
+        self.game.board(&self.map).unwrap_or(&self.game.grid)
+    }
+
+    fn cell(&self) -> Option<char>
+    {
+        self.game.cell_at(&Cell::new(&self.map, self.cursor.0, self.cursor.1))
+    }
+
+    fn move_cursor(&mut self, rows: i32, cols: i32)
+    {
+        let grid = self.grid();
+        if grid.is_empty() { return; }
+        let row = (self.cursor.0 as i32 + rows).clamp(0, grid.len() as i32 - 1);
+        let col = (self.cursor.1 as i32 + cols).clamp(0, grid[0].len() as i32 - 1);
+        self.cursor = (row as usize, col as usize);
+    }
+
+    fn next_token(&mut self)
+    {
+        let tokens = self.tokens();
+        if tokens.is_empty() { return self.log.push(format!("No tokens on {}", self.map)); }
+        let next = match self.selected.and_then(|key| tokens.iter().position(|tok| *tok == key)) {
+            Some(n) => tokens[(n + 1) % tokens.len()],
+            None => tokens[0]
+        };
+        self.select(next);
+    }
+
+    fn cycle_map(&mut self, step: i32)
+    {
+        let ids = self.game.map_ids();
+        let current = ids.iter().position(|id| *id == self.map).unwrap_or(0) as i32;
+        self.map = ids[(current + step).rem_euclid(ids.len() as i32) as usize].clone();
+        self.cursor = (0, 0);
+        self.browse();
+    }
+
+    /******************************************************************************
+     *  enter_move - Shows where the selected token can move this turn
+     *---------------------------------------------------------------------------*/
+    fn enter_move(&mut self)
+    {
+        let key = match self.selected {
+            Some(key) => key,
+            None => return self.log.push("Select a token first".to_string())
+        };
+        let token = &self.game.characters[&key];
+        if token.map != self.map { self.select(key); }
+        let token = &self.game.characters[&key];
+        self.options = get_action_range(self.grid(), token.row, token.column, token.sheet.speed, false);
+        self.mode = Mode::Move;
+    }
+
+    fn enter_menu(&mut self, mode: Mode)
+    {
+        if self.selected.is_none() { return self.log.push("Select a token first".to_string()); }
+        self.options.clear();
+        self.mode = mode;
+    }
+
+    /******************************************************************************
+     *  abilities - Abilities of the selected token, in menu order
+     *---------------------------------------------------------------------------*/
+    pub fn abilities(&self) -> Vec<String>
+    {
+        let mut result: Vec<String> = match self.selected.and_then(|key| self.game.characters.get(&key)) {
+            Some(token) => token.sheet.abilities.iter().cloned().collect(),
+            None => Vec::new()
+        };
+        result.sort();
+        return result;
+    }
+
+    fn choose_ability(&mut self, digit: char)
+    {
+        let key = self.selected.unwrap();
+        let name = match menu_index(digit).and_then(|n| self.abilities().get(n).cloned()) {
+            Some(name) => name,
+            None => return
+        };
+        let range = match self.game.abilities.get(&name) {
+            Some(ability) => ability.range as i32,
+            None => { self.browse(); return self.log.push(format!("'{}' is not defined in this game", name)); }
+        };
+        if range <= 0 {
+            self.push(Request { caster: key, action_type: 2, subtype_key: Some(name), ..Default::default() });
+            return self.browse();
+        }
+        let token = &self.game.characters[&key];
+        self.options = get_action_range(self.grid(), token.row, token.column, range, true);
+        self.mode = Mode::Target(name);
+    }
+
+    fn choose_item(&mut self, digit: char)
+    {
+        let key = self.selected.unwrap();
+        let count = self.game.characters[&key].sheet.items.len();
+        let index = match menu_index(digit) {
+            Some(n) if n < count => n,
+            _ => return
+        };
+        self.push(Request { caster: key, action_type: 1, subtype_key: Some(index.to_string()), ..Default::default() });
+        self.browse();
+    }
+
+    fn queue_move(&mut self)
+    {
+        let (row, col) = self.cursor;
+        if !self.options.contains(&(row as i32, col as i32)) { return self.log.push("Can't move there".to_string()); }
+        let caster = self.selected.unwrap();
+        self.push(Request { caster, action_type: 0, target_cell: Some((row, col)), ..Default::default() });
+        self.browse();
+    }
+
+    fn queue_target(&mut self, ability: &str)
+    {
+        let (row, col) = self.cursor;
+        if !self.options.contains(&(row as i32, col as i32)) { return self.log.push("Out of range".to_string()); }
+        let caster = self.selected.unwrap();
+        let target = self.cell().filter(|c| self.game.characters.contains_key(c));
+        self.push(Request {
+            caster, action_type: 2,
+            subtype_key: Some(ability.to_string()),
+            target_cell: Some((row, col)),
+            target_tokens: target.map(|c| vec![c]),
+            ..Default::default()
+        });
+        self.browse();
+    }
+
+    fn push(&mut self, request: Request)
+    {
+        self.log.push(format!("Queued: {}", self.describe(&request)));
+        self.queue.push(request);
+    }
+
+    /******************************************************************************
+     *  execute_queue - Logs the queued requests, sorts them by initiative and
+     *                  executes the ones check_request lets through
+     *---------------------------------------------------------------------------*/
+    pub fn execute_queue(&mut self)
+    {
+        if self.queue.is_empty() { return self.log.push("Nothing queued".to_string()); }
+        for request in std::mem::take(&mut self.queue) { self.game.log_request(request.caster, vec![request]); }
+        self.game.sort_requests();
+        for (_, requests) in std::mem::take(&mut self.game.requests) {
+            for request in requests {
+                let line = self.describe(&request);
+                if let Err(reason) = self.game.check_request(&request) {
+                    self.log.push(format!("Refused: {} ({})", line, reason));
+                    continue;
+                }
+                match self.game.apply(Command::Execute(request)) {
+                    Ok(Applied::Executed(record)) => self.log.push(record.to_string()),
+                    Ok(_) => self.log.push(line),
+                    Err(err) => self.log.push(format!("Refused: {} ({})", line, err))
+                }
+            }
+        }
+        if let Some(key) = self.selected { self.select(key); }
+    }
+
+    /******************************************************************************
+     *  describe - One line summary of a request for the log
+     *---------------------------------------------------------------------------*/
+    fn describe(&self, request: &Request) -> String
+    {
+        let key = request.subtype_key.clone().unwrap_or_default();
+        match request.action_type {
+            0 => {
+                let (row, col) = request.target_cell.unwrap_or_default();
+                format!("{} moves to {},{}", request.caster, row, col)
+            },
+            1 => {
+                let item = key.parse::<usize>().ok()
+                    .and_then(|n| self.game.characters.get(&request.caster).and_then(|tok| tok.sheet.items.get(n)));
+                format!("{} uses {}", request.caster, item.map_or(key.clone(), |item| item.name.clone()))
+            },
+            2 => match &request.target_tokens {
+                Some(targets) => format!("{} uses {} on {}", request.caster, key, targets.iter().collect::<String>()),
+                None => format!("{} uses {}", request.caster, key)
+            },
+            3 => format!("{} unequips {}", request.caster, key),
+            _ => format!("{} does nothing", request.caster)
+        }
+    }
+
+    fn save(&mut self)
+    {
+        let path = match &self.path {
+            Some(path) => path.clone(),
+            None => return self.log.push("No file to save to".to_string())
+        };
+        let bytes = if path.ends_with(".json") { serde_json::to_string_pretty(&self.game.to_save()).unwrap().into_bytes() }
+        else { binary::encode_game(&self.game, true) };
+        let line = match fs::write(&path, bytes) {
+            Ok(_) => format!("Saved to {}", path),
+            Err(err) => format!("Couldn't save to {}: {}", path, err)
+        };
+        self.log.push(line);
+    }
+
+    /******************************************************************************
+     *  render - Draws the map, the sheet of the selected token, the queue and the
+     *           log
+     *---------------------------------------------------------------------------*/
+    pub fn render<W: Write>(&self, out: &mut W) -> io::Result<()>
+    {
+        queue!(out, terminal::Clear(terminal::ClearType::All), cursor::MoveTo(0, 0))?;
+        queue!(out, SetAttribute(Attribute::Bold), Print(format!("ByteDungeon - {} - {:?}", self.map, self.mode)),
+               SetAttribute(Attribute::Reset))?;
+
+        let grid = self.grid();
+        for (row, cells) in grid.iter().enumerate() {
+            queue!(out, cursor::MoveTo(0, row as u16 + 1))?;
+            for (col, c) in cells.iter().enumerate() {
+                let (fg, bg) = self.colors(row, col, *c);
+                queue!(out, SetForegroundColor(fg), SetBackgroundColor(bg))?;
+                if (row, col) == self.cursor { queue!(out, SetAttribute(Attribute::Reverse))?; }
+                queue!(out, Print(cell_text(*c)), SetAttribute(Attribute::Reset), ResetColor)?;
+            }
+        }
+
+        let side = grid.first().map_or(0, |row| row.len() as u16 * 2) + 2;
+        let mut lines = self.sheet_lines();
+        lines.push(String::new());
+        lines.push(format!("Queue ({})", self.queue.len()));
+        lines.extend(self.queue.iter().map(|request| format!("  {}", self.describe(request))));
+        for (n, line) in lines.iter().enumerate() { queue!(out, cursor::MoveTo(side, n as u16 + 1), Print(line))?; }
+
+        let top = (grid.len().max(lines.len()) + 2) as u16;
+        let start = self.log.len().saturating_sub(LOG_LINES);
+        for (n, line) in self.log[start..].iter().enumerate() { queue!(out, cursor::MoveTo(0, top + n as u16), Print(line))?; }
+        queue!(out, cursor::MoveTo(0, top + LOG_LINES as u16 + 1), SetForegroundColor(Color::DarkGrey), Print(HELP), ResetColor)?;
+        return Ok(());
+    }
+
+    fn colors(&self, row: usize, col: usize, c: char) -> (Color, Color)
+    {
+        let highlighted = self.options.contains(&(row as i32, col as i32));
+        let bg = if highlighted { Color::DarkBlue } else { Color::Reset };
+        match c {
+            '0' => (Color::DarkGrey, bg),
+            '1' => (Color::Grey, bg),
+            _ if Some(c) == self.selected => (Color::Black, Color::Yellow),
+            _ if highlighted => (Color::White, Color::DarkRed),
+            _ => (Color::White, bg)
+        }
+    }
+
+    /******************************************************************************
+     *  sheet_lines - Character sheet of the selected token, with the menu of the
+     *                current mode
+     *---------------------------------------------------------------------------*/
+    fn sheet_lines(&self) -> Vec<String>
+    {
+        let (key, token) = match self.selected.and_then(|key| self.game.characters.get(&key).map(|tok| (key, tok))) {
+            Some(selected) => selected,
+            None => return vec!["No token selected".to_string()]
+        };
+        let sheet = &token.sheet;
+        let mut lines = vec![
+            format!("{} {}", key, sheet.name),
+            format!("HP {}/{}  Speed {}  Init {}", sheet.hitpoints, sheet.max_hp, sheet.speed, sheet.initiative),
+            format!("On {} at {},{}", token.map, token.row, token.column)
+        ];
+        let mut effects: Vec<&String> = sheet.effects.keys().collect();
+        effects.sort();
+        if !effects.is_empty() { lines.push(format!("Effects: {}", effects.iter().map(|e| e.as_str()).collect::<Vec<_>>().join(", "))); }
+        let mut slots: Vec<(&String, &crate::Item)> = sheet.equipment.iter().collect();
+        slots.sort_by(|a, b| a.0.cmp(b.0));
+        for (slot, item) in slots { lines.push(format!("  {}: {}", slot, item.name)); }
+
+        let numbered = |title: &str, names: Vec<String>, active: bool| {
+            let mut result = vec![format!("{}{}", title, if active { " (pick a number, esc to cancel)" } else { "" })];
+            result.extend(names.iter().enumerate().map(|(n, name)| format!("  {} {}", n + 1, name)));
+            result
+        };
+        lines.push(String::new());
+        lines.extend(numbered("Abilities", self.abilities(), self.mode == Mode::Abilities));
+        let items = sheet.items.iter().map(|item| format!("{} x{}", item.name, item.uses)).collect();
+        lines.extend(numbered("Items", items, self.mode == Mode::Items));
+        return lines;
+    }
+}
+
+/******************************************************************************
+ *  menu_index - Index picked by a digit key, menus are numbered from 1
+ *---------------------------------------------------------------------------*/
+fn menu_index(digit: char) -> Option<usize>
+{
+    digit.to_digit(10).filter(|n| *n > 0).map(|n| n as usize - 1)
+}
+
+/******************************************************************************
+ *  cell_text - Two column text of a cell
+ *---------------------------------------------------------------------------*/
+fn cell_text(c: char) -> String
+{
+    match c {
+        '0' => "· ".to_string(),
+        '1' => "██".to_string(),
+        _ if c.is_ascii() => format!("{} ", c),
+        _ => c.to_string()
+    }
+}
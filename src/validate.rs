@@ -0,0 +1,280 @@
+/*/////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+ *
+ *      Game set linter for ByteDungeon, finds broken content before it panics mid-game
+ *
+ *      Implementation and Assumptions
+ *          - Effects and abilities are referenced by key from items, abilities and sheets, every key must exist
+ *          - Items, abilities and effects are stored by name, the key must match the name of what it stores
+ *          - Every token must stand on its own char on an existing map, and every token char on a map needs a token
+ *          - Effects target health, speed, initiative or a stat the characters have, in any case
+ *          - Errors are content that makes the game panic or misbehave, warnings are suspicious but playable
+ *          - Paths use the same notation as checksum.rs (ie: items.Short sword.effects, maps.cellar[2][3])
+ *
+ */////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+use serde::{Serialize, Deserialize};
+
+use std::fmt;
+use std::collections::HashMap;
+
+use crate::{GameSession, Character, Item};
+use crate::maps::{Cell, MAIN_MAP};
+
+/***********************************************
+ * Severity - How bad an issue is
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Severity
+{
+    Error,
+    Warning
+}
+
+/***********************************************
+ * IssueKind - What is wrong
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum IssueKind
+{
+    UnknownEffect(String),
+    UnknownAbility(String),
+    KeyMismatch { key: String, name: String },  // Stored under KEY but named NAME
+    DuplicateName(String),
+    InvalidToken(char),                         // '0' and '1' are reserved for floor and wall
+    UnknownMap(String),
+    TokenMismatch { found: Option<char> },      // The token's cell holds another char (None: outside the map)
+    StrayToken(char),                           // Token char on the grid without a token standing there
+    RaggedRow { expected: usize, found: usize },
+    CellOffBoard,                               // Link or object on a cell outside its map
+    NegativeRange(i16),
+    InvertedRoll { low: i32, high: i32 },
+    InvalidMaxHp(i32),
+    UnknownStat(String)                         // Effect target that isn't health, speed, initiative or a stat
+}
+
+/***********************************************
+ * Issue - Problem found at a path of the game
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Issue
+{
+    pub severity: Severity,
+    pub path: String,
+    pub kind: IssueKind
+}
+
+impl IssueKind
+{
+    pub fn severity(&self) -> Severity
+    {
+        match self {
+            IssueKind::DuplicateName(_) | IssueKind::InvertedRoll { .. } => Severity::Warning,
+            _ => Severity::Error
+        }
+    }
+}
+
+impl fmt::Display for Issue
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} at {}: ", self.severity, self.path)?;
+        match &self.kind {
+            IssueKind::UnknownEffect(key) => write!(f, "no effect named '{}'", key),
+            IssueKind::UnknownAbility(key) => write!(f, "no ability named '{}'", key),
+            IssueKind::KeyMismatch { key, name } => write!(f, "stored as '{}' but named '{}'", key, name),
+            IssueKind::DuplicateName(name) => write!(f, "'{}' is used by more than one character", name),
+            IssueKind::InvalidToken(c) => write!(f, "'{}' is reserved and can't be a token", c),
+            IssueKind::UnknownMap(map) => write!(f, "no map named '{}'", map),
+            IssueKind::TokenMismatch { found: Some(c) } => write!(f, "the token's cell holds '{}'", c),
+            IssueKind::TokenMismatch { found: None } => write!(f, "the token is outside its map"),
+            IssueKind::StrayToken(c) => write!(f, "'{}' has no token standing here", c),
+            IssueKind::RaggedRow { expected, found } => write!(f, "row has {} cells, expected {}", found, expected),
+            IssueKind::CellOffBoard => write!(f, "cell is outside its map"),
+            IssueKind::NegativeRange(range) => write!(f, "negative range {}", range),
+            IssueKind::InvertedRoll { low, high } => write!(f, "roll low {} is above high {}", low, high),
+            IssueKind::InvalidMaxHp(hp) => write!(f, "max hp {} is below 1", hp),
+            IssueKind::UnknownStat(stat) => write!(f, "no stat named '{}'", stat)
+        }
+    }
+}
+
+/***********************************************
+ * Linter - Walks a session collecting issues
+ **********************************************/
+struct Linter<'a>
+{
+    game: &'a GameSession,
+    issues: Vec<Issue>
+}
+
+/******************************************************************************
+ *  validate_game - Returns every issue found in the session, errors first
+ *---------------------------------------------------------------------------*/
+pub fn validate_game(game: &GameSession) -> Vec<Issue>
+{
+    let mut linter = Linter { game, issues: Vec::new() };
+    linter.definitions();
+    linter.characters();
+    linter.boards();
+    let mut issues = linter.issues;
+    issues.sort_by(|a, b| (a.severity != Severity::Error, &a.path).cmp(&(b.severity != Severity::Error, &b.path)));
+    return issues;
+}
+
+impl<'a> Linter<'a>
+{
+    fn report(&mut self, path: String, kind: IssueKind)
+    {
+        self.issues.push(Issue { severity: kind.severity(), path, kind });
+    }
+
+    /******************************************************************************
+     *  definitions - Items, abilities and effects of the game set
+     *---------------------------------------------------------------------------*/
+    fn definitions(&mut self)
+    {
+        let game = self.game;
+        for (key, item) in &game.items {
+            self.item(&format!("items.{}", key), item);
+            if *key != item.name {
+                self.report(format!("items.{}", key), IssueKind::KeyMismatch { key: key.clone(), name: item.name.clone() });
+            }
+        }
+        for (key, ability) in &game.abilities {
+            let path = format!("abilities.{}", key);
+            if *key != ability.name { self.report(path.clone(), IssueKind::KeyMismatch { key: key.clone(), name: ability.name.clone() }); }
+            if ability.range < 0 { self.report(format!("{}.range", path), IssueKind::NegativeRange(ability.range)); }
+            let [low, high] = ability.casting_roll;
+            if low > high { self.report(format!("{}.casting_roll", path), IssueKind::InvertedRoll { low, high }); }
+            for effect in ability.target_effects.iter().chain(ability.caster_effects.iter()) {
+                if !game.effects.contains_key(effect) { self.report(format!("{}.effects", path), IssueKind::UnknownEffect(effect.clone())); }
+            }
+        }
+        for (key, effect) in &game.effects {
+            let path = format!("effects.{}", key);
+            if *key != effect.name { self.report(path.clone(), IssueKind::KeyMismatch { key: key.clone(), name: effect.name.clone() }); }
+            let [low, high] = effect.modifier;
+            if low > high { self.report(format!("{}.modifier", path), IssueKind::InvertedRoll { low, high }); }
+            let mut sheets = game.characters.values().map(|token| &token.sheet).chain(game.sheets.values());
+            if !base_stat(&effect.target_stat) && !sheets.any(|sheet| targets_stat(sheet, &effect.target_stat)) {
+                self.report(format!("{}.target_stat", path), IssueKind::UnknownStat(effect.target_stat.clone()));
+            }
+        }
+    }
+
+    /******************************************************************************
+     *  characters - Sheets of the tokens in play and of the unassigned sheets
+     *---------------------------------------------------------------------------*/
+    fn characters(&mut self)
+    {
+        let game = self.game;
+        let mut names: HashMap<&str, Vec<String>> = HashMap::new();
+        for (key, token) in &game.characters {
+            let path = format!("characters.{}", key);
+            self.sheet(&format!("{}.sheet", path), &token.sheet);
+            names.entry(&token.sheet.name).or_default().push(format!("{}.sheet", path));
+            if *key == '0' || *key == '1' { self.report(path.clone(), IssueKind::InvalidToken(*key)); }
+
+            match game.board(&token.map) {
+                None => self.report(format!("{}.map", path), IssueKind::UnknownMap(token.map.clone())),
+                Some(_) => {
+                    let found = game.cell_at(&Cell::new(&token.map, token.row, token.column));
+                    if found != Some(*key) { self.report(path, IssueKind::TokenMismatch { found }); }
+                }
+            }
+        }
+        for (key, sheet) in &game.sheets {
+            let path = format!("sheets.{}", key);
+            self.sheet(&path, sheet);
+            names.entry(&sheet.name).or_default().push(path);
+        }
+        for (name, mut paths) in names {
+            if paths.len() < 2 { continue; }
+            paths.sort();
+            for path in paths { self.report(format!("{}.name", path), IssueKind::DuplicateName(name.to_string())); }
+        }
+    }
+
+    /******************************************************************************
+     *  boards - Grids of every map, links and objects
+     *---------------------------------------------------------------------------*/
+    fn boards(&mut self)
+    {
+        let game = self.game;
+        for id in game.map_ids() {
+            let grid = game.board(&id).unwrap();
+            let prefix = if id == MAIN_MAP { "grid".to_string() } else { format!("maps.{}", id) };
+            let width = grid.first().map_or(0, |row| row.len());
+            for (row, cells) in grid.iter().enumerate() {
+                if cells.len() != width {
+                    self.report(format!("{}[{}]", prefix, row), IssueKind::RaggedRow { expected: width, found: cells.len() });
+                }
+                for (col, c) in cells.iter().enumerate() {
+                    if *c == '0' || *c == '1' { continue; }
+                    let placed = game.characters.get(c).is_some_and(|tok| tok.map == id && tok.row == row && tok.column == col);
+                    if !placed { self.report(format!("{}[{}][{}]", prefix, row, col), IssueKind::StrayToken(*c)); }
+                }
+            }
+        }
+        for (n, link) in game.links.iter().enumerate() {
+            for cell in [&link.from, &link.to] {
+                if !game.in_bounds(cell) { self.report(format!("links[{}]", n), IssueKind::CellOffBoard); }
+            }
+        }
+        for (n, object) in game.objects.iter().enumerate() {
+            if !game.in_bounds(&object.cell) { self.report(format!("objects[{}]", n), IssueKind::CellOffBoard); }
+        }
+    }
+
+    /******************************************************************************
+     *  sheet - One character sheet, with its items and equipment
+     *---------------------------------------------------------------------------*/
+    fn sheet(&mut self, path: &str, sheet: &Character)
+    {
+        if sheet.max_hp < 1 { self.report(format!("{}.max_hp", path), IssueKind::InvalidMaxHp(sheet.max_hp)); }
+        for ability in &sheet.abilities {
+            if !self.game.abilities.contains_key(ability) {
+                self.report(format!("{}.abilities", path), IssueKind::UnknownAbility(ability.clone()));
+            }
+        }
+        for (key, effect) in &sheet.effects {
+            if !targets_stat(sheet, &effect.target_stat) {
+                self.report(format!("{}.effects.{}.target_stat", path, key), IssueKind::UnknownStat(effect.target_stat.clone()));
+            }
+        }
+        for (n, item) in sheet.items.iter().enumerate() { self.item(&format!("{}.items[{}]", path, n), item); }
+        for (slot, item) in &sheet.equipment { self.item(&format!("{}.equipment.{}", path, slot), item); }
+    }
+
+    /******************************************************************************
+     *  item - Effects and abilities granted by an item
+     *---------------------------------------------------------------------------*/
+    fn item(&mut self, path: &str, item: &Item)
+    {
+        for effect in &item.effects {
+            if !self.game.effects.contains_key(effect) { self.report(format!("{}.effects", path), IssueKind::UnknownEffect(effect.clone())); }
+        }
+        for ability in &item.abilities {
+            if !self.game.abilities.contains_key(ability) {
+                self.report(format!("{}.abilities", path), IssueKind::UnknownAbility(ability.clone()));
+            }
+        }
+    }
+}
+
+/******************************************************************************
+ *  base_stat - Tells if STAT is one every sheet has (health, speed, initiative)
+ *---------------------------------------------------------------------------*/
+fn base_stat(stat: &str) -> bool
+{
+    return ["health", "speed", "initiative"].contains(&stat.to_lowercase().as_str());
+}
+
+/******************************************************************************
+ *  targets_stat - Tells if an effect on STAT changes something on SHEET, the
+ *                 same case insensitive lookup apply_effect does
+ *---------------------------------------------------------------------------*/
+fn targets_stat(sheet: &Character, stat: &str) -> bool
+{
+    return base_stat(stat) || sheet.stats.keys().any(|key| key.to_lowercase() == stat.to_lowercase());
+}
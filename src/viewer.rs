@@ -0,0 +1,177 @@
+/*/////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+ *
+ *      Redacted views of a session for players and spectators, what the table may see without the DM's notes
+ *
+ *      Implementation and Assumptions
+ *          - The host and co-DMs see the whole game, a view for any other role is a projection:
+ *              it starts from what players receive (see layers.rs), without hidden tokens, GM notes or traps
+ *              the combat log is removed
+ *              monsters (tokens no player controls) show their health band instead of hitpoints
+ *              cells out of sight of the party are fog (FOG), tokens, objects and links in the fog are removed
+ *          - The party sees the cells within SIGHT (grid distance) of its tokens that a straight line reaches
+ *            without crossing a wall, walls themselves are seen, a map without party tokens is all fog
+ *          - A projection is marked with a "projection" field, loading it as a saved game is refused (see
+ *            migrate.rs) so a view can never replace the real session
+ *
+ */////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+
+use std::fmt;
+use std::collections::{HashMap, HashSet};
+
+use crate::GameSession;
+use crate::permissions::Role;
+
+pub const FOG: char = '?';
+pub const SIGHT: i32 = 8;
+pub const PROJECTION: &str = "projection";        // Field marking a document as a view, not a save
+pub const BAND_MAX_HP: i32 = 4;                     // Max hitpoints of a monster cut down to its band
+
+/***********************************************
+ * HealthBand - Rough health shown for monsters
+ **********************************************/
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthBand
+{
+    Healthy,                                    // Untouched
+    Wounded,                                    // Above half
+    Bloodied,                                   // Half or less
+    Critical,                                   // A quarter or less
+    Down                                        // No hitpoints left
+}
+
+impl fmt::Display for HealthBand
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HealthBand::Healthy => write!(f, "healthy"),
+            HealthBand::Wounded => write!(f, "wounded"),
+            HealthBand::Bloodied => write!(f, "bloodied"),
+            HealthBand::Critical => write!(f, "critical"),
+            HealthBand::Down => write!(f, "down")
+        }
+    }
+}
+
+impl HealthBand
+{
+    /******************************************************************************
+     *  of - Band of a character with HITPOINTS out of MAX_HP
+     *---------------------------------------------------------------------------*/
+    pub fn of(hitpoints: i32, max_hp: i32) -> HealthBand
+    {
+        if hitpoints <= 0 { return HealthBand::Down; }
+        if max_hp <= 0 || hitpoints >= max_hp { return HealthBand::Healthy; }
+        if hitpoints * 4 <= max_hp { return HealthBand::Critical; }
+        if hitpoints * 2 <= max_hp { return HealthBand::Bloodied; }
+        return HealthBand::Wounded;
+    }
+
+    /******************************************************************************
+     *  hitpoints - Hitpoints out of BAND_MAX_HP that give back the same band
+     *---------------------------------------------------------------------------*/
+    pub fn hitpoints(self) -> i32
+    {
+        match self {
+            HealthBand::Healthy => BAND_MAX_HP,
+            HealthBand::Wounded => 3,
+            HealthBand::Bloodied => 2,
+            HealthBand::Critical => 1,
+            HealthBand::Down => 0
+        }
+    }
+}
+
+impl GameSession
+{
+    /******************************************************************************
+     *  export_for_viewer - What a user with ROLE may see of the session, see header
+     *
+     *  RETURN: The full save for the host and co-DMs, a marked projection otherwise
+     *---------------------------------------------------------------------------*/
+    pub fn export_for_viewer(&self, role: Role) -> Value
+    {
+        if role.is_dm() { return self.to_save(); }
+        let mut view = self.player_copy();
+        view.combat_log.clear();
+
+        let seen = self.party_sight();
+        let in_sight = |map: &str, row: usize, col: usize| seen.get(map).is_some_and(|cells| cells.contains(&(row, col)));
+        for id in self.map_ids() {
+            let grid = view.board_mut(&id).unwrap();
+            for (row, cells) in grid.iter_mut().enumerate() {
+                for (col, c) in cells.iter_mut().enumerate() {
+                    if !in_sight(&id, row, col) { *c = FOG; }
+                }
+            }
+        }
+        view.characters.retain(|_, token| in_sight(&token.map, token.row, token.column));
+        view.objects.retain(|object| in_sight(&object.cell.map, object.cell.row, object.cell.column));
+        view.links.retain(|link| in_sight(&link.from.map, link.from.row, link.from.column) && in_sight(&link.to.map, link.to.row, link.to.column));
+
+        let mut value = view.to_save();
+        for (key, token) in view.characters.iter() {
+            if !view.permissions.controllers_of(*key).is_empty() { continue; }
+            let sheet = value["characters"][key.to_string()]["sheet"].as_object_mut().unwrap();
+            sheet.remove("hitpoints");
+            sheet.remove("max_hp");
+            sheet.insert("health".to_string(), json!(HealthBand::of(token.sheet.hitpoints, token.sheet.max_hp)));
+        }
+        value[PROJECTION] = json!({ "role": role });
+        return value;
+    }
+
+    /******************************************************************************
+     *  party_sight - Cells seen by the tokens players control, by map
+     *---------------------------------------------------------------------------*/
+    pub fn party_sight(&self) -> HashMap<String, HashSet<(usize, usize)>>
+    {
+        let mut result: HashMap<String, HashSet<(usize, usize)>> = HashMap::new();
+        for (key, token) in self.characters.iter() {
+            if self.permissions.controllers_of(*key).is_empty() { continue; }
+            if let Some(grid) = self.board(&token.map) {
+                result.entry(token.map.clone()).or_default().extend(sight(grid, token.row, token.column, SIGHT));
+            }
+        }
+        return result;
+    }
+}
+
+/******************************************************************************
+ *  sight - Cells within RANGE of a cell that a straight line reaches without
+ *          crossing a wall (Bresenham)
+ *---------------------------------------------------------------------------*/
+pub fn sight(grid: &[Vec<char>], src_row: usize, src_col: usize, range: i32) -> HashSet<(usize, usize)>
+{
+    let mut result = HashSet::new();
+    if grid.is_empty() { return result; }
+    let (rows, cols) = (grid.len() as i32, grid[0].len() as i32);
+    let (src_row, src_col) = (src_row as i32, src_col as i32);
+    for row in (src_row - range).max(0)..(src_row + range + 1).min(rows) {
+        for col in (src_col - range).max(0)..(src_col + range + 1).min(cols) {
+            if (row - src_row).abs() + (col - src_col).abs() > range { continue; }
+            if clear_line(grid, (src_row, src_col), (row, col)) { result.insert((row as usize, col as usize)); }
+        }
+    }
+    return result;
+}
+
+/******************************************************************************
+ *  clear_line - True if no wall stands strictly between FROM and TO
+ *---------------------------------------------------------------------------*/
+fn clear_line(grid: &[Vec<char>], from: (i32, i32), to: (i32, i32)) -> bool
+{
+    let (d_row, d_col) = ((to.0 - from.0).abs(), -(to.1 - from.1).abs());
+    let (step_row, step_col) = ((to.0 - from.0).signum(), (to.1 - from.1).signum());
+    let (mut row, mut col, mut err) = (from.0, from.1, d_row + d_col);
+    while (row, col) != to {
+        if (row, col) != from && grid[row as usize][col as usize] == '1' { return false; }
+        let double = 2 * err;
+        if double >= d_col { err += d_col; row += step_row; }
+        if double <= d_row { err += d_row; col += step_col; }
+    }
+    return true;
+}
@@ -0,0 +1,101 @@
+//! Native tests for the monster AI proposing requests for the tokens the DM plays.
+
+mod common;
+
+use byte_dungeon::GameSession;
+use byte_dungeon::ai::Behaviour;
+use byte_dungeon::history::Command;
+use byte_dungeon::migrate::load_save;
+use byte_dungeon::permissions::Role;
+use common::{elf_at, load, place};
+use serde_json::{json, Value};
+
+// Tutorial with the elf at 2,16 near the dragon, the skeleton out of the fight, CHANGE edits the save first
+fn encounter(change: impl Fn(&mut Value)) -> GameSession {
+    let mut save = elf_at(2, 16);
+    save["characters"]["💀"]["sheet"]["hitpoints"] = json!(0);
+    save["effects"]["Mend"] = json!({ "name": "Mend", "duration": 0, "target_stat": "health", "modifier": [5, 5], "temporary": false });
+    save["abilities"]["Mend"] = json!({ "name": "Mend", "range": 1, "action_points": 1, "casting_roll": [1, 20], "stat_modifier": null,
+                                        "target_effects": ["Mend"] });
+    save["abilities"]["Firebolt"] = json!({ "name": "Firebolt", "range": 4, "action_points": 1, "casting_roll": [1, 20], "stat_modifier": null,
+                                            "target_effects": ["Slash damage"] });
+    change(&mut save);
+    load(save)
+}
+
+// Steps from the elf at 2,16 to the cell a move goes to
+fn from_the_elf(request: &Value) -> u64 {
+    let (row, col) = (request["target_cell"][0].as_i64().unwrap(), request["target_cell"][1].as_i64().unwrap());
+    ((row - 2).abs() + (col - 16).abs()) as u64
+}
+
+fn behave(game: &mut GameSession, behaviour: Behaviour) -> Vec<Value> {
+    game.apply(Command::SetBehaviour { token: '🐉', behaviour: Some(behaviour) }).unwrap();
+    game.propose_turn('🐉', &['🧝']).iter().map(|request| json!(request)).collect()
+}
+
+#[test]
+fn tokens_are_aggressive_by_default() {
+    let game = encounter(|_| {});
+    let plan = game.auto_play(&['🧝']);
+    assert_eq!(json!(plan), json!([
+        { "caster": "🐉", "action_type": 0, "subtype_key": null, "target_cell": [2, 17], "target_tokens": null },
+        { "caster": "🐉", "action_type": 2, "subtype_key": "Slash", "target_cell": null, "target_tokens": ["🧝"] }
+    ]), "walk up and hit");
+    assert_eq!(game.grid[2][18], '🐉', "proposing never changes the session");
+}
+
+#[test]
+fn defenders_only_step_out_for_enemies_they_can_reach() {
+    let mut near = encounter(|_| {});
+    assert_eq!(behave(&mut near, Behaviour::Defender).len(), 2, "a defender steps out for an enemy it can reach this turn");
+    let mut far = encounter(|save| place(save, "🧝", 2, 8));
+    assert!(behave(&mut far, Behaviour::Defender).is_empty(), "and holds its ground otherwise");
+}
+
+#[test]
+fn cowards_run_when_badly_hurt() {
+    let mut hurt = encounter(|save| save["characters"]["🐉"]["sheet"]["hitpoints"] = json!(3));
+    let plan = behave(&mut hurt, Behaviour::Coward);
+    assert_eq!(plan.len(), 1);
+    assert_eq!(plan[0]["action_type"], 0);
+    assert!(from_the_elf(&plan[0]) > 2, "a coward under a quarter of its hitpoints runs: {}", plan[0]);
+}
+
+#[test]
+fn kiters_back_off_after_their_shot() {
+    let mut ranged = encounter(|save| save["characters"]["🐉"]["sheet"]["abilities"] = json!(["Firebolt"]));
+    let plan = behave(&mut ranged, Behaviour::Kiter);
+    assert_eq!(plan[0]["subtype_key"], "Firebolt");
+    assert_eq!(plan[1]["action_type"], 0);
+    assert!(from_the_elf(&plan[1]) > 2 && from_the_elf(&plan[1]) <= 4, "{}", plan[1]);
+}
+
+#[test]
+fn support_tokens_heal_hurt_allies_first() {
+    let mut healer = encounter(|save| {
+        save["characters"]["🐉"]["sheet"]["abilities"] = json!(["Mend", "Slash"]);
+        place(save, "💀", 2, 19);
+        save["characters"]["💀"]["sheet"]["hitpoints"] = json!(8);
+    });
+    let plan = behave(&mut healer, Behaviour::Support);
+    assert_eq!((plan[0]["subtype_key"].clone(), plan[0]["target_tokens"].clone()), (json!("Mend"), json!(["💀"])));
+}
+
+#[test]
+fn behaviours_are_gm_information_saved_and_undone_like_edits() {
+    let mut game = encounter(|_| {});
+    behave(&mut game, Behaviour::Defender);
+    game.apply(Command::SetRole { user: "ana".into(), role: Some(Role::Player) }).unwrap();
+    game.apply(Command::Grant { user: "ana".into(), token: '🧝' }).unwrap();
+    assert_eq!(game.party(), vec!['🧝']);
+    assert_eq!(game.auto_play(&game.party()).len(), 2);
+    assert_eq!(game.behaviours.get(&'🐉'), Some(&Behaviour::Defender));
+    assert!(game.player_copy().behaviours.is_empty(), "behaviours are GM information");
+    let reloaded = load_save(game.to_save()).unwrap().0;
+    assert_eq!(reloaded.behaviours, game.behaviours);
+    game.undo();
+    game.undo();
+    game.undo();
+    assert_eq!(game.behaviours.get(&'🐉'), None);
+}
@@ -0,0 +1,35 @@
+//! Native tests for text map import and export.
+
+mod common;
+
+use byte_dungeon::ascii::{AsciiError, Legend};
+use byte_dungeon::maps::MAIN_MAP;
+use common::tutorial;
+
+#[test]
+fn export_then_import_keeps_the_board() {
+    let mut game = tutorial();
+    let legend = Legend::default();
+    let text = game.export_ascii(MAIN_MAP, &legend).unwrap();
+    assert!(text.starts_with("🧝..#"));
+
+    let grid = game.grid.clone();
+    game.import_ascii(MAIN_MAP, &text, &legend).unwrap();
+    assert_eq!(game.grid, grid);
+    assert_eq!(game.export_ascii(MAIN_MAP, &legend).unwrap(), text);
+}
+
+#[test]
+fn import_reports_every_problem() {
+    let mut game = tutorial();
+    let errors = game.import_ascii("crypt", "#####\n#.?.#\n####\n", &Legend::default()).unwrap_err();
+    assert_eq!(errors, vec![
+        AsciiError::UnknownGlyph { line: 2, column: 3, glyph: '?' },
+        AsciiError::RaggedRow { line: 3, expected: 5, found: 4 }
+    ]);
+    assert!(game.board("crypt").is_none());
+
+    game.import_ascii("crypt", "#####\n#+k^#\n#####\n", &Legend::default()).unwrap();
+    assert_eq!(game.board("crypt").unwrap()[1], vec!['1', '0', '0', '0', '1']);
+    assert_eq!(game.export_ascii("crypt", &Legend::default()).unwrap(), "#####\n#+k^#\n#####\n");
+}
@@ -0,0 +1,36 @@
+//! Native tests for binary saves.
+
+mod common;
+
+use byte_dungeon::binary::{decode_document, decode_game, encode_game, BinaryError};
+use common::tutorial;
+
+#[test]
+fn binary_saves_round_trip_to_the_json_form() {
+    let mut game = tutorial();
+    game.make_request(0, "", '🧝', 1, 0);
+    game.roll(1, 20);
+    let json = serde_json::to_string(&game.to_save()).unwrap();
+
+    for compress in [false, true] {
+        let bytes = encode_game(&game, compress);
+        let limit = if compress { json.len() / 3 } else { json.len() };
+        assert!(bytes.len() < limit, "{} bytes vs {}", bytes.len(), json.len());
+        assert_eq!(decode_document(&bytes).unwrap(), game.to_save());
+        let (loaded, applied) = decode_game(&bytes).unwrap();
+        assert!(applied.is_empty());
+        assert_eq!(loaded.state_hash(), game.state_hash());
+        assert_eq!(loaded.events.len(), 2);
+    }
+}
+
+#[test]
+fn truncated_or_unknown_saves_are_refused() {
+    let mut bytes = encode_game(&tutorial(), true);
+    assert_eq!(decode_game(&bytes[..3]).unwrap_err(), BinaryError::NotASave);
+    bytes[5] = 0x80;
+    assert_eq!(decode_game(&bytes).unwrap_err(), BinaryError::UnknownFlags(0x80));
+    bytes[5] = 1;
+    bytes.truncate(bytes.len() / 2);
+    assert_eq!(decode_game(&bytes).unwrap_err(), BinaryError::Decompress);
+}
@@ -0,0 +1,105 @@
+//! Native tests for chat commands, dice expressions and whispers routed by the server.
+
+mod common;
+
+use byte_dungeon::chat::{self, ChatError, ChatKind, Term, MAX_LINE_DICE, MAX_TERMS};
+use byte_dungeon::protocol::{ClientMessage, Envelope, ServerMessage};
+use byte_dungeon::server::Server;
+use common::{load, tutorial_save};
+
+#[test]
+fn rolls_add_up_their_dice_and_numbers() {
+    let mut game = load(tutorial_save());
+    let roll = game.chat(None, "/roll 2d6 + 3").unwrap();
+    assert_eq!(roll.kind, ChatKind::Roll);
+    assert_eq!(roll.rolls[0].expression, "2d6+3");
+    assert_eq!(roll.rolls[0].terms[0].values.len(), 2);
+    let dice: i32 = roll.rolls[0].terms[0].values.iter().sum();
+    assert_eq!(roll.rolls[0].total, dice + 3);
+    assert!(roll.render("Ana").starts_with("Ana rolls 2d6+3: ["));
+}
+
+#[test]
+fn stats_are_taken_from_the_speakers_token() {
+    let mut game = load(tutorial_save());
+    let seq = game.events.len();
+    let check = game.chat(Some('🧝'), "/r 1d20+dexterity-1").unwrap();
+    assert_eq!(check.rolls[0].total, check.rolls[0].terms[0].values[0] + 5 - 1);
+    assert_eq!(game.events.len(), seq + 1, "every die is logged");
+}
+
+#[test]
+fn lines_with_unknown_stats_roll_nothing() {
+    let mut game = load(tutorial_save());
+    let seq = game.events.len();
+    assert_eq!(game.chat(None, "/r 1d20+Dexterity"), Err(ChatError::NoToken("Dexterity".into())));
+    assert_eq!(game.chat(Some('🧝'), "/r 1d20+Luck"), Err(ChatError::UnknownStat("Luck".into())));
+    assert_eq!(game.events.len(), seq);
+}
+
+#[test]
+fn inline_rolls_are_rendered_in_the_text() {
+    let mut game = load(tutorial_save());
+    let emote = game.chat(None, "/me swings for [[1d8]] and [[d4-1]] [[").unwrap();
+    assert_eq!(emote.rolls.len(), 2);
+    let text = emote.render("Ana");
+    assert!(text.starts_with("Ana swings for [1d8: [") && text.ends_with("] [["), "{}", text);
+    let say = chat::parse("hello [[2d4]]").unwrap();
+    assert_eq!((say.kind, say.expressions[0].terms[0].1.clone()), (ChatKind::Say, Term::Dice { count: 2, sides: 4 }));
+}
+
+#[test]
+fn malformed_lines_are_refused() {
+    assert_eq!(chat::parse("   "), Err(ChatError::Empty));
+    assert_eq!(chat::parse("/dance"), Err(ChatError::UnknownCommand("/dance".into())));
+    assert_eq!(chat::parse("/w bob"), Err(ChatError::MissingArgument("/w".into())));
+    assert_eq!(chat::parse("/r 1000d6"), Err(ChatError::TooManyDice("1000d6".into())));
+    assert_eq!(chat::parse("/r 2d6++3"), Err(ChatError::BadDice("2d6++3".into())));
+}
+
+#[test]
+fn totals_that_could_overflow_are_refused_before_rolling() {
+    let mut game = load(tutorial_save());
+    let seq = game.events.len();
+    let big = i32::MAX.to_string();
+    assert_eq!(game.chat(None, &format!("/r {}+{}", big, big)), Err(ChatError::Overflow(format!("{}+{}", big, big))));
+    assert_eq!(game.chat(None, &format!("/r 1d20+{}", big)), Err(ChatError::Overflow(format!("1d20+{}", big))));
+    assert_eq!(game.events.len(), seq);
+    assert_eq!(game.chat(None, &format!("/r {}-1", big)).unwrap().rolls[0].total, i32::MAX - 1);
+}
+
+#[test]
+fn a_line_rolls_a_bounded_number_of_dice() {
+    let mut game = load(tutorial_save());
+    let seq = game.events.len();
+    assert_eq!(game.chat(None, &"[[100d6]]".repeat(3)), Err(ChatError::LineDice(300)));
+    let terms = vec!["1"; MAX_TERMS + 1].join("+");
+    assert_eq!(game.chat(None, &format!("/r {}", terms)), Err(ChatError::TooManyTerms(MAX_TERMS + 1)));
+    assert_eq!(game.chat(None, &"[[d4]] ".repeat(2000)), Err(ChatError::TooManyTerms(2000)));
+    assert_eq!(game.events.len(), seq, "refused lines roll nothing");
+
+    game.chat(None, &"[[100d6]]".repeat(2)).unwrap();
+    assert_eq!(game.events.len(), seq + MAX_LINE_DICE as usize);
+}
+
+#[test]
+fn the_server_sends_whispers_to_both_ends_only() {
+    let mut server = Server::default();
+    let mut send = |client, message| server.handle(client, Envelope::new(message, None));
+    send(1, ClientMessage::StartHosting { room: "den".into(), user: "dm".into(), name: "DM".into(), set: String::new(), game: Some(tutorial_save()) });
+    send(2, ClientMessage::FindSession { room: "den".into(), user: "ana".into(), name: "Ana".into(), secret: None });
+    send(3, ClientMessage::FindSession { room: "den".into(), user: "bob".into(), name: "Bob".into(), secret: None });
+    let chats = |sent: Vec<(u64, Envelope<ServerMessage>)>| -> Vec<(u64, String)> {
+        sent.into_iter().filter_map(|(client, envelope)| match envelope.message {
+            ServerMessage::Chat { from, message } => Some((client, message.render(&from))),
+            _ => None
+        }).collect()
+    };
+    let whisper = chats(send(2, ClientMessage::Chat { text: "/w Bob meet me at the door".into() }));
+    assert_eq!(whisper, vec![(2, "Ana whispers to Bob: meet me at the door".to_string()),
+                             (3, "Ana whispers to Bob: meet me at the door".to_string())]);
+    let said = chats(send(3, ClientMessage::Chat { text: "/me nods".into() }));
+    assert_eq!(said.iter().map(|(client, _)| *client).collect::<Vec<_>>(), vec![1, 2, 3]);
+    let refused = send(2, ClientMessage::Chat { text: "/w carl hi".into() });
+    assert!(matches!(&refused.last().unwrap().1.message, ServerMessage::TransactionFailed { .. }), "whispers need a known user");
+}
@@ -0,0 +1,40 @@
+//! Native tests for state hashing and desync reports.
+
+mod common;
+use byte_dungeon::GameSession;
+use byte_dungeon::checksum::compare_states;
+use common::tutorial_save;
+
+fn with_traits(traits: Vec<String>) -> GameSession {
+    let mut value = tutorial_save();
+    value["characters"]["🐉"]["sheet"]["traits"] = serde_json::json!(traits);
+    serde_json::from_value(value).unwrap()
+}
+
+#[test]
+fn hash_ignores_ordering_and_reports_differences() {
+    let traits: Vec<String> = (0..32).map(|n| format!("trait {}", n)).collect();
+    let game = with_traits(traits.clone());
+    let mut other = with_traits(traits.into_iter().rev().collect());
+    assert_eq!(game.state_hash(), other.state_hash());
+    assert_eq!(game.state_hash().len(), 16);
+
+    other.make_request(0, "", '🧝', 0, 1);
+    assert_ne!(game.state_hash(), other.state_hash());
+
+    let diffs = compare_states(&game, &other, 2);
+    let paths: Vec<&str> = diffs.iter().map(|diff| diff.path.as_str()).collect();
+    assert_eq!(paths, vec!["characters.🧝.column", "grid[0][0]"]);
+    assert_eq!(diffs[1].left, Some(serde_json::json!("🧝")));
+}
+
+#[test]
+fn generators_that_would_roll_differently_change_the_hash() {
+    let game = with_traits(Vec::new());
+    let mut other = with_traits(Vec::new());
+    other.log_request('🧝', Vec::new());
+    assert_eq!(game.state_hash(), other.state_hash(), "requests aren't state");
+    other.roll(1, 6);
+    assert_ne!(game.state_hash(), other.state_hash(), "the next roll would differ");
+    assert_eq!(compare_states(&game, &other, 10)[0].path, "rng.state");
+}
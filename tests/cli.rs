@@ -0,0 +1,49 @@
+//! Native tests for the byte-dungeon command line tool.
+
+use std::process::Command;
+
+const TUTORIAL: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tutorial.json");
+
+fn cli(args: &[&str]) -> (i32, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_byte-dungeon")).args(args).output().unwrap();
+    (output.status.code().unwrap(), String::from_utf8(output.stdout).unwrap())
+}
+
+#[test]
+fn converts_runs_and_diffs_saves() {
+    let dir = std::env::temp_dir().join(format!("byte-dungeon-cli-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+
+    assert_eq!(cli(&["validate", TUTORIAL]).0, 0);
+    assert_eq!(cli(&["convert", TUTORIAL, &path("game.bdng")]).0, 0);
+    assert_eq!(cli(&["convert", &path("game.bdng"), &path("main.txt")]).0, 0);
+    assert!(std::fs::read_to_string(path("main.txt")).unwrap().starts_with("🧝..#"));
+
+    std::fs::write(path("moves.json"), r#"[{"caster": "🧝", "action_type": 0, "target_cell": [1, 0]}]"#).unwrap();
+    assert_eq!(cli(&["run", &path("game.bdng"), &path("moves.json"), "-o", &path("after.json")]).0, 0);
+    let (code, out) = cli(&["diff", &path("game.bdng"), &path("after.json")]);
+    assert_eq!(code, 1);
+    assert_eq!(out.lines().next(), Some("characters.🧝.row: 0 -> 1"));
+    assert_eq!(cli(&["diff", TUTORIAL, &path("game.bdng")]).0, 0);
+    assert_eq!(cli(&["unknown"]).0, 2);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn malformed_requests_are_refused_not_run() {
+    let dir = std::env::temp_dir().join(format!("byte-dungeon-cli-refused-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let requests = dir.join("bad.json");
+    std::fs::write(&requests, r#"[{"caster": "🧝", "action_type": 0},
+                                  {"caster": "🧝", "action_type": 1, "subtype_key": "abc"},
+                                  {"caster": "🧝", "action_type": 0, "target_cell": [1, 0]}]"#).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_byte-dungeon")).args(["run", TUTORIAL, requests.to_str().unwrap()]).output().unwrap();
+    let errors = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(output.status.code(), Some(1), "{}", errors);
+    let refused: Vec<&str> = errors.lines().filter(|line| line.starts_with("request")).collect();
+    assert_eq!(refused, vec!["request 0: a move needs a target cell", "request 1: 'abc' is not an item index"]);
+    let game: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(game["characters"]["🧝"]["row"], 1, "valid requests still run");
+    std::fs::remove_dir_all(dir).unwrap();
+}
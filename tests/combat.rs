@@ -0,0 +1,89 @@
+//! Native tests for the combat log kept of executed requests.
+
+mod common;
+
+use byte_dungeon::{GameSession, Request};
+use byte_dungeon::combat::{ActionRecord, RollRecord};
+use byte_dungeon::history::{Applied, Command};
+use byte_dungeon::layers::Visibility;
+use byte_dungeon::maps::{Cell, MAIN_MAP};
+use byte_dungeon::migrate::load_save;
+use byte_dungeon::permissions::Action;
+use common::face_to_face;
+use serde_json::{json, Value};
+
+fn execute(game: &mut GameSession, request: Value) -> ActionRecord {
+    let request: Request = serde_json::from_value(request).unwrap();
+    match game.apply(Command::Execute(request)).unwrap() {
+        Applied::Executed(record) => record,
+        applied => panic!("no record: {:?}", applied)
+    }
+}
+
+// The dragon slashes the elf after a d20 rolled for it
+fn slash(game: &mut GameSession) -> (i32, ActionRecord) {
+    let rolled_at = game.next_seq();
+    let roll = game.roll(1, 20);
+    let record = execute(game, json!({ "caster": "🐉", "action_type": 2, "subtype_key": "Slash", "target_cell": null,
+                                       "target_tokens": ["🧝"], "rolled_at": rolled_at }));
+    (roll, record)
+}
+
+#[test]
+fn moves_are_recorded_with_the_cells_left_and_reached() {
+    let mut game = face_to_face();
+    let moved = execute(&mut game, json!({ "caster": "🧝", "action_type": 0, "subtype_key": null, "target_cell": [1, 17], "target_tokens": null }));
+    assert_eq!(moved.action, Some(Action::Move));
+    assert_eq!(moved.changes[0].moved, Some((Cell::new(MAIN_MAP, 2, 17), Cell::new(MAIN_MAP, 1, 17))));
+    assert!(moved.rolls.is_empty());
+    assert_eq!(moved.to_string(), "🧝 moves: 🧝 to 1,17");
+}
+
+#[test]
+fn abilities_are_recorded_with_their_roll_damage_and_effects() {
+    let mut game = face_to_face();
+    let (roll, slash) = slash(&mut game);
+    assert_eq!(slash.rolls, vec![RollRecord { low: 1, high: 20, result: roll }]);
+    assert_eq!(slash.changes.len(), 1);
+    assert_eq!((slash.changes[0].token, slash.changes[0].hitpoints), ('🧝', -10));
+    assert_eq!(slash.changes[0].added, vec!["Slash damage".to_string()]);
+    assert_eq!(slash.to_string(), format!("🐉 uses Slash on 🧝 (rolled {} on d20): 🧝 -10 HP, 🧝 gains Slash damage", roll));
+}
+
+#[test]
+fn records_only_hold_the_dice_rolled_for_their_request() {
+    let mut game = face_to_face();
+    game.chat(Some('🧝'), "/r 1d20").unwrap();
+    game.roll(1, 20);
+    let slash = execute(&mut game, json!({ "caster": "🐉", "action_type": 2, "subtype_key": "Slash", "target_cell": null, "target_tokens": ["🧝"] }));
+    assert!(slash.rolls.is_empty(), "earlier chat rolls and failed attempts aren't this request's");
+}
+
+#[test]
+fn the_log_survives_undo_and_reloads_but_isnt_state() {
+    let mut game = face_to_face();
+    let (_, slash) = slash(&mut game);
+    assert!(game.undo().is_some());
+    assert_eq!(game.combat_log, vec![slash]);
+    let (reloaded, _) = load_save(game.to_save()).unwrap();
+    assert_eq!(reloaded.combat_lines(), game.combat_lines());
+    assert_eq!(reloaded.state_hash(), GameSession { combat_log: Vec::new(), ..reloaded.clone() }.state_hash());
+}
+
+#[test]
+fn players_only_get_the_records_of_tokens_they_see() {
+    let mut game = face_to_face();
+    execute(&mut game, json!({ "caster": "🧝", "action_type": 0, "subtype_key": null, "target_cell": [1, 17], "target_tokens": null }));
+    let (_, slash) = slash(&mut game);
+    game.apply(Command::SetVisibility { token: '🐉', visibility: Visibility::Hidden }).unwrap();
+    assert_eq!(game.player_copy().combat_log.len(), 1);
+    assert_eq!(game.record_for_players(&slash), None);
+}
+
+#[test]
+fn requests_of_tokens_off_the_map_are_refused_not_recorded() {
+    let mut game = face_to_face();
+    let request: Request = serde_json::from_value(json!({ "caster": "🦊", "action_type": 0, "subtype_key": null, "target_cell": [1, 0], "target_tokens": null })).unwrap();
+    assert_eq!(game.apply(Command::Execute(request)).unwrap_err().to_string(), "'🦊' is not on a map");
+    assert!(game.combat_log.is_empty());
+}
@@ -0,0 +1,52 @@
+//! Fixtures shared by the native tests, each test file only uses some of them.
+#![allow(dead_code)]
+
+use byte_dungeon::GameSession;
+use byte_dungeon::history::Command;
+use byte_dungeon::migrate::load_save;
+use byte_dungeon::permissions::Role;
+use serde_json::{json, Value};
+
+pub const TUTORIAL: &str = include_str!("../fixtures/tutorial.json");
+
+// The tutorial save as JSON, to edit before loading it
+pub fn tutorial_save() -> Value {
+    serde_json::from_str(TUTORIAL).unwrap()
+}
+
+// The tutorial: the elf at 0,0, the dragon at 2,18 and the skeleton at 6,2 of a 15x30 main map
+pub fn tutorial() -> GameSession {
+    serde_json::from_str(TUTORIAL).unwrap()
+}
+
+pub fn load(save: Value) -> GameSession {
+    load_save(save).unwrap().0
+}
+
+// Moves TOKEN of SAVE to ROW, COL of the main map, the cell it leaves becomes floor
+pub fn place(save: &mut Value, token: &str, row: usize, col: usize) {
+    let from = (save["characters"][token]["row"].as_u64().unwrap() as usize, save["characters"][token]["column"].as_u64().unwrap() as usize);
+    save["grid"][from.0][from.1] = json!("0");
+    save["grid"][row][col] = json!(token);
+    save["characters"][token]["row"] = json!(row);
+    save["characters"][token]["column"] = json!(col);
+}
+
+// Tutorial save with the elf at ROW, COL
+pub fn elf_at(row: usize, col: usize) -> Value {
+    let mut save = tutorial_save();
+    place(&mut save, "🧝", row, col);
+    save
+}
+
+// Tutorial with the elf standing next to the dragon
+pub fn face_to_face() -> GameSession {
+    load(elf_at(2, 17))
+}
+
+// Makes "dm" the host and "ana" a player controlling the elf
+pub fn seat_party(game: &mut GameSession) {
+    game.apply(Command::SetRole { user: "dm".into(), role: Some(Role::Host) }).unwrap();
+    game.apply(Command::SetRole { user: "ana".into(), role: Some(Role::Player) }).unwrap();
+    game.apply(Command::Grant { user: "ana".into(), token: '🧝' }).unwrap();
+}
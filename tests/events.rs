@@ -0,0 +1,51 @@
+//! Native tests for the event log and replay.
+
+mod common;
+
+use byte_dungeon::GameSession;
+use byte_dungeon::events::{replay, EventKind, ReplayError};
+use byte_dungeon::history::Command;
+use byte_dungeon::maps::{Cell, MAIN_MAP};
+use common::tutorial;
+
+fn board(game: &GameSession) -> serde_json::Value {
+    serde_json::json!({ "grid": game.grid, "sheets": game.sheets.keys().collect::<Vec<_>>() })
+}
+
+#[test]
+fn replay_rebuilds_the_session_at_any_point() {
+    let mut game = tutorial();
+    let start = game.clone();
+    game.apply(Command::ToggleCell(Cell::new(MAIN_MAP, 0, 1))).unwrap();
+    game.make_request(0, "", '🧝', 1, 0);
+    let halfway = board(&game);
+    let rolled = game.roll(1, 20);
+    game.apply(Command::ToggleCell(Cell::new(MAIN_MAP, 1, 0))).unwrap();
+    game.undo();
+    game.apply(Command::ToggleCell(Cell::new(MAIN_MAP, 2, 0))).unwrap();
+    assert_eq!(game.events.len(), 6);
+    assert!(matches!(game.events[2].kind, EventKind::Roll { result, .. } if result == rolled));
+
+    let rebuilt = replay(&start, &game.events, None).unwrap();
+    assert_eq!(board(&rebuilt), board(&game));
+    assert_eq!(rebuilt.events.len(), game.events.len());
+    assert_eq!(board(&replay(&start, &game.events, Some(1)).unwrap()), halfway);
+
+    let snapshot = replay(&start, &game.events, Some(2)).unwrap();
+    assert_eq!(board(&replay(&snapshot, &game.events, None).unwrap()), board(&game));
+}
+
+#[test]
+fn replay_refuses_a_log_with_missing_events() {
+    let mut game = tutorial();
+    let start = game.clone();
+    for col in 1..5 { game.apply(Command::ToggleCell(Cell::new(MAIN_MAP, 0, col))).unwrap(); }
+    assert_eq!(replay(&start, &game.events[3..], None).unwrap_err(), ReplayError::Gap { expected: 0, found: 3 });
+}
+
+#[test]
+fn fresh_web_sessions_roll_their_own_dice() {
+    byte_dungeon::reset_session();
+    let rolled = byte_dungeon::roll_dice(1, 1_000_000_000);
+    assert_ne!(rolled, GameSession::default().roll(1, 1_000_000_000), "an unseeded generator always rolls the same");
+}
@@ -0,0 +1,57 @@
+//! Native tests for the procedural dungeon generator.
+
+use byte_dungeon::generator::{generate, GeneratorOptions, Layout};
+use byte_dungeon::maps::ObjectKind;
+
+fn reachable(grid: &[Vec<char>]) -> bool {
+    let floors: Vec<(usize, usize)> = (0..grid.len())
+        .flat_map(|r| (0..grid[r].len()).map(move |c| (r, c)))
+        .filter(|&(r, c)| grid[r][c] == '0')
+        .collect();
+    let mut seen = vec![vec![false; grid[0].len()]; grid.len()];
+    let mut stack = vec![floors[0]];
+    let mut count = 1;
+    seen[floors[0].0][floors[0].1] = true;
+    while let Some((r, c)) = stack.pop() {
+        for (nr, nc) in [(r - 1, c), (r + 1, c), (r, c - 1), (r, c + 1)] {
+            if grid[nr][nc] == '0' && !seen[nr][nc] {
+                seen[nr][nc] = true;
+                count += 1;
+                stack.push((nr, nc));
+            }
+        }
+    }
+    count == floors.len()
+}
+
+#[test]
+fn layouts_are_seeded_and_connected() {
+    for layout in [Layout::Rooms, Layout::Caves, Layout::Maze] {
+        let options = GeneratorOptions { layout, rows: 21, cols: 31, seed: 7, traps: 3, spawns: 2, ..Default::default() };
+        let first = generate(&options).unwrap();
+        assert_eq!(first, generate(&options).unwrap());
+        assert_ne!(first, generate(&GeneratorOptions { seed: 8, ..options.clone() }).unwrap());
+
+        assert!(first.grid[0].iter().all(|c| *c == '1'));
+        assert!(reachable(&first.grid), "{:?} is not connected", layout);
+        let traps = first.objects.iter().filter(|obj| obj.2 == ObjectKind::Trap).count();
+        let spawns = first.objects.iter().filter(|obj| obj.2 == ObjectKind::Spawn(None)).count();
+        assert_eq!((traps, spawns), (3, 2));
+        assert!(first.objects.iter().all(|(r, c, _)| first.grid[*r][*c] == '0'));
+    }
+}
+
+#[test]
+fn large_caves_are_connected_without_reflooding_the_map() {
+    let options = GeneratorOptions { layout: Layout::Caves, rows: 400, cols: 400, seed: 3, ..Default::default() };
+    let started = std::time::Instant::now();
+    let map = generate(&options).unwrap();
+    assert!(reachable(&map.grid));
+    assert!(started.elapsed().as_secs() < 10, "took {:?}", started.elapsed());
+}
+
+#[test]
+fn rejects_unusable_options() {
+    assert!(generate(&GeneratorOptions { rows: 3, ..Default::default() }).is_err());
+    assert!(generate(&GeneratorOptions { density: Some(101), ..Default::default() }).is_err());
+}
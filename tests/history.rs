@@ -0,0 +1,89 @@
+//! Native tests for undo/redo of session edits.
+
+mod common;
+
+use byte_dungeon::history::{Applied, Command};
+use byte_dungeon::maps::{Cell, MAIN_MAP};
+use byte_dungeon::resize::{Anchor, OffBoard};
+use byte_dungeon::protocol::{ClientMessage, Envelope};
+use byte_dungeon::server::{Server, HISTORY_LIMIT};
+use common::{tutorial, tutorial_save};
+
+#[test]
+fn undo_and_redo_single_edits() {
+    let mut game = tutorial();
+    assert_eq!(game.apply(Command::ToggleCell(Cell::new(MAIN_MAP, 0, 1))).unwrap(), Applied::Cell('1'));
+    assert_eq!(game.apply(Command::ToggleCell(Cell::new(MAIN_MAP, 0, 0))).unwrap(), Applied::Cell('0'));
+    assert!(game.sheets.contains_key(&'🧝'));
+    assert_eq!(game.history_list().undo.len(), 2);
+
+    assert!(game.undo().is_some());
+    assert_eq!(game.grid[0][0], '🧝');
+    assert!(game.characters.contains_key(&'🧝') && !game.sheets.contains_key(&'🧝'));
+    assert!(game.undo().is_some());
+    assert_eq!(game.grid[0][1], '0');
+    assert!(game.undo().is_none());
+
+    assert!(game.redo().is_some());
+    assert_eq!(game.grid[0][1], '1');
+    game.apply(Command::ToggleCell(Cell::new(MAIN_MAP, 0, 2))).unwrap();
+    assert!(game.history_list().redo.is_empty());
+    assert!(game.redo().is_none());
+
+    game.set_history_limit(1);
+    assert_eq!(game.history_list().undo.len(), 1);
+}
+
+#[test]
+fn edits_off_the_map_are_refused_and_never_undone() {
+    let mut game = tutorial();
+    assert!(game.apply(Command::ToggleCell(Cell::new(MAIN_MAP, 99, 0))).is_err());
+    assert!(game.apply(Command::ToggleCell(Cell::new("crypt", 0, 0))).is_err());
+    assert!(game.history_list().undo.is_empty());
+    assert!(game.undo().is_none());
+}
+
+#[test]
+fn grouped_edits_undo_together() {
+    let mut game = tutorial();
+    game.begin_group("Grow and wall off");
+    game.apply(Command::Resize { map: MAIN_MAP.to_string(), rows: 20, cols: 40, anchor: Anchor::TopLeft,
+        policy: OffBoard::Reject }).unwrap();
+    game.begin_group("nested");
+    game.apply(Command::ToggleCell(Cell::new(MAIN_MAP, 19, 39))).unwrap();
+    game.end_group();
+    game.end_group();
+    assert_eq!(game.history_list().undo, vec!["Grow and wall off".to_string()]);
+
+    game.requests.push(('🧝', Vec::new()));
+    assert_eq!(game.undo().unwrap(), "Grow and wall off");
+    assert_eq!((game.grid.len(), game.grid[0].len()), (15, 30));
+    assert_eq!(game.requests.len(), 1);
+    game.redo();
+    assert_eq!(game.grid[19][39], '1');
+}
+
+#[test]
+fn refused_commands_in_a_group_are_rolled_back() {
+    let mut game = tutorial();
+    game.begin_group("Edit");
+    game.apply(Command::ToggleCell(Cell::new(MAIN_MAP, 0, 1))).unwrap();
+    let before = game.to_save();
+    assert!(game.apply(Command::GiveItem { token: '🧝', item: "Nothing".into() }).is_err());
+    assert!(game.apply(Command::ToggleCell(Cell::new(MAIN_MAP, 99, 0))).is_err());
+    assert_eq!(game.to_save(), before, "a refused command changes nothing, even inside a group");
+    game.end_group();
+    assert_eq!(game.history_list().undo, vec!["Edit".to_string()]);
+    game.undo();
+    assert_eq!(game.grid[0][1], '0');
+}
+
+#[test]
+fn server_rooms_keep_a_single_history_entry() {
+    let mut server = Server::default();
+    server.handle(1, Envelope::new(ClientMessage::StartHosting { room: "den".into(), user: "dm".into(), name: "DM".into(),
+        set: String::new(), game: Some(tutorial_save()) }, None));
+    let game = &mut server.rooms.get_mut("den").unwrap().game;
+    for col in 1..4 { game.apply(Command::ToggleCell(Cell::new(MAIN_MAP, 0, col))).unwrap(); }
+    assert_eq!(game.history_list().undo.len(), HISTORY_LIMIT);
+}
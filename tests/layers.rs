@@ -0,0 +1,81 @@
+//! Native tests for hidden tokens, detection checks and the GM notes layer.
+
+mod common;
+
+use byte_dungeon::GameSession;
+use byte_dungeon::history::Command;
+use byte_dungeon::layers::{Layer, LayerEntry, MapNote, Visibility};
+use byte_dungeon::maps::{Cell, MapObject, ObjectKind, MAIN_MAP};
+use byte_dungeon::sync::{self, TokenDelta};
+use byte_dungeon::viewer::{HealthBand, BAND_MAX_HP};
+use common::{elf_at, load, seat_party};
+use serde_json::json;
+
+// Tutorial with the elf, controlled by ana, standing next to the dragon
+fn face_to_face() -> GameSession {
+    let mut game = common::face_to_face();
+    seat_party(&mut game);
+    game
+}
+
+#[test]
+fn players_never_receive_hidden_tokens_or_notes() {
+    let mut game = face_to_face();
+    game.apply(Command::SetVisibility { token: '🐉', visibility: Visibility::Hidden }).unwrap();
+    game.apply(Command::SetVisibility { token: '🧝', visibility: Visibility::Invisible { dc: 30 } }).unwrap();
+    game.apply(Command::AddNote(MapNote { cell: Cell::new(MAIN_MAP, 2, 18), text: "ambush".into() })).unwrap();
+
+    let players = game.player_copy();
+    assert!(!players.characters.contains_key(&'🐉'));
+    assert_eq!(players.grid[2][18], '0');
+    assert!(players.characters.contains_key(&'🧝'), "a player always sees their own token");
+    assert!(players.notes.is_empty() && players.events.is_empty());
+    assert_eq!(game.layer(MAIN_MAP, Layer::GmNotes).len(), 1);
+    assert!(matches!(&game.layer(MAIN_MAP, Layer::Token)[0], LayerEntry::Token { token: '🐉', visibility: Visibility::Hidden, .. }));
+}
+
+#[test]
+fn notes_off_the_map_are_refused() {
+    let mut game = face_to_face();
+    assert!(game.apply(Command::AddNote(MapNote { cell: Cell::new(MAIN_MAP, 99, 0), text: "lost".into() })).is_err());
+    assert!(game.apply(Command::AddNote(MapNote { cell: Cell::new("crypt", 0, 0), text: "lost".into() })).is_err());
+    assert!(game.notes.is_empty());
+}
+
+#[test]
+fn detection_needs_an_invisible_token_in_sight() {
+    let mut game = face_to_face();
+    assert!(game.detect('🐉', '🧝').unwrap_err().contains("isn't invisible"));
+    game.apply(Command::SetVisibility { token: '🐉', visibility: Visibility::Invisible { dc: 100 } }).unwrap();
+    assert!(game.detect('🐉', '💀').unwrap_err().contains("out of sight"));
+    assert_eq!(game.detect('🐉', '🧝'), Ok(false));
+    assert!(!game.seen_by_players('🐉'));
+}
+
+#[test]
+fn detected_tokens_are_synced_to_players() {
+    let mut game = face_to_face();
+    game.apply(Command::SetVisibility { token: '🐉', visibility: Visibility::Invisible { dc: -20 } }).unwrap();
+    let before = game.player_copy();
+    assert_eq!(game.detect('🐉', '🧝'), Ok(true));
+    let delta = sync::diff(&before, &game.player_copy());
+    assert!(matches!(&delta.tokens[..], [('🐉', TokenDelta::Placed(_))]));
+    assert!(delta.notes.is_none());
+}
+
+#[test]
+fn players_never_receive_traps_or_the_hitpoints_of_monsters() {
+    let mut save = elf_at(2, 17);
+    save["characters"]["🐉"]["sheet"]["hitpoints"] = json!(7);
+    let mut game = load(save);
+    seat_party(&mut game);
+    game.objects.push(MapObject { cell: Cell::new(MAIN_MAP, 1, 17), kind: ObjectKind::Trap });
+    game.objects.push(MapObject { cell: Cell::new(MAIN_MAP, 0, 17), kind: ObjectKind::Door });
+
+    let players = game.player_copy();
+    assert_eq!(players.objects.iter().map(|object| object.kind.clone()).collect::<Vec<_>>(), vec![ObjectKind::Door]);
+    let copy = players.to_save();
+    assert_eq!(copy["characters"]["🐉"]["sheet"]["hitpoints"], HealthBand::Bloodied.hitpoints());
+    assert_eq!(copy["characters"]["🐉"]["sheet"]["max_hp"], BAND_MAX_HP);
+    assert_eq!(copy["characters"]["🧝"], game.to_save()["characters"]["🧝"], "the party keeps its own hitpoints");
+}
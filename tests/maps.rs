@@ -0,0 +1,70 @@
+//! Native tests for multi-map sessions.
+
+mod common;
+
+use byte_dungeon::history::Command;
+use byte_dungeon::maps::{Cell, MapLink, MAIN_MAP};
+use byte_dungeon::resize::{Anchor, OffBoard, ResizeError};
+use common::tutorial;
+
+#[test]
+fn stairs_move_token_between_maps() {
+    let mut game = tutorial();
+    assert!(game.add_map("cellar", 5, 5));
+    assert!(!game.add_map("cellar", 5, 5));
+    assert!(game.link_cells(MapLink {
+        name: "stairs".to_string(),
+        from: Cell::new(MAIN_MAP, 1, 0),
+        to: Cell::new("cellar", 2, 2),
+        two_way: true
+    }));
+
+    game.move_token('🧝', 1, 0);
+    assert_eq!(game.board(MAIN_MAP).unwrap()[0][0], '0');
+    assert_eq!(game.board(MAIN_MAP).unwrap()[1][0], '0');
+    assert_eq!(game.board("cellar").unwrap()[2][2], '🧝');
+
+    assert!(game.remove_map("cellar"));
+    assert!(game.sheets.contains_key(&'🧝'));
+    assert!(game.links.is_empty());
+    assert_eq!(game.map_ids(), vec![MAIN_MAP.to_string()]);
+}
+
+#[test]
+fn anchored_resize_translates_and_handles_falling_tokens() {
+    let mut game = tutorial();
+    game.resize_map(MAIN_MAP, 17, 32, Anchor::Center, OffBoard::Reject).unwrap();
+    assert_eq!((game.grid.len(), game.grid[0].len()), (17, 32));
+    assert_eq!(game.grid[1][1], '🧝');
+    assert_eq!(game.grid[0][0], '0');
+
+    let err = game.resize_map(MAIN_MAP, 15, 30, Anchor::BottomRight, OffBoard::Reject).unwrap_err();
+    assert_eq!(err, ResizeError::TokensOffBoard(vec!['🧝']));
+    assert_eq!(game.grid.len(), 17);
+
+    let report = game.resize_map(MAIN_MAP, 15, 30, Anchor::BottomRight, OffBoard::Nearest).unwrap();
+    assert_eq!(report.moved.len(), 1);
+    let (token, row, col) = report.moved[0];
+    assert_eq!((token, game.grid[row][col]), ('🧝', '🧝'));
+
+    let report = game.resize_map(MAIN_MAP, 0, 5, Anchor::TopLeft, OffBoard::ReturnToSheets).unwrap();
+    assert_eq!(report.returned.len(), 3);
+    assert!(game.grid.is_empty() && game.characters.is_empty());
+}
+
+#[test]
+fn resizes_too_large_to_allocate_are_refused() {
+    let mut game = tutorial();
+    let err = game.resize_map(MAIN_MAP, 100_000, 100_000, Anchor::TopLeft, OffBoard::Reject).unwrap_err();
+    assert_eq!(err, ResizeError::TooLarge { rows: 100_000, cols: 100_000 });
+    assert_eq!(game.grid.len(), 15);
+}
+
+#[test]
+fn maps_too_large_to_allocate_are_refused() {
+    let mut game = tutorial();
+    assert!(!game.add_map("abyss", 100_000, 100_000));
+    let err = game.apply(Command::AddMap { id: "abyss".into(), rows: usize::MAX, cols: 2 }).unwrap_err();
+    assert!(err.to_string().contains("too large"), "{}", err);
+    assert_eq!(game.map_ids(), vec![MAIN_MAP.to_string()]);
+}
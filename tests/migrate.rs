@@ -0,0 +1,34 @@
+//! Native tests for versioned saves and their migrations.
+
+mod common;
+
+use byte_dungeon::maps::MAIN_MAP;
+use byte_dungeon::migrate::{load_save, LoadError, FORMAT_VERSION};
+use common::tutorial_save;
+use serde_json::json;
+
+#[test]
+fn old_saves_are_upgraded_and_new_ones_load_as_is() {
+    let mut old = tutorial_save();
+    let sheet = old["characters"]["🐉"]["sheet"].as_object_mut().unwrap();
+    sheet.remove("traits");
+    sheet.remove("effects");
+
+    let (game, applied) = load_save(old).unwrap();
+    assert_eq!(applied.len(), FORMAT_VERSION as usize);
+    assert!(applied[0].starts_with("1:"));
+    assert_eq!(game.board(MAIN_MAP).unwrap().len(), 15);
+
+    let save = game.to_save();
+    assert_eq!(save["format_version"], FORMAT_VERSION);
+    let (reloaded, applied) = load_save(save).unwrap();
+    assert!(applied.is_empty());
+    assert_eq!(reloaded.state_hash(), game.state_hash());
+}
+
+#[test]
+fn saves_from_newer_versions_or_of_another_shape_are_refused() {
+    let future = json!({ "format_version": FORMAT_VERSION + 1 });
+    assert_eq!(load_save(future).unwrap_err(), LoadError::TooNew { found: FORMAT_VERSION + 1, supported: FORMAT_VERSION });
+    assert!(matches!(load_save(json!({ "grid": 3 })), Err(LoadError::Invalid(_))));
+}
@@ -0,0 +1,85 @@
+//! Native tests for users, roles and token control.
+
+mod common;
+
+use byte_dungeon::{GameSession, Request};
+use byte_dungeon::history::{Command, CommandError};
+use byte_dungeon::permissions::{Action, PermissionError, Role};
+use byte_dungeon::sync;
+use serde_json::json;
+use common::tutorial;
+
+fn request(user: &str, caster: &str, action_type: i32, row: usize, col: usize) -> Request {
+    serde_json::from_value(json!({ "caster": caster, "action_type": action_type, "subtype_key": null,
+                                   "target_cell": [row, col], "target_tokens": null, "user": user })).unwrap()
+}
+
+fn refused(game: &mut GameSession, req: Request) -> PermissionError {
+    match game.apply(Command::Execute(req)) {
+        Err(CommandError::Permission(err)) => err,
+        other => panic!("expected a permission error, got {:?}", other)
+    }
+}
+
+// Tutorial where ana and bo control the elf, ana the dragon too, and eve watches
+fn table() -> GameSession {
+    let mut game = tutorial();
+    for (user, role) in [("dm", Role::Host), ("ana", Role::Player), ("bo", Role::Player), ("eve", Role::Spectator)] {
+        game.apply(Command::SetRole { user: user.into(), role: Some(role) }).unwrap();
+    }
+    for (user, token) in [("ana", '🧝'), ("bo", '🧝'), ("ana", '🐉')] {
+        game.apply(Command::Grant { user: user.into(), token }).unwrap();
+    }
+    game
+}
+
+#[test]
+fn players_act_for_the_tokens_they_control() {
+    let mut game = table();
+    assert_eq!(game.permissions.tokens_of("ana"), vec!['🐉', '🧝']);
+    assert_eq!(game.permissions.controllers_of('🧝'), vec!["ana".to_string(), "bo".to_string()]);
+
+    game.apply(Command::Execute(request("bo", "🧝", 0, 0, 1))).unwrap();
+    assert_eq!(game.grid[0][1], '🧝');
+    assert_eq!(refused(&mut game, request("bo", "🐉", 0, 2, 17)), PermissionError::NotController { user: "bo".into(), token: '🐉' });
+    game.apply(Command::Execute(request("dm", "💀", 0, 6, 3))).unwrap();
+}
+
+#[test]
+fn spectators_and_unknown_users_never_act() {
+    let mut game = table();
+    assert_eq!(refused(&mut game, request("eve", "🧝", 0, 0, 2)), PermissionError::Spectator("eve".into()));
+    assert_eq!(refused(&mut game, request("zed", "🧝", 0, 0, 2)), PermissionError::UnknownUser("zed".into()));
+    assert_eq!(game.grid[0][0], '🧝');
+}
+
+#[test]
+fn withheld_actions_are_refused_to_that_player_only() {
+    let mut game = table();
+    game.apply(Command::SetAllowed { user: "ana".into(), action: Action::Move, allowed: false }).unwrap();
+    assert_eq!(refused(&mut game, request("ana", "🧝", 0, 0, 2)), PermissionError::Withheld { user: "ana".into(), action: Action::Move });
+    assert!(game.check_request(&request("ana", "🧝", 0, 0, 2)).unwrap_err().contains("isn't allowed to move"));
+    game.apply(Command::Execute(request("bo", "🧝", 0, 0, 2))).unwrap();
+}
+
+#[test]
+fn the_session_always_keeps_a_host() {
+    let mut game = table();
+    assert!(matches!(game.apply(Command::SetRole { user: "dm".into(), role: Some(Role::CoDm) }),
+                     Err(CommandError::Permission(PermissionError::LastHost))));
+    assert_eq!(game.permissions.role("dm"), Some(Role::Host));
+}
+
+#[test]
+fn role_changes_undo_and_sync() {
+    let mut client = tutorial();
+    let mut game = table();
+    game.apply(Command::SetRole { user: "bo".into(), role: None }).unwrap();
+    assert_eq!(game.permissions.controllers_of('🧝'), vec!["ana".to_string()]);
+    game.undo();
+    assert_eq!(game.permissions.controllers_of('🧝').len(), 2);
+
+    client.apply_delta(&sync::diff(&client, &game)).unwrap();
+    assert_eq!(client.permissions, game.permissions);
+    assert_eq!(client.state_hash(), game.state_hash());
+}
@@ -0,0 +1,29 @@
+//! Native tests for the versioned network protocol and its TypeScript typings.
+
+use byte_dungeon::protocol::{self, ClientMessage, Envelope, ProtocolError, ServerMessage, PROTOCOL_VERSION};
+use byte_dungeon::server::Server;
+
+#[test]
+fn envelopes_are_versioned_acked_and_typed() {
+    let text = protocol::encode(ClientMessage::ApproveRequest { index: 2 }, Some(7));
+    assert_eq!(text, format!(r#"{{"v":{},"id":7,"type":"approveRequest","index":2}}"#, PROTOCOL_VERSION));
+    let envelope: Envelope<ClientMessage> = protocol::decode(&text).unwrap();
+    assert_eq!(envelope.id, Some(7));
+    assert!(matches!(envelope.message, ClientMessage::ApproveRequest { index: 2 }));
+
+    let old = r#"{"v":0,"type":"startTurn"}"#;
+    assert_eq!(protocol::decode::<ClientMessage>(old).unwrap_err(), ProtocolError::Version { found: 0, supported: PROTOCOL_VERSION });
+    assert!(matches!(protocol::decode::<ClientMessage>(r#"{"type":"startTurn"}"#), Err(ProtocolError::Malformed(_))));
+    assert!(matches!(protocol::decode::<ClientMessage>(r#"{"v":1,"type":"approveRequest"}"#), Err(ProtocolError::Malformed(_))));
+
+    let mut server = Server::default();
+    let host = ClientMessage::StartHosting { room: "den".into(), user: "dm".into(), name: "DM".into(), set: String::new(), game: None };
+    let sent = server.handle(1, Envelope::new(host, Some(1)));
+    assert!(matches!(sent.last(), Some((1, Envelope { id: Some(1), message: ServerMessage::Ack, .. }))));
+    let sent = server.handle(2, Envelope::new(ClientMessage::StartTurn, Some(5)));
+    assert!(matches!(sent.last(), Some((2, Envelope { id: Some(5), message: ServerMessage::TransactionFailed { .. }, .. }))));
+    assert!(!server.handle(1, Envelope::new(ClientMessage::StartTurn, None)).iter().any(|(_, sent)| sent.id.is_some()));
+
+    assert_eq!(protocol::typescript(), include_str!("../webfiles/protocol.d.ts"),
+               "typings are stale, run `byte-dungeon typescript > webfiles/protocol.d.ts`");
+}
@@ -0,0 +1,76 @@
+//! Native tests for settling the conflicts between the requests of a simultaneous turn.
+
+mod common;
+
+use byte_dungeon::{GameSession, Request};
+use byte_dungeon::resolve::{Conflict, Policy, Resolution};
+use common::{elf_at, load};
+use serde_json::json;
+
+// Tutorial with the elf (initiative 4) at ROW, COL near the dragon (initiative 2) at 2,18, the skeleton is dead
+fn near_the_dragon(row: usize, col: usize) -> GameSession {
+    let mut save = elf_at(row, col);
+    save["characters"]["💀"]["sheet"]["hitpoints"] = json!(0);
+    load(save)
+}
+
+fn queue(game: &mut GameSession, caster: char, action_type: i32, key: Option<&str>, cell: Option<(usize, usize)>, targets: Option<Vec<char>>) {
+    let request: Request = serde_json::from_value(json!({ "caster": caster, "action_type": action_type, "subtype_key": key,
+                                                          "target_cell": cell, "target_tokens": targets })).unwrap();
+    game.log_request(caster, vec![request]);
+}
+
+// The elf at 2,16 and the dragon both moving to 2,17, in initiative order
+fn race_for_a_cell() -> GameSession {
+    let mut game = near_the_dragon(2, 16);
+    queue(&mut game, '🐉', 0, None, Some((2, 17)), None);
+    queue(&mut game, '🧝', 0, None, Some((2, 17)), None);
+    game.sort_requests();
+    game
+}
+
+#[test]
+fn the_earlier_move_keeps_the_cell_by_default() {
+    let game = race_for_a_cell();
+    let outcomes = game.resolve_requests(Policy::InitiativeWins);
+    assert_eq!(json!(outcomes[0].request)["caster"], "🧝");
+    assert_eq!(outcomes[0].resolution, Resolution::Kept);
+    assert_eq!(outcomes[1].conflicts, vec![Conflict::SameDestination { with: '🧝' }]);
+    assert_eq!(outcomes[1].resolution, Resolution::Cancelled);
+}
+
+#[test]
+fn bumped_moves_go_to_the_nearest_free_cell() {
+    let game = race_for_a_cell();
+    let outcomes = game.resolve_requests(Policy::Bump);
+    assert_eq!(outcomes[1].resolution, Resolution::Bumped { cell: (1, 17) });
+    assert_eq!(json!(outcomes[1].request)["target_cell"], json!([1, 17]));
+}
+
+#[test]
+fn cancel_drops_every_request_of_a_conflict() {
+    let game = race_for_a_cell();
+    let outcomes = game.resolve_requests(Policy::Cancel);
+    assert!(outcomes.iter().all(|outcome| outcome.resolution == Resolution::Cancelled));
+    assert_eq!(game.grid[2][16], '🧝', "resolving never changes the session");
+}
+
+#[test]
+fn abilities_drop_dead_targets_and_go_on() {
+    let mut game = near_the_dragon(2, 17);
+    queue(&mut game, '🐉', 2, Some("Slash"), None, Some(vec!['🧝', '💀']));
+    let outcomes = game.resolve_requests(Policy::InitiativeWins);
+    assert_eq!(outcomes[0].conflicts, vec![Conflict::TargetDead { target: '💀' }]);
+    assert_eq!(outcomes[0].resolution, Resolution::Retargeted { dropped: vec!['💀'] });
+}
+
+#[test]
+fn abilities_without_a_target_left_are_cancelled() {
+    let mut game = near_the_dragon(2, 17);
+    queue(&mut game, '🐉', 2, Some("Slash"), None, Some(vec!['🧝', '💀']));
+    queue(&mut game, '🧝', 0, None, Some((1, 16)), None);
+    game.sort_requests();
+    let outcomes = game.resolve_requests(Policy::InitiativeWins);
+    assert_eq!(outcomes[1].conflicts, vec![Conflict::OutOfRange { target: '🧝' }, Conflict::TargetDead { target: '💀' }]);
+    assert_eq!(outcomes[1].resolution, Resolution::Cancelled);
+}
@@ -0,0 +1,214 @@
+//! Native tests for the game server, over real WebSocket connections on localhost.
+
+mod common;
+use byte_dungeon::protocol::{ClientMessage, Envelope, Pending, ServerMessage, PROTOCOL_VERSION};
+use byte_dungeon::layers::{MapNote, Visibility};
+use byte_dungeon::maps::{Cell, MAIN_MAP};
+use byte_dungeon::server::{serve, Server};
+use common::{elf_at, tutorial_save};
+use serde_json::{json, Value};
+use tungstenite::{connect, Message, WebSocket};
+use tungstenite::stream::MaybeTlsStream;
+
+use std::thread;
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+type Client = WebSocket<MaybeTlsStream<TcpStream>>;
+
+fn client(address: &str) -> Client {
+    let (socket, _) = connect(format!("ws://{}", address)).unwrap();
+    if let MaybeTlsStream::Plain(stream) = socket.get_ref() { stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap(); }
+    socket
+}
+
+fn send(socket: &mut Client, mut message: Value) {
+    message["v"] = json!(PROTOCOL_VERSION);
+    socket.send(Message::Text(message.to_string())).unwrap();
+}
+
+// Reads messages until one of type KIND, skipping the others (logs, syncs)
+fn expect(socket: &mut Client, kind: &str) -> Value {
+    loop {
+        if let Message::Text(text) = socket.read().unwrap() {
+            let message: Value = serde_json::from_str(&text).unwrap();
+            if message["type"] == kind { return message; }
+        }
+    }
+}
+
+#[test]
+fn host_runs_a_turn_and_players_only_act_for_their_tokens() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || serve(listener, Server::default()));
+
+    let game = tutorial_save();
+    let mut host = client(&address);
+    send(&mut host, json!({ "type": "startHosting", "room": "den", "user": "dm", "name": "DM", "game": game }));
+    assert_eq!(expect(&mut host, "startSession")["room"], "den");
+
+    let mut player = client(&address);
+    send(&mut player, json!({ "type": "startTurn" }));
+    assert_eq!(expect(&mut player, "transactionFailed")["reason"], "join a session first");
+    send(&mut player, json!({ "type": "findSession", "room": "den", "user": "p1", "name": "Ana" }));
+    let joined = expect(&mut player, "joinGame");
+    assert_eq!(joined["tokens"], json!([]));
+    assert_eq!(joined["game"]["grid"][0][0], "🧝");
+
+    send(&mut player, json!({ "type": "requestAccess", "token": "🧝" }));
+    assert_eq!(expect(&mut host, "loadAccessRequests")["requests"][0]["user"], "p1");
+    send(&mut player, json!({ "type": "grantAccess", "index": 0 }));
+    assert_eq!(expect(&mut player, "transactionFailed")["reason"], "only the host can do that");
+    send(&mut host, json!({ "type": "grantAccess", "index": 0 }));
+    send(&mut host, json!({ "type": "startTurn" }));
+    assert_eq!(expect(&mut player, "addTokenAccess")["tokens"], json!([]));
+    assert_eq!(expect(&mut player, "addTokenAccess")["tokens"], json!(["🧝"]));
+
+    let request = |caster: &str, row: u32, col: u32| json!({ "caster": caster, "action_type": 0, "subtype_key": null,
+                                                             "target_cell": [row, col], "target_tokens": null });
+    send(&mut player, json!({ "type": "addTurn", "requests": [request("🐉", 2, 17)] }));
+    assert_eq!(expect(&mut player, "transactionFailed")["reason"], "'p1' doesn't control 🐉");
+    send(&mut player, json!({ "type": "addTurn", "requests": [request("🧝", 0, 1), request("🧝", 9, 9)] }));
+    while expect(&mut host, "socketLog")["message"] != "'Ana' has ended turn" {}
+    send(&mut host, json!({ "type": "endTurn" }));
+    let pending = expect(&mut host, "loadRequests");
+    assert_eq!(pending["requests"].as_array().unwrap().len(), 2);
+    assert_eq!(pending["requests"][0]["user"], "p1");
+
+    send(&mut host, json!({ "type": "approveRequest", "index": 0 }));
+    assert_eq!(expect(&mut player, "executeRequest")["request"]["target_cell"], json!([0, 1]));
+    let delta = expect(&mut player, "sync")["delta"].clone();
+    assert!(delta["cells"].as_array().unwrap().iter().any(|cell| cell[1] == "🧝"));
+
+    send(&mut host, json!({ "type": "approveRequest", "index": 0 }));
+    let refused = expect(&mut player, "socketLog");
+    assert!(refused["message"].as_str().unwrap().starts_with("Refused the request of 🧝"));
+    assert_eq!(expect(&mut host, "loadRequests")["requests"].as_array().unwrap().len(), 1);
+    assert_eq!(expect(&mut host, "loadRequests")["requests"], json!([]));
+}
+
+#[test]
+fn rejoining_as_someone_else_needs_their_secret() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || serve(listener, Server::default()));
+
+    let game = tutorial_save();
+    let mut host = client(&address);
+    send(&mut host, json!({ "type": "startHosting", "room": "den", "user": "dm", "name": "DM", "game": game }));
+    let secret = expect(&mut host, "startSession")["secret"].clone();
+    assert_eq!(secret.as_str().unwrap().len(), 32);
+
+    let mut impostor = client(&address);
+    send(&mut impostor, json!({ "type": "findSession", "room": "den", "user": "dm", "name": "DM" }));
+    assert_eq!(expect(&mut impostor, "transactionFailed")["reason"], "'dm' already joined this room, rejoin with its secret");
+    send(&mut impostor, json!({ "type": "findSession", "room": "den", "user": "dm", "name": "DM", "secret": "guess" }));
+    expect(&mut impostor, "transactionFailed");
+    send(&mut impostor, json!({ "type": "setRole", "user": "mallory", "role": "co-dm" }));
+    assert_eq!(expect(&mut impostor, "transactionFailed")["reason"], "join a session first");
+
+    let mut back = client(&address);
+    send(&mut back, json!({ "type": "findSession", "room": "den", "user": "dm", "name": "DM", "secret": secret }));
+    assert_eq!(expect(&mut back, "hostRejoin")["secret"], secret);
+}
+
+#[test]
+fn invalid_saves_are_refused_when_hosting() {
+    let mut game = tutorial_save();
+    game["characters"]["🧝"]["row"] = json!(500);
+    let mut server = Server::default();
+    let sent = server.handle(1, Envelope::new(ClientMessage::StartHosting { room: "den".into(), user: "dm".into(),
+        name: "DM".into(), set: String::new(), game: Some(game) }, Some(3)));
+    match &sent[0].1.message {
+        ServerMessage::TransactionFailed { reason } => assert!(reason.starts_with("The saved game isn't valid: Error at characters.🧝"), "{}", reason),
+        other => panic!("{:?}", other)
+    }
+    assert!(server.rooms.is_empty());
+}
+
+#[test]
+fn a_message_that_panics_leaves_the_room_as_it_was() {
+    let mut server = Server::default();
+    let message = |message: ClientMessage| Envelope::new(message, Some(1));
+    server.receive(1, message(ClientMessage::StartHosting { room: "den".into(), user: "dm".into(), name: "DM".into(),
+        set: String::new(), game: Some(tutorial_save()) }));
+    server.receive(1, message(ClientMessage::StartTurn));
+    let request = serde_json::from_value(json!({ "caster": "🧝", "action_type": 0, "subtype_key": null,
+                                                 "target_cell": [0, 1], "target_tokens": null })).unwrap();
+    server.receive(1, message(ClientMessage::AddTurn { requests: vec![request] }));
+
+    // A token on a map that doesn't exist can't be resolved
+    let room = server.rooms.get_mut("den").unwrap();
+    let mut broken = serde_json::to_value(&room.game).unwrap();
+    broken["characters"]["🧝"]["map"] = json!("cellar");
+    room.game = serde_json::from_value(broken).unwrap();
+    assert_eq!(room.game.requests.len(), 1);
+
+    let sent = server.receive(1, message(ClientMessage::EndTurn));
+    match &sent.last().unwrap().1.message {
+        ServerMessage::TransactionFailed { reason } => assert_eq!(reason, "the server couldn't handle that message"),
+        other => panic!("{:?}", other)
+    }
+    let room = &server.rooms["den"];
+    assert!(room.turn_open && room.pending.is_empty());
+    assert_eq!(serde_json::to_value(&room.game.requests).unwrap()[0][1][0]["target_cell"], json!([0, 1]));
+}
+
+#[test]
+fn players_are_not_told_about_hidden_targets_or_why_requests_were_refused() {
+    let mut game = elf_at(2, 17);
+    game["characters"]["🐉"]["visibility"] = json!("Hidden");
+    game["characters"]["🧝"]["sheet"]["abilities"] = json!(["Slash"]);
+    let mut server = Server::default();
+    server.handle(1, Envelope::new(ClientMessage::StartHosting { room: "den".into(), user: "dm".into(), name: "DM".into(),
+        set: String::new(), game: Some(game) }, None));
+    server.handle(2, Envelope::new(ClientMessage::FindSession { room: "den".into(), user: "p1".into(), name: "Ana".into(), secret: None }, None));
+
+    let approve = |server: &mut Server, ability: &str| {
+        let request = serde_json::from_value(json!({ "caster": "🧝", "action_type": 2, "subtype_key": ability,
+                                                     "target_cell": [2, 18], "target_tokens": ["🐉"] })).unwrap();
+        server.rooms.get_mut("den").unwrap().pending.push(Pending { user: "p1".into(), request });
+        let sent = server.handle(1, Envelope::new(ClientMessage::ApproveRequest { index: 0 }, None));
+        sent.into_iter().map(|(client, envelope)| (client, serde_json::to_value(&envelope.message).unwrap())).collect::<Vec<_>>()
+    };
+    let received = |sent: &Vec<(u64, Value)>, client: u64, kind: &str| -> Vec<Value> {
+        sent.iter().filter(|(to, message)| *to == client && message["type"] == kind).map(|(_, message)| message.clone()).collect()
+    };
+
+    let sent = approve(&mut server, "Slash");
+    assert_eq!(received(&sent, 1, "executeRequest")[0]["request"]["target_tokens"], json!(["🐉"]));
+    let request = &received(&sent, 2, "executeRequest")[0]["request"];
+    assert_eq!((&request["target_tokens"], &request["target_cell"]), (&json!([]), &Value::Null));
+
+    let sent = approve(&mut server, "Fireball");
+    let dm_log = received(&sent, 1, "socketLog");
+    assert!(dm_log[0]["message"].as_str().unwrap().starts_with("Refused the request of 🧝: "), "{}", dm_log[0]);
+    assert_eq!(received(&sent, 2, "socketLog")[0]["message"], "Refused the request of 🧝");
+}
+
+#[test]
+fn only_the_dms_hide_tokens_and_pin_notes() {
+    let mut server = Server::default();
+    server.handle(1, Envelope::new(ClientMessage::StartHosting { room: "den".into(), user: "dm".into(), name: "DM".into(),
+        set: String::new(), game: Some(tutorial_save()) }, None));
+    server.handle(2, Envelope::new(ClientMessage::FindSession { room: "den".into(), user: "p1".into(), name: "Ana".into(), secret: None }, None));
+    let mut send = |client: u64, message: ClientMessage| match server.handle(client, Envelope::new(message, Some(1))).pop().unwrap().1.message {
+        ServerMessage::TransactionFailed { reason } => Err(reason),
+        _ => Ok(())
+    };
+
+    let hide = ClientMessage::SetVisibility { token: '🐉', visibility: Visibility::Hidden };
+    assert_eq!(send(2, hide.clone()), Err("only the host can do that".to_string()));
+    assert_eq!(send(1, hide), Ok(()));
+    let note = MapNote { cell: Cell::new(MAIN_MAP, 2, 18), text: "Ambush".into() };
+    assert_eq!(send(1, ClientMessage::AddNote { note }), Ok(()));
+    assert_eq!(send(1, ClientMessage::Detect { token: '🐉', by: '🧝' }), Err("'🐉' isn't invisible".to_string()));
+    assert_eq!(server.rooms["den"].game.notes.len(), 1);
+    assert!(!server.rooms["den"].game.seen_by_players('🐉'));
+
+    let sent = server.handle(1, Envelope::new(ClientMessage::RemoveNotes { cell: Cell::new(MAIN_MAP, 2, 18) }, None));
+    assert!(server.rooms["den"].game.notes.is_empty());
+    let to_player: Vec<&ServerMessage> = sent.iter().filter(|(client, _)| *client == 2).map(|(_, envelope)| &envelope.message).collect();
+    assert!(matches!(to_player[..], [ServerMessage::Sync { .. }]), "players only get their copy: {:?}", to_player);
+}
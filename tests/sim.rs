@@ -0,0 +1,71 @@
+//! Native tests for the Monte Carlo combat simulator.
+
+mod common;
+
+use byte_dungeon::GameSession;
+use byte_dungeon::ai::Behaviour;
+use byte_dungeon::history::Command;
+use byte_dungeon::sim::{SimError, SimOptions};
+use common::{load, place, tutorial_save};
+use serde_json::json;
+
+fn duel() -> (GameSession, SimOptions) {
+    (load(tutorial_save()), SimOptions { runs: 40, seed: 9, party: vec!['🧝'], ..Default::default() })
+}
+
+#[test]
+fn encounters_are_played_out_to_a_result() {
+    let (game, options) = duel();
+    let report = game.simulate(&options).unwrap();
+    assert_eq!(report.party_wins + report.monster_wins + report.draws, 40);
+    assert!(report.party_wins > 0 && report.monster_wins > 0, "{}", report);
+    assert!(report.average_rounds >= 1.0);
+    assert_eq!(report.hitpoints.keys().cloned().collect::<String>(), "🐉💀🧝");
+    assert!(report.hitpoints.values().all(|hitpoints| (0.0..=20.0).contains(hitpoints)));
+    assert_eq!(game.combat_log.len(), 0, "the session itself is never played");
+}
+
+#[test]
+fn reports_count_the_usefulness_of_each_ability() {
+    let (game, options) = duel();
+    let report = game.simulate(&options).unwrap();
+    let slash = &report.abilities[&'🧝']["Slash"];
+    assert!(slash.uses >= slash.hits && slash.hits > 0);
+    assert_eq!(slash.damage, 10 * slash.hits as i64, "every Slash that lands takes 10 hitpoints");
+    let kills: u32 = report.abilities[&'🧝'].values().map(|stats| stats.kills).sum();
+    assert!(kills >= 2 * report.party_wins, "a party win takes the dragon and the skeleton down");
+}
+
+#[test]
+fn the_same_seed_plays_the_same_combats() {
+    let (game, options) = duel();
+    let report = game.simulate(&options).unwrap();
+    assert_eq!(game.simulate(&options).unwrap(), report);
+    assert_ne!(game.simulate(&SimOptions { seed: 10, ..options }).unwrap(), report);
+}
+
+#[test]
+fn encounters_without_runs_or_sides_are_refused() {
+    let (game, options) = duel();
+    assert_eq!(game.simulate(&SimOptions { runs: 0, ..options.clone() }), Err(SimError::NoRuns));
+    assert_eq!(game.simulate(&SimOptions { party: Vec::new(), ..options.clone() }), Err(SimError::NoParty));
+    assert_eq!(game.simulate(&SimOptions { party: vec!['🧝', '🐉', '💀'], ..options }), Err(SimError::NoMonsters));
+}
+
+#[test]
+fn heroes_play_the_behaviour_the_dm_gave_them_and_healing_counts_for_allies() {
+    let mut save = tutorial_save();
+    place(&mut save, "💀", 1, 0);
+    save["characters"]["💀"]["sheet"]["abilities"] = json!(["Mend"]);
+    save["characters"]["🧝"]["sheet"]["hitpoints"] = json!(8);
+    save["abilities"]["Mend"] = json!({ "name": "Mend", "range": 1, "action_points": 2, "casting_roll": [1, 20],
+        "stat_modifier": null, "requirements": [], "target_effects": ["Mend"], "caster_effects": [] });
+    save["effects"]["Mend"] = json!({ "name": "Mend", "duration": 0, "target_stat": "health", "modifier": [5, 1], "temporary": false });
+    let mut game = load(save);
+    game.apply(Command::SetBehaviour { token: '💀', behaviour: Some(Behaviour::Support) }).unwrap();
+
+    let report = game.simulate(&SimOptions { runs: 20, seed: 3, party: vec!['🧝', '💀'], ..Default::default() }).unwrap();
+    let mend = &report.abilities[&'💀']["Mend"];
+    assert!(mend.hits > 0 && mend.healing > 0, "{}", report);
+    assert_eq!((mend.damage, mend.kills), (0, 0), "healing an ally is never damage");
+}
@@ -0,0 +1,70 @@
+//! Native tests for the persisted rooms of the game server.
+
+mod common;
+use byte_dungeon::protocol::{ClientMessage, Envelope, ServerMessage};
+use byte_dungeon::server::Server;
+use byte_dungeon::storage::Store;
+use byte_dungeon::Request;
+use common::tutorial_save;
+
+use std::fs;
+use std::io::Write;
+
+fn send(server: &mut Server, client: u64, message: ClientMessage) {
+    server.handle(client, Envelope::new(message, None));
+}
+
+#[test]
+fn rooms_survive_a_crash_and_are_compacted_on_restore() {
+    let dir = std::env::temp_dir().join(format!("byte-dungeon-storage-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let (mut server, warnings) = Server::with_store(Store::open(&dir).unwrap()).unwrap();
+    assert!(warnings.is_empty());
+
+    let game = tutorial_save();
+    let started = server.handle(1, Envelope::new(ClientMessage::StartHosting { room: "den".into(), user: "dm".into(),
+        name: "DM".into(), set: "tutorial".into(), game: Some(game) }, None));
+    let secret = match &started[0].1.message {
+        ServerMessage::StartSession { secret, .. } => secret.clone(),
+        other => panic!("{:?}", other)
+    };
+    send(&mut server, 2, ClientMessage::FindSession { room: "den".into(), user: "p1".into(), name: "Ana".into(), secret: None });
+    send(&mut server, 2, ClientMessage::RequestAccess { token: '🧝' });
+    send(&mut server, 1, ClientMessage::GrantAccess { index: 0 });
+    send(&mut server, 1, ClientMessage::StartTurn);
+    let moves: Vec<Request> = serde_json::from_str(r#"[
+        {"caster": "🧝", "action_type": 0, "subtype_key": null, "target_cell": [0, 1], "target_tokens": null},
+        {"caster": "🧝", "action_type": 0, "subtype_key": null, "target_cell": [1, 0], "target_tokens": null}
+    ]"#).unwrap();
+    send(&mut server, 2, ClientMessage::AddTurn { requests: moves });
+    send(&mut server, 1, ClientMessage::EndTurn);
+    send(&mut server, 1, ClientMessage::ApproveRequest { index: 0 });
+    let before = server.rooms["den"].game.state_hash();
+    let seq = server.rooms["den"].game.next_seq();
+
+    let log = dir.join("64656e").join("events.jsonl");
+    assert!(fs::read_to_string(&log).unwrap().lines().count() > 0);
+    fs::OpenOptions::new().append(true).open(&log).unwrap().write_all(b"{\"seq\":99,\"ki").unwrap();
+    drop(server);
+
+    let (mut server, warnings) = Server::with_store(Store::open(&dir).unwrap()).unwrap();
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].contains("cut short"));
+    let room = &server.rooms["den"];
+    assert_eq!(room.game.state_hash(), before);
+    assert_eq!(room.game.next_seq(), seq);
+    assert_eq!(room.pending.len(), 1);
+    assert_eq!(room.tokens_of("p1"), vec!['🧝']);
+    assert!(room.game.requests.is_empty());
+    assert_eq!(fs::read_to_string(&log).unwrap(), "");
+    let snapshot: serde_json::Value = serde_json::from_str(&fs::read_to_string(dir.join("64656e").join("snapshot.json")).unwrap()).unwrap();
+    assert_eq!((snapshot["events"].as_array().unwrap().len(), snapshot["base_seq"].as_u64()), (0, Some(seq)));
+
+    send(&mut server, 3, ClientMessage::FindSession { room: "den".into(), user: "dm".into(), name: "DM".into(), secret: None });
+    assert!(server.rooms["den"].pending.len() == 1, "the secret survives the restart");
+    send(&mut server, 1, ClientMessage::FindSession { room: "den".into(), user: "dm".into(), name: "DM".into(), secret: Some(secret) });
+    send(&mut server, 1, ClientMessage::ApproveRequest { index: 0 });
+    assert!(server.rooms["den"].pending.is_empty());
+    assert_ne!(server.rooms["den"].game.state_hash(), before);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,96 @@
+//! Native tests for incremental session sync.
+
+mod common;
+
+use byte_dungeon::history::Command;
+use byte_dungeon::maps::{Cell, MAIN_MAP};
+use byte_dungeon::sync::{diff, DeltaError, TokenDelta};
+use common::tutorial;
+
+#[test]
+fn deltas_patch_clients_in_order() {
+    let mut host = tutorial();
+    let mut client = host.clone();
+    let base = host.clone();
+
+    host.make_request(0, "", '🧝', 1, 0);
+    host.apply(Command::ToggleCell(Cell::new(MAIN_MAP, 5, 5))).unwrap();
+    host.apply(Command::AddMap { id: "cellar".to_string(), rows: 3, cols: 3 }).unwrap();
+    let delta = diff(&base, &host);
+    assert_eq!((delta.from, delta.to), (0, 3));
+    assert_eq!(delta.cells.len(), 3);
+    assert_eq!(delta.grids.len(), 1);
+    assert!(matches!(&delta.tokens[..], [('🧝', TokenDelta::Changed { cell: Some(_), hitpoints: None, effects: None })]));
+    assert!(serde_json::to_string(&delta).unwrap().len() * 10 < serde_json::to_string(&host).unwrap().len());
+
+    client.apply_delta(&delta).unwrap();
+    assert_eq!(client.version, 3);
+    assert_eq!(serde_json::to_value(&client.grid).unwrap(), serde_json::to_value(&host.grid).unwrap());
+    assert_eq!(client.characters, host.characters);
+    assert_eq!(client.board("cellar"), host.board("cellar"));
+    assert_eq!(client.apply_delta(&delta).unwrap_err(), DeltaError::Version { version: 3, from: 0 });
+    assert!(diff(&host, &client).is_empty());
+}
+
+#[test]
+fn empty_deltas_apply_as_no_ops() {
+    let mut host = tutorial();
+    let mut client = host.clone();
+    host.apply(Command::ToggleCell(Cell::new(MAIN_MAP, 5, 5))).unwrap();
+    client.apply_delta(&diff(&tutorial(), &host)).unwrap();
+
+    let nothing = diff(&host, &host);
+    assert_eq!((nothing.from, nothing.to), (1, 1));
+    client.apply_delta(&nothing).unwrap();
+    assert_eq!(client.version, 1);
+
+    let mut forged = diff(&tutorial(), &host);
+    forged.from = 1;
+    assert_eq!(client.apply_delta(&forged).unwrap_err(), DeltaError::Backward { from: 1, to: 1 }, "changes need a later version");
+}
+
+#[test]
+fn requests_and_rolls_logged_by_a_client_keep_it_in_sync() {
+    let mut host = tutorial();
+    let mut client = host.clone();
+    let base = host.clone();
+
+    client.log_request('🧝', Vec::new());
+    client.roll(1, 20);
+    client.sort_requests();
+    assert_eq!(client.version, base.version, "requests, rolls and sorts don't bump the version");
+    host.apply(Command::ToggleCell(Cell::new(MAIN_MAP, 5, 5))).unwrap();
+    client.apply_delta(&diff(&base, &host)).unwrap();
+    assert_eq!(client.grid[5][5], host.grid[5][5]);
+    assert_eq!(client.requests.len(), 1, "the client keeps its own requests");
+}
+
+#[test]
+fn deltas_that_dont_move_forward_are_rejected() {
+    let mut host = tutorial();
+    let base = host.clone();
+    host.apply(Command::ToggleCell(Cell::new(MAIN_MAP, 5, 5))).unwrap();
+    let mut delta = diff(&base, &host);
+    delta.to = delta.from;
+    let mut client = base.clone();
+    assert_eq!(client.apply_delta(&delta).unwrap_err(), DeltaError::Backward { from: 0, to: 0 });
+    assert_eq!(client.grid, base.grid);
+}
+
+#[test]
+fn clients_kept_current_with_deltas_match_the_host_hash() {
+    let mut host = tutorial();
+    let mut client = host.clone();
+    let base = host.clone();
+    host.roll(1, 20);
+    host.apply(Command::ToggleCell(Cell::new(MAIN_MAP, 5, 5))).unwrap();
+    client.apply_delta(&diff(&base, &host)).unwrap();
+    assert_eq!(client.state_hash(), host.state_hash());
+
+    let synced = host.clone();
+    host.roll(1, 20);
+    let rolled = diff(&synced, &host);
+    assert_eq!((rolled.from, rolled.to), (1, 1), "rolls don't bump the version");
+    client.apply_delta(&rolled).unwrap();
+    assert_eq!(client.state_hash(), host.state_hash());
+}
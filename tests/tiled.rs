@@ -0,0 +1,120 @@
+//! Native tests for the Tiled JSON importer.
+
+use byte_dungeon::GameSession;
+use byte_dungeon::maps::{Cell, ObjectKind};
+use byte_dungeon::tiled::{TileMeaning, TiledError, TiledMapping};
+
+const MAP: &str = r#"{
+    "width": 5, "height": 4, "tilewidth": 16, "tileheight": 16, "orientation": "orthogonal", "infinite": false,
+    "layers": [
+        { "type": "tilelayer", "name": "ground", "width": 5, "height": 4,
+          "data": [0, 2, 2, 2, 2,
+                   0, 1, 1, 9, 2,
+                   0, 1, 1, 1, 2,
+                   0, 2, 2, 2, 2] },
+        { "type": "imagelayer", "name": "sky" },
+        { "type": "objectgroup", "name": "things", "objects": [
+            { "id": 1, "name": "A", "type": "spawn", "x": 20, "y": 20, "point": true },
+            { "id": 2, "name": "", "class": "door", "x": 32, "y": 32, "width": 16, "height": 16 },
+            { "id": 3, "name": "zone", "type": "spawn", "x": 0, "y": 0, "polygon": [] }
+        ] }
+    ]
+}"#;
+
+#[test]
+fn converts_layers_and_reports_the_rest() {
+    let mut mapping = TiledMapping::default();
+    mapping.tiles.insert(1, TileMeaning::Floor);
+    mapping.tiles.insert(2, TileMeaning::Wall);
+    mapping.objects.insert("spawn".to_string(), TileMeaning::Spawn(None));
+    mapping.objects.insert("door".to_string(), TileMeaning::Door);
+
+    let mut game = GameSession::default();
+    let report = game.import_tiled("level", MAP, &mapping).unwrap();
+    let grid = game.board("level").unwrap();
+    assert_eq!(grid[0].iter().collect::<String>(), "11111");
+    assert_eq!(grid[1].iter().collect::<String>(), "10001");
+    assert_eq!((report.rows, report.cols, report.objects, report.tokens), (4, 5, 1, 1));
+    assert_eq!(report.unsupported.len(), 3, "{:?}", report.unsupported);
+
+    assert_eq!(game.objects_at(&Cell::new("level", 2, 2))[0].kind, ObjectKind::Door);
+    assert_eq!(game.objects_at(&Cell::new("level", 1, 1))[0].kind, ObjectKind::Spawn(Some('A')));
+    assert!(game.import_tiled("level", r#"{"width": 2, "height": 2, "tilewidth": 1, "tileheight": 1, "infinite": true}"#,
+        &mapping).is_err());
+}
+
+fn mapping() -> TiledMapping {
+    let mut mapping = TiledMapping::default();
+    mapping.tiles.insert(1, TileMeaning::Floor);
+    mapping.tiles.insert(2, TileMeaning::Wall);
+    mapping.objects.insert("spawn".to_string(), TileMeaning::Spawn(None));
+    mapping.objects.insert("trap".to_string(), TileMeaning::Trap);
+    mapping
+}
+
+// 3x3 map, floor in the middle column, OBJECTS in a single object layer
+fn small_map(data: &str, objects: &str) -> String {
+    format!(r#"{{ "width": 3, "height": 3, "tilewidth": 16, "tileheight": 16,
+        "layers": [ {{ "type": "tilelayer", "name": "ground", "data": {} }},
+                    {{ "type": "objectgroup", "name": "things", "objects": {} }} ] }}"#, data, objects)
+}
+
+#[test]
+fn maps_over_the_cell_limit_are_refused() {
+    let huge = r#"{"width": 100000, "height": 100000, "tilewidth": 16, "tileheight": 16, "layers": []}"#;
+    let err = GameSession::default().import_tiled("level", huge, &mapping()).unwrap_err();
+    assert_eq!(err, TiledError::TooLarge { rows: 100000, cols: 100000 });
+}
+
+#[test]
+fn objects_and_spawns_on_walls_are_reported() {
+    let map = small_map("[2, 1, 2, 2, 1, 2, 2, 1, 2]", r#"[
+        { "id": 1, "name": "A", "type": "spawn", "x": 0, "y": 0, "point": true },
+        { "id": 2, "name": "", "type": "trap", "x": 32, "y": 32, "width": 16, "height": 16 },
+        { "id": 3, "name": "B", "type": "spawn", "x": 20, "y": 20, "point": true }
+    ]"#);
+    let report = GameSession::default().import_tiled("level", &map, &mapping()).unwrap();
+    assert_eq!((report.objects, report.tokens), (0, 1));
+    assert_eq!(report.unsupported, vec!["Trap at 2,2 is on a wall, it was dropped".to_string(),
+                                        "spawn of 'A' at 0,0 is on a wall, it was dropped".to_string()]);
+}
+
+#[test]
+fn two_tokens_spawned_on_one_cell_keep_the_first() {
+    let map = small_map("[2, 1, 2, 2, 1, 2, 2, 1, 2]", r#"[
+        { "id": 1, "name": "A", "type": "spawn", "x": 20, "y": 20, "point": true },
+        { "id": 2, "name": "B", "type": "spawn", "x": 24, "y": 24, "point": true }
+    ]"#);
+    let mut game = GameSession::default();
+    let report = game.import_tiled("level", &map, &mapping()).unwrap();
+    assert_eq!(report.tokens, 1);
+    assert_eq!(report.unsupported, vec!["spawn of 'B' at 1,1 is taken by 'A', it was dropped".to_string()]);
+    assert_eq!(game.objects_at(&Cell::new("level", 1, 1)).len(), 1);
+}
+
+#[test]
+fn values_that_are_not_tile_ids_are_reported() {
+    let map = small_map(r#"[1, 1, 1, -3, "x", 1, 1, 1, 99999999999]"#, "[]");
+    let mut game = GameSession::default();
+    let report = game.import_tiled("level", &map, &mapping()).unwrap();
+    assert_eq!(report.unsupported, vec!["layer 'ground' has 3 values that aren't tile ids, they were read as empty".to_string()]);
+    assert_eq!(game.board("level").unwrap()[1].iter().collect::<String>(), "110");
+}
+
+#[test]
+fn objects_at_negative_positions_are_reported() {
+    let map = small_map("[1, 1, 1, 1, 1, 1, 1, 1, 1]", r#"[
+        { "id": 1, "name": "", "type": "trap", "x": -16, "y": 0, "width": 32, "height": 16 },
+        { "id": 2, "name": "", "type": "trap", "x": 0, "y": -40, "width": 16, "height": 16 },
+        { "id": 3, "name": "C", "type": "spawn", "x": -4, "y": 8, "point": true }
+    ]"#);
+    let mut game = GameSession::default();
+    let report = game.import_tiled("level", &map, &mapping()).unwrap();
+    assert_eq!(report.unsupported, vec![
+        "object 1 '' in layer 'things' starts outside the map, it was cut to the cells inside".to_string(),
+        "object 2 '' in layer 'things' is outside the map, it was skipped".to_string(),
+        "object 3 'C' in layer 'things' is outside the map, it was skipped".to_string()
+    ]);
+    assert_eq!((report.objects, report.tokens), (1, 0));
+    assert_eq!(game.objects_at(&Cell::new("level", 0, 0))[0].kind, ObjectKind::Trap);
+}
@@ -0,0 +1,57 @@
+//! Native tests for the terminal client, driven by key presses without a terminal.
+
+mod common;
+use byte_dungeon::maps::{Cell, MAIN_MAP};
+use byte_dungeon::tui::{App, Mode};
+use common::tutorial;
+use crossterm::event::KeyCode;
+
+#[test]
+fn queued_requests_execute_and_render() {
+    let mut app = App::new(tutorial(), None);
+    assert_eq!(app.tokens(), vec!['🐉', '💀', '🧝']);
+    while app.selected != Some('🧝') { app.handle(KeyCode::Tab); }
+
+    app.handle(KeyCode::Char('i'));
+    app.handle(KeyCode::Char('1'));
+    app.handle(KeyCode::Char('m'));
+    assert_eq!(app.mode, Mode::Move);
+    assert!(app.options.contains(&(0, 1)));
+    app.handle(KeyCode::Right);
+    app.handle(KeyCode::Enter);
+    app.handle(KeyCode::Char('m'));
+    app.handle(KeyCode::Left);
+    app.handle(KeyCode::Enter);                 // The token's own cell isn't an option, nothing queued
+    app.handle(KeyCode::Esc);
+    assert_eq!(app.queue.len(), 2);
+
+    app.handle(KeyCode::Char('x'));
+    assert!(app.queue.is_empty());
+    assert_eq!(app.game.cell_at(&Cell::new(MAIN_MAP, 0, 1)), Some('🧝'));
+    assert_eq!(app.cursor, (0, 1));
+    assert!(app.abilities().contains(&"Slash".to_string()));
+
+    app.handle(KeyCode::Char('u'));
+    assert_eq!(app.game.cell_at(&Cell::new(MAIN_MAP, 0, 0)), Some('🧝'));
+
+    let mut screen = Vec::new();
+    app.render(&mut screen).unwrap();
+    let screen = String::from_utf8_lossy(&screen);
+    assert!(screen.contains("Queue (0)") && screen.contains("Undid"));
+    assert!(!app.quit);
+    app.handle(KeyCode::Char('q'));
+    assert!(app.quit);
+}
+
+#[test]
+fn requests_that_no_longer_fit_are_refused_not_run() {
+    let mut app = App::new(tutorial(), None);
+    while app.selected != Some('🧝') { app.handle(KeyCode::Tab); }
+    for key in ['i', '1', 'i', '1'] { app.handle(KeyCode::Char(key)); }
+    assert_eq!(app.queue.len(), 2);
+
+    app.handle(KeyCode::Char('x'));
+    assert!(app.queue.is_empty());
+    assert_eq!(app.log.last().unwrap(), "Refused: 🧝 uses 0 ('🧝' has no item 0)");
+    assert!(app.abilities().contains(&"Slash".to_string()), "the first use still equipped the sword");
+}
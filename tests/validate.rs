@@ -0,0 +1,55 @@
+//! Native tests for the game set linter.
+
+mod common;
+
+use byte_dungeon::GameSession;
+use byte_dungeon::validate::{validate_game, IssueKind, Severity};
+use common::{load, tutorial, tutorial_save};
+use serde_json::json;
+
+#[test]
+fn reports_broken_content_with_paths() {
+    let game = tutorial();
+    assert_eq!(validate_game(&game), vec![]);
+
+    let mut value = serde_json::to_value(&game).unwrap();
+    value["abilities"]["Slash"]["target_effects"] = serde_json::json!(["Poison"]);
+    value["abilities"]["Slash"]["range"] = serde_json::json!(-2);
+    value["characters"]["💀"]["row"] = serde_json::json!(7);
+    value["characters"]["🐉"]["sheet"]["max_hp"] = serde_json::json!(0);
+    value["characters"]["🐉"]["sheet"]["name"] = serde_json::json!("Warrior");
+    value["grid"][3] = serde_json::json!(["0", "0"]);
+    let broken: GameSession = serde_json::from_value(value).unwrap();
+
+    let report = validate_game(&broken);
+    let paths: Vec<&str> = report.iter().map(|issue| issue.path.as_str()).collect();
+    assert_eq!(paths, vec![
+        "abilities.Slash.effects",
+        "abilities.Slash.range",
+        "characters.🐉.sheet.max_hp",
+        "characters.💀",
+        "grid[3]",
+        "grid[6][2]",
+        "characters.🐉.sheet.name",
+        "characters.🧝.sheet.name"
+    ]);
+    assert_eq!(report.iter().filter(|issue| issue.severity == Severity::Error).count(), 6);
+    assert_eq!(report[3].kind, IssueKind::TokenMismatch { found: Some('0') });
+    assert_eq!(report[7].kind, IssueKind::DuplicateName("Warrior".to_string()));
+}
+
+#[test]
+fn effects_on_stats_no_character_has_are_errors() {
+    let mut value = tutorial_save();
+    value["effects"]["Slash damage"]["target_stat"] = json!("Strength");
+    let mut game = load(value.clone());
+    assert_eq!(validate_game(&game), vec![]);
+    game.use_ability('🐉', "Slash", Some(vec!['🧝']));
+    assert_eq!(game.to_save()["characters"]["🧝"]["sheet"]["stats"]["Strength"], -7, "stats are matched in any case");
+
+    value["effects"]["Slash damage"]["target_stat"] = json!("Luck");
+    let report = validate_game(&load(value));
+    assert_eq!(report.len(), 1);
+    assert_eq!((report[0].path.as_str(), &report[0].kind), ("effects.Slash damage.target_stat", &IssueKind::UnknownStat("Luck".to_string())));
+    assert_eq!(report[0].severity, Severity::Error);
+}
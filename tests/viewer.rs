@@ -0,0 +1,68 @@
+//! Native tests for the redacted views sent to players and spectators.
+
+mod common;
+
+use byte_dungeon::GameSession;
+use byte_dungeon::maps::{Cell, MapObject, ObjectKind, MAIN_MAP};
+use byte_dungeon::migrate::{load_save, LoadError};
+use byte_dungeon::permissions::Role;
+use byte_dungeon::viewer::HealthBand;
+use common::{load, place, seat_party, tutorial_save};
+use serde_json::json;
+
+// Tutorial with the skeleton next to the elf, hurt, and an unplaced ghost sheet
+fn ambush() -> GameSession {
+    let mut save = tutorial_save();
+    place(&mut save, "💀", 1, 1);
+    save["characters"]["💀"]["sheet"]["hitpoints"] = json!(4);
+    save["sheets"]["👻"] = save["characters"]["💀"]["sheet"].clone();
+    let mut game = load(save);
+    seat_party(&mut game);
+    for (row, col, kind) in [(0, 1, ObjectKind::Trap), (0, 2, ObjectKind::Door), (14, 29, ObjectKind::Door)] {
+        game.objects.push(MapObject { cell: Cell::new(MAIN_MAP, row, col), kind });
+    }
+    game.roll(1, 20);
+    game
+}
+
+#[test]
+fn dms_see_the_whole_game() {
+    let game = ambush();
+    assert_eq!(game.export_for_viewer(Role::CoDm), game.to_save());
+    assert_eq!(game.export_for_viewer(Role::Host), game.to_save());
+}
+
+#[test]
+fn spectators_only_see_what_the_party_sees() {
+    let view = ambush().export_for_viewer(Role::Spectator);
+    assert_eq!(view["projection"]["role"], "spectator");
+    assert_eq!(view["sheets"], json!({}));
+    assert_eq!(view["events"], json!([]));
+    assert_eq!(view["grid"][0][1], "0");
+    assert_eq!(view["grid"][3][0], "1");
+    assert_eq!(view["grid"][4][0], "?");
+    assert_eq!(view["grid"][14][29], "?");
+    assert!(!view["characters"].as_object().unwrap().contains_key("🐉"));
+    assert_eq!(view["objects"], json!([{ "cell": { "map": MAIN_MAP, "row": 0, "column": 2 }, "kind": "Door" }]));
+}
+
+#[test]
+fn monsters_show_a_health_band_instead_of_hitpoints() {
+    let view = ambush().export_for_viewer(Role::Spectator);
+    let characters = view["characters"].as_object().unwrap();
+    assert_eq!(characters["🧝"]["sheet"]["hitpoints"], 20);
+    assert!(characters["💀"]["sheet"].get("hitpoints").is_none());
+    assert_eq!(characters["💀"]["sheet"]["health"], "bloodied");
+
+    assert_eq!(HealthBand::of(10, 10), HealthBand::Healthy);
+    assert_eq!(HealthBand::of(6, 10), HealthBand::Wounded);
+    assert_eq!(HealthBand::of(2, 10), HealthBand::Critical);
+    assert_eq!(HealthBand::of(0, 10), HealthBand::Down);
+    assert_eq!(HealthBand::of(5, 0), HealthBand::Healthy, "a sheet without max hitpoints is never hurt");
+}
+
+#[test]
+fn views_cant_be_loaded_as_saves() {
+    let view = ambush().export_for_viewer(Role::Spectator);
+    assert_eq!(load_save(view).unwrap_err(), LoadError::Projection);
+}
@@ -1151,10 +1151,11 @@ function getEquip() {
 ////////////////////////////////////////////////    IN-SESSION FUNCTIONS    ////////////////////////////////////////////////////
 
 /******************************************************************************
- * startTurn - "Start turn" button's onclick, starts turn for connected users
+ * startTurn - "Start turn" button's onclick, starts turn for connected users,
+ *             players joining later get the copy without hidden tokens
  *****************************************************************************/
 function startTurn() {
-    socket.emit("startTurn", current_session, JSON.stringify(wasm.export_game()));
+    socket.emit("startTurn", current_session, JSON.stringify(wasm.export_game()), JSON.stringify(wasm.export_player_copy()));
     document.getElementById("roll-button").disabled = false;
     document.getElementById("roll-button-label").innerHTML = "End turn";
     document.getElementById("roll-button").onclick = endTurn;
@@ -1431,7 +1432,11 @@ socket.on("joinGame", (room, key, assignments, game) => {
     else {
         if (assignments === null) { set_assignments = new Map(); }
         else { for (let i = 0; i <assignments.length; i++) { set_assignments.set(assignments[i], 3.0); } }
-        wasm.load_game(JSON.parse(game));
+        try { wasm.load_game(JSON.parse(game)); }
+        catch (err) {
+            logMessage(`Couldn't load the game of ${room}: ${err}`);
+            return;
+        }
         game_backup = wasm.export_game();
         loadGameView();
     }
@@ -1453,7 +1458,11 @@ socket.on("hostRejoin", (room, key, game) => {
         });
     }
     else {
-        wasm.load_game(JSON.parse(game));
+        try { wasm.load_game(JSON.parse(game)); }
+        catch (err) {
+            logMessage(`Couldn't load the game of ${room}: ${err}`);
+            return;
+        }
         game_backup = wasm.export_game();
         socket.emit("confirmSession", room);
     }
@@ -1477,7 +1486,11 @@ socket.on("loadRequests", (requests) => {
  * Socket-executeRequest - Execute the current request for this user
  *****************************************************************************/
 socket.on("executeRequest", (req) => {
-    wasm.execute_request(req);
+    try { wasm.execute_request(req); }
+    catch (err) {
+        logMessage(`Refused the request of ${req.caster}: ${err}`);
+        return;
+    }
     clearTempTokens();
     let dim = wasm.get_dimensions();
     drawClickableGrid(dim[1], dim[0], wasm.board_to_string());
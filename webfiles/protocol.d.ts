@@ -0,0 +1,72 @@
+// Generated by `byte-dungeon typescript`, do not edit
+
+export const PROTOCOL_VERSION = 1;
+
+export type Envelope<T> = { v: number, id?: number } & T;
+
+export type ClientMessage = { "type": "startHosting", room: string, user: string, name: string, set: string, game?: JsonValue, } | { "type": "findSession", room: string, user: string, name: string, secret?: string, } | { "type": "requestAccess", token: string, } | { "type": "getAccessRequests" } | { "type": "grantAccess", index: number, } | { "type": "removeAccess", index: number, } | { "type": "startTurn" } | { "type": "addTurn", requests: Array<Request>, } | { "type": "endTurn" } | { "type": "approveRequest", index: number, } | { "type": "removeRequest", index: number, } | { "type": "emitRollRequest", index: number, } | { "type": "roll20", index: number, } | { "type": "broadcastLog", message: string, } | { "type": "chat", text: string, } | { "type": "setRole", user: string, role: Role | null, } | { "type": "grantToken", user: string, token: string, } | { "type": "revokeToken", user: string, token: string, } | { "type": "setAllowed", user: string, action: Action, allowed: boolean, } | { "type": "setPolicy", policy: Policy, } | { "type": "setBehaviour", token: string, behaviour: Behaviour | null, } | { "type": "autoPlay" } | { "type": "setVisibility", token: string, visibility: Visibility, } | { "type": "addNote", note: MapNote, } | { "type": "removeNotes", cell: Cell, } | { "type": "detect", token: string, by: string, };
+
+export type ServerMessage = { "type": "startSession", room: string, secret: string, } | { "type": "hostRejoin", room: string, set: string, secret: string, game: JsonValue, } | { "type": "joinGame", room: string, set: string, secret: string, tokens: Array<string>, game: JsonValue, } | { "type": "transactionFailed", reason: string, } | { "type": "socketLog", message: string, } | { "type": "chat", from: string, message: ChatMessage, } | { "type": "loadAccessRequests", requests: Array<AccessRequest>, } | { "type": "addTokenAccess", tokens: Array<string>, } | { "type": "grantAllAccess" } | { "type": "loadRequests", requests: Array<Pending>, } | { "type": "resolved", outcomes: Array<Outcome>, } | { "type": "enableRoll", index: number, request: Request, } | { "type": "ack" } | { "type": "executeRequest", request: Request, } | { "type": "sync", delta: SessionDelta, } | { "type": "view", game: JsonValue, };
+
+export type AccessRequest = { user: string, name: string, token: string, };
+
+export type Pending = { user: string, request: Request, };
+
+export type Request = { caster: string, action_type: number, subtype_key: string | null, target_cell: [number, number] | null, target_tokens: Array<string> | null, user?: string, rolled_at?: number, };
+
+export type SessionDelta = { from: number, to: number, grids?: Array<[string, Array<Array<string>> | null]>, cells?: Array<[Cell, string]>, tokens?: Array<[string, TokenDelta]>, sheets?: Array<[string, Character | null]>, items?: Array<[string, Item | null]>, abilities?: Array<[string, Ability | null]>, effects?: Array<[string, Effect | null]>, links?: Array<MapLink>, objects?: Array<MapObject>, permissions?: Permissions, notes?: Array<MapNote>, behaviours?: { [key in string]: Behaviour }, rng?: Rng, };
+
+export type TokenDelta = "Removed" | { "Placed": Token } | { "Changed": { cell?: Cell, hitpoints?: number, effects?: { [key in string]: Effect }, } };
+
+export type Token = { row: number, column: number, map: string, initiative: number | null, sheet: Character, visibility: Visibility, };
+
+export type Character = { name: string, speed: number, initiative: number, hitpoints: number, max_hp: number, stats: { [key in string]: number }, traits: Array<string>, items: Array<Item>, equipment: { [key in string]: Item }, abilities: Array<string>, effects: { [key in string]: Effect }, };
+
+export type Item = { name: string, uses: number, weight: number, slots: Array<string>, effects: Array<string>, abilities: Array<string>, };
+
+export type Ability = { name: string, range: number, action_points: number, casting_roll: [number, number], stat_modifier: string | null, requirements: Array<Array<string>>, target_effects: Array<string>, caster_effects: Array<string>, };
+
+export type Effect = { name: string, duration: number, target_stat: string, modifier: [number, number], temporary: boolean, };
+
+export type Cell = { map: string, row: number, column: number, };
+
+export type MapLink = { name: string, from: Cell, to: Cell, two_way: boolean, };
+
+export type MapObject = { cell: Cell, kind: ObjectKind, };
+
+export type ObjectKind = "Door" | "Trap" | { "Terrain": string } | { "Spawn": string | null };
+
+export type Permissions = { roles: { [key in string]: Role }, controllers: { [key in string]: Array<string> }, withheld: { [key in string]: Array<Action> }, };
+
+export type Role = "host" | "co-dm" | "player" | "spectator";
+
+export type Action = "move" | "item" | "ability" | "unequip";
+
+export type Visibility = "Visible" | "Hidden" | { "Invisible": { dc: number, } };
+
+export type MapNote = { cell: Cell, text: string, };
+
+export type Policy = "initiative-wins" | "bump" | "cancel";
+
+export type Outcome = { request: Request, conflicts: Array<Conflict>, resolution: Resolution, };
+
+export type Conflict = { "SameDestination": { with: string, } } | "Blocked" | { "OutOfRange": { target: string, } } | { "TargetDead": { target: string, } } | "CasterDown";
+
+export type Resolution = "Kept" | { "Bumped": { cell: [number, number], } } | { "Retargeted": { dropped: Array<string>, } } | "Cancelled";
+
+export type ChatMessage = { kind: ChatKind, text: string, rolls: Array<DiceRoll>, };
+
+export type ChatKind = "Say" | "Roll" | { "Whisper": { to: string, } } | "Emote";
+
+export type DiceRoll = { expression: string, terms: Array<RolledTerm>, total: number, };
+
+export type RolledTerm = { negative: boolean, term: Term, values: Array<number>, };
+
+export type Term = { "Dice": { count: number, sides: number, } } | { "Flat": number } | { "Stat": string };
+
+export type Behaviour = "aggressive" | "kiter" | "defender" | "coward" | "support";
+
+export type Rng = { state: number, };
+
+export type JsonValue = number | string | boolean | Array<JsonValue> | { [key in string]: JsonValue } | null;
+